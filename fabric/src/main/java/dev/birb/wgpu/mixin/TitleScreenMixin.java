@@ -5,13 +5,19 @@ import com.google.gson.JsonObject;
 import dev.birb.wgpu.WgpuMcMod;
 import dev.birb.wgpu.render.Wgpu;
 import dev.birb.wgpu.rust.WgpuNative;
+import net.minecraft.block.Block;
+import net.minecraft.block.BlockState;
+import net.minecraft.block.FluidBlock;
 import net.minecraft.client.MinecraftClient;
 import net.minecraft.client.gui.DrawContext;
 import net.minecraft.client.gui.screen.TitleScreen;
 import net.minecraft.client.model.TexturedModelData;
+import net.minecraft.client.render.RenderLayer;
+import net.minecraft.client.render.RenderLayers;
 import net.minecraft.client.render.entity.model.EntityModelLayer;
 import net.minecraft.client.render.entity.model.EntityModels;
 import net.minecraft.client.texture.TextureManager;
+import net.minecraft.registry.Registries;
 import org.spongepowered.asm.mixin.Mixin;
 import org.spongepowered.asm.mixin.Unique;
 import org.spongepowered.asm.mixin.injection.At;
@@ -33,6 +39,9 @@ public class TitleScreenMixin {
     @Inject(method = "render", at = @At("HEAD"))
     private void render(DrawContext context, int mouseX, int mouseY, float delta, CallbackInfo ci) {
         if (!updatedTitle && Wgpu.isInitialized()) {
+            for (Block block : Registries.BLOCK) {
+                WgpuNative.registerBlockLayer(Registries.BLOCK.getId(block).toString(), getLayerIndex(block));
+            }
 
             Thread bakeBlocks = new Thread(WgpuNative::cacheBlockStates);
             bakeBlocks.setContextClassLoader(Thread.currentThread().getContextClassLoader());
@@ -61,4 +70,21 @@ public class TitleScreenMixin {
             WgpuMcMod.ENTITIES_UPLOADED = true;
         }
     }
+
+    // The index of the wgpu-mc render layer a block's default state is drawn in: solid, cutout or translucent
+    @Unique
+    private static int getLayerIndex(Block block) {
+        BlockState state = block.getDefaultState();
+        RenderLayer layer = block instanceof FluidBlock
+                ? RenderLayers.getFluidLayer(state.getFluidState())
+                : RenderLayers.getBlockLayer(state);
+
+        if (layer == RenderLayer.getTranslucent() || layer == RenderLayer.getTripwire()) {
+            return 2;
+        } else if (layer == RenderLayer.getCutout() || layer == RenderLayer.getCutoutMipped()) {
+            return 1;
+        }
+
+        return 0;
+    }
 }
@@ -1,8 +1,10 @@
 package dev.birb.wgpu.mixin.render;
 
 import dev.birb.wgpu.rust.WgpuNative;
+import net.minecraft.client.MinecraftClient;
 import net.minecraft.client.render.Camera;
 import net.minecraft.client.render.GameRenderer;
+import net.minecraft.client.render.WorldRenderer;
 import net.minecraft.client.util.math.MatrixStack;
 import net.minecraft.resource.ResourceFactory;
 import net.minecraft.resource.ResourceManager;
@@ -34,6 +36,13 @@ public abstract class GameRendererMixin {
 
     @Inject(at = @At("RETURN"), method = "render")
     public void render(float tickDelta, long startTime, boolean tick, CallbackInfo ci) {
+        //Sections baked with textures from before a resource reload are rebuilt by vanilla, which bakes them again
+        int[] sections = WgpuNative.takeSectionsToRebake();
+        WorldRenderer worldRenderer = MinecraftClient.getInstance().worldRenderer;
+        for (int i = 0; i + 2 < sections.length; i += 3) {
+            worldRenderer.scheduleBlockRender(sections[i], sections[i + 1], sections[i + 2]);
+        }
+
         WgpuNative.render(tickDelta,startTime,tick);
     }
 
@@ -1,6 +1,7 @@
 package dev.birb.wgpu.mixin.render;
 
 import dev.birb.wgpu.palette.RustPalette;
+import dev.birb.wgpu.render.Wgpu;
 import dev.birb.wgpu.render.RebuildTaskAccessor;
 import dev.birb.wgpu.rust.WgpuNative;
 import io.netty.buffer.ByteBuf;
@@ -11,11 +12,13 @@ import net.minecraft.client.render.chunk.ChunkBuilder;
 import net.minecraft.client.render.chunk.ChunkRendererRegion;
 import net.minecraft.client.world.ClientWorld;
 import net.minecraft.network.PacketByteBuf;
+import net.minecraft.registry.entry.RegistryEntry;
 import net.minecraft.util.collection.PackedIntegerArray;
 import net.minecraft.util.collection.PaletteStorage;
 import net.minecraft.util.math.BlockPos;
 import net.minecraft.util.math.ChunkSectionPos;
 import net.minecraft.util.math.Vec3i;
+import net.minecraft.world.biome.Biome;
 import net.minecraft.world.chunk.*;
 import net.minecraft.world.chunk.light.ChunkLightProvider;
 import org.jetbrains.annotations.Nullable;
@@ -66,6 +69,7 @@ public class RebuildTaskMixin implements RebuildTaskAccessor {
 
             byte[][] skyIndices = new byte[27][2048];
             byte[][] blockIndices = new byte[27][2048];
+            int[][] biomeIndices = new int[27][];
             BlockPos origin = chunk.getOrigin();
             Vec3i sectionCoord = new Vec3i(origin.getX()>>4,origin.getY()>>4,origin.getZ()>>4);
             for(int x=0;x<3;x++){
@@ -75,14 +79,24 @@ public class RebuildTaskMixin implements RebuildTaskAccessor {
                     for(int y=0;y<3;y++){
                         int id = x+3*y+9*z;
                         Palette<?> palette;
+                        ChunkSection chunkSection;
                         PalettedContainer<?> section;
                         try {
-                            section = worldChunk.getSection(world.sectionCoordToIndex(sectionCoord.getY()+y-1)).getBlockStateContainer();
+                            chunkSection = worldChunk.getSection(world.sectionCoordToIndex(sectionCoord.getY()+y-1));
+                            section = chunkSection.getBlockStateContainer();
                             palette = section.data.palette;
                         } catch (ArrayIndexOutOfBoundsException e) {
                             continue;
                         }
 
+                        //One biome per 4x4x4 cell, indexed x, then z, then y
+                        ReadableContainer<RegistryEntry<Biome>> biomeContainer = chunkSection.getBiomeContainer();
+                        int[] biomes = new int[64];
+                        for(int i=0;i<64;i++){
+                            biomes[i] = Wgpu.getBiomeId(biomeContainer.get(i & 3, i >> 4, (i >> 2) & 3).value());
+                        }
+                        biomeIndices[id] = biomes;
+
                         long sectionPos = ChunkSectionPos.from(sectionCoord.getX()+x-1,sectionCoord.getY()+y-1,sectionCoord.getZ()+z-1).asLong();
                         if(skyLightProvider != null && blockLightProvider != null) {
                             ChunkNibbleArray skyNibble = skyLightProvider.lightStorage.uncachedStorage.get(sectionPos);
@@ -128,7 +142,8 @@ public class RebuildTaskMixin implements RebuildTaskAccessor {
                     }
                 }
             }
-            WgpuNative.bakeSection(sectionCoord.getX(),sectionCoord.getY(),sectionCoord.getZ(),paletteIndices, storageIndices, blockIndices, skyIndices);
+            int biomeBlendRadius = client.options.getBiomeBlendRadius().getValue();
+            WgpuNative.bakeSection(sectionCoord.getX(),sectionCoord.getY(),sectionCoord.getZ(),paletteIndices, storageIndices, blockIndices, skyIndices, biomeIndices, biomeBlendRadius);
             return CompletableFuture.completedFuture(ChunkBuilder.Result.SUCCESSFUL);
         }
     }
@@ -16,5 +16,6 @@ public class ShaderReloadListener implements SimpleSynchronousResourceReloadList
     public void reload(ResourceManager manager) {
         WgpuResourceProvider.manager = manager;
         WgpuNative.reloadShaders();
+        WgpuNative.reloadResources();
     }
 }
@@ -8,12 +8,15 @@ import lombok.Getter;
 import lombok.Setter;
 import net.minecraft.client.MinecraftClient;
 import net.minecraft.util.crash.CrashReport;
-import net.minecraft.util.math.BlockPos;
+import net.minecraft.world.biome.Biome;
+import net.minecraft.world.biome.BiomeEffects;
 import sun.misc.Unsafe;
 
 import java.lang.reflect.Field;
 import java.util.ArrayList;
 import java.util.HashMap;
+import java.util.IdentityHashMap;
+import java.util.Map;
 
 import static dev.birb.wgpu.WgpuMcMod.LOGGER;
 
@@ -36,6 +39,8 @@ public class Wgpu {
     public static HashMap<Integer, Integer> keyStates = new HashMap<>();
     public static ArrayList<Runnable> injectPartIds = new ArrayList<>();
 
+    private static final Map<Biome, Integer> BIOME_IDS = new IdentityHashMap<>();
+
     @Getter
     @Setter
     private static String wmIdentity;
@@ -165,31 +170,28 @@ public class Wgpu {
         EntityState.matrixIndices.get(entity).put(part, index);
     }
 
-    public static int helperGetBlockColor(int x, int y, int z, int tintIndex) {
-        MinecraftClient client = MinecraftClient.getInstance();
-
-        BlockPos pos;
-        pos = new BlockPos(x, y, z);
-
-        int color = client.getBlockColors().getColor(
-                client.world.getBlockState(pos),
-                client.world,
-                pos,
-                tintIndex
-        );
+    /**
+     * Get the id a biome is known by on the Rust side, registering it first if it hasn't been seen yet
+     */
+    public static synchronized int getBiomeId(Biome biome) {
+        return BIOME_IDS.computeIfAbsent(biome, b -> {
+            int id = BIOME_IDS.size();
+            BiomeEffects effects = b.getEffects();
 
-        int r = color >> 16 & 255;
-        int g = color >> 8 & 255;
-        int b = color & 255;
+            WgpuNative.registerBiome(
+                    id,
+                    b.getTemperature(),
+                    b.weather.downfall(),
+                    effects.getGrassColor().orElse(-1),
+                    effects.getFoliageColor().orElse(-1),
+                    effects.getWaterColor(),
+                    effects.getGrassColorModifier().ordinal()
+            );
 
-        int fixedColor = r | (g << 8) | (b << 16);
-
-        return fixedColor;
-
-//         return 0x59f7ab;
-//        return 0xffffffff;
+            return id;
+        });
     }
-    
+
     public static void helperSetClassLoader(ClassLoader loader) {
         Thread.currentThread().setContextClassLoader(loader);
     }
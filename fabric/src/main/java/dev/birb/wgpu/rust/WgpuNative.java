@@ -78,6 +78,8 @@ public class WgpuNative {
 
     public static native void registerBlockState(Object state, String blockId, String stateKey);
 
+    public static native void registerBiome(int id, float temperature, float downfall, int grassColor, int foliageColor, int waterColor, int grassColorModifier);
+
     public static native void doEventLoop();
 
     public static native String getBackend();
@@ -136,6 +138,8 @@ public class WgpuNative {
 
     public static native void registerBlock(String name);
 
+    public static native void registerBlockLayer(String name, int layer);
+
     public static native void clearPalette(long l);
 
     public static native void destroyPaletteStorage(long paletteStorage);
@@ -144,7 +148,7 @@ public class WgpuNative {
 
     public static native void setCamera(double x, double y, double z, float renderYaw, float renderPitch);
 
-    public static native void bakeSection(int x, int y, int z, long[] paletteIndices, long[] storageIndices, byte[][] blockIndices, byte[][] skyIndices);
+    public static native void bakeSection(int x, int y, int z, long[] paletteIndices, long[] storageIndices, byte[][] blockIndices, byte[][] skyIndices, int[][] biomeIndices, int biomeBlendRadius);
 
     public static native void setMatrix(int type, float[] mat);
 
@@ -182,6 +186,10 @@ public class WgpuNative {
 
     public static native void reloadShaders();
 
+    public static native void reloadResources();
+
+    public static native int[] takeSectionsToRebake();
+
     public static native void setSectionPos(int x,int z);
 
     public static native void render(float tickDelta, long startTime, boolean tick);
@@ -35,7 +35,9 @@ fn vert(
 ) -> VertexResult {
     var vr: VertexResult;
 
-    var tex_coords: vec2<f32> = vec2<f32>(f32(tex_coords_u32 & 0xffffu), f32(tex_coords_u32 >> 16u)) * vec2<f32>(0.00048828125, 0.00048828125);
+    //UVs are in texels of the entity atlas, which isn't a fixed size
+    var texel_size = 1.0 / vec2<f32>(textureDimensions(t_texture));
+    var tex_coords: vec2<f32> = vec2<f32>(f32(tex_coords_u32 & 0xffffu), f32(tex_coords_u32 >> 16u)) * texel_size;
 
     var part_transform_index: u32 = (entity_index * push_constants.parts_per_entity) + part_id;
     var part_transform: mat4x4<f32> = transforms.mats[part_transform_index];
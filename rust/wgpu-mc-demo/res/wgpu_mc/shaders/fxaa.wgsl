@@ -0,0 +1,75 @@
+@group(0) @binding(0) var t_input: texture_2d<f32>;
+@group(0) @binding(1) var t_sampler: sampler;
+
+const REDUCE_MIN: f32 = 1.0 / 128.0;
+const REDUCE_MUL: f32 = 1.0 / 8.0;
+const SPAN_MAX: f32 = 8.0;
+
+struct VertexResult {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>
+};
+
+@vertex
+fn vert(
+    @location(0) pos_in: vec2<f32>
+) -> VertexResult {
+    var vr: VertexResult;
+    vr.pos = vec4<f32>(pos_in, 0.0, 1.0);
+    vr.uv = pos_in * vec2(0.5, -0.5) + 0.5;
+
+    return vr;
+}
+
+fn luma(col: vec3<f32>) -> f32 {
+    return dot(col, vec3(0.299, 0.587, 0.114));
+}
+
+fn sample(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(t_input, t_sampler, uv, 0.0).rgb;
+}
+
+//Timothy Lottes' FXAA, in the form without the quality presets
+@fragment
+fn frag(
+    in: VertexResult
+) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+
+    let luma_nw = luma(sample(in.uv + vec2(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample(in.uv + vec2(1.0, -1.0) * texel));
+    let luma_sw = luma(sample(in.uv + vec2(-1.0, 1.0) * texel));
+    let luma_se = luma(sample(in.uv + vec2(1.0, 1.0) * texel));
+    let col_m = sample(in.uv);
+    let luma_m = luma(col_m);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    //Blur along the edge, which runs perpendicular to the luma gradient
+    var dir = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let dir_scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * dir_scale, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel;
+
+    let col_a = 0.5 * (
+        sample(in.uv + dir * (1.0 / 3.0 - 0.5)) +
+        sample(in.uv + dir * (2.0 / 3.0 - 0.5))
+    );
+    let col_b = col_a * 0.5 + 0.25 * (
+        sample(in.uv + dir * -0.5) +
+        sample(in.uv + dir * 0.5)
+    );
+
+    //The wider blur crossed another edge, so fall back to the narrower one
+    let luma_b = luma(col_b);
+    if(luma_b < luma_min || luma_b > luma_max) {
+        return vec4(col_a, 1.0);
+    }
+
+    return vec4(col_b, 1.0);
+}
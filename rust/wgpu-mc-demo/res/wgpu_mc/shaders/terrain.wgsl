@@ -24,6 +24,17 @@ struct ChunkOffset {
 
 @group(1) @binding(0) var<storage> chunk_data: array<u32>;
 
+struct TerrainDraw {
+    section_pos: vec3<i32>,
+    base_vertex: u32
+}
+
+//One entry per section being drawn, indexed by the instance index
+@group(2) @binding(0) var<storage> terrain_draws: array<TerrainDraw>;
+
+//Frame offsets of animated textures in texels, indexed by the animation index of each vertex. Entry 0 isn't animated
+@group(3) @binding(0) var<storage> animated_uvs: array<UV>;
+
 struct VertexResult {
     @builtin(position) pos: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
@@ -43,16 +54,21 @@ struct VertexResult {
     @interpolate(flat) @location(14) ao3: f32,
     @interpolate(flat) @location(15) ao4: f32,
     @location(16) light_uv: vec2<f32>,
-    @interpolate(flat) @location(17) int: u32
+    @interpolate(flat) @location(17) int: u32,
+    @location(19) tile: vec2<f32>,
+    @interpolate(flat) @location(20) tile_bounds: vec4<f32>
 };
 
-var<push_constant> section_pos: vec3i;
-
 @vertex
 fn vert(
     @builtin(vertex_index) vi: u32,
-    @builtin(instance_index) base_vertex: u32
+    @builtin(instance_index) draw_index: u32
 ) -> VertexResult {
+    //UVs are in texels, the atlas grows as textures are added
+    var texel_size = 1.0 / vec2<f32>(textureDimensions(t_texture));
+
+    var base_vertex = terrain_draws[draw_index].base_vertex;
+    var section_pos = terrain_draws[draw_index].section_pos;
 //    var vert1_i = (vi >> 2) << 4;
 //    var vert1_i = (vi << 2) & 0xfffffffc;
 //    var vert1_i = ((vi >> 2u) << 2u)+base_vertex;
@@ -99,6 +115,27 @@ fn vert(
 
     vr.light_uv = light_uv;
 
+    //Animated textures are offset from their first frame to the frame being shown, which is the same for the whole quad
+    var animation = animated_uvs[(chunk_data[vert1_base + 2u] >> 16u) & 0x3ffu];
+    var frame_offset = animation.uv1 * texel_size;
+
+    //Quads merged by greedy meshing repeat the texture between the UV bounds of the quad
+    var tile_bits = (vert1_v4 | vert2_v4 | vert3_v4 | vert4_v4) >> 16u;
+    vr.tile_bounds = vec4(0.0, 0.0, 0.0, 0.0);
+
+    if(tile_bits != 0u) {
+        var uv_min = vec2(1.0, 1.0);
+        var uv_max = vec2(0.0, 0.0);
+
+        for(var i = 0u; i < 4u; i++) {
+            var corner = texel_size * vec2(f32(chunk_data[vert1_base + (i << 2u) + 1u] >> 16u), f32(chunk_data[vert1_base + (i << 2u) + 2u] & 0xffffu));
+            uv_min = min(uv_min, corner);
+            uv_max = max(uv_max, corner);
+        }
+
+        vr.tile_bounds = vec4(uv_min + frame_offset, uv_max + frame_offset);
+    }
+
     var v1 = chunk_data[id];
     var v2 = chunk_data[id + 1u];
     var v3 = chunk_data[id + 2u];
@@ -110,8 +147,8 @@ fn vert(
 
     var ao: f32 = f32((v4 >> 8u) & 0xff) * 0.33333;
 
-    var u: f32 = f32((v2 >> 16u) & 0xffffu) * 0.00048828125;
-    var v: f32 = f32(v3 & 0xffffu) * 0.00048828125;
+    var u: f32 = f32((v2 >> 16u) & 0xffffu) * texel_size.x;
+    var v: f32 = f32(v3 & 0xffffu) * texel_size.y;
 
     if(((v3 >> 29u) & 1u) == 1u) {
         x = 16.0;
@@ -129,15 +166,16 @@ fn vert(
     var world_pos = pos + vec3<f32>(f32(section_pos.x) * 16.0, f32(section_pos.y) * 16.0, f32(section_pos.z) * 16.0);
 
     vr.pos = mat4_persp * mat4_view * mat4_model * vec4(world_pos, 1.0);
-    vr.tex_coords = vec2<f32>(u, v);
-    vr.tex_coords2 = vec2(0.0, 0.0);
+    vr.tex_coords = vec2<f32>(u, v) + frame_offset;
+    vr.tile = vec2(f32((v4 >> 16u) & 0xffu), f32(v4 >> 24u));
+    vr.tex_coords2 = vec2<f32>(u, v) + animation.uv2 * texel_size;
     vr.world_pos = world_pos;
     vr.ao = ao;
 
     var light_coords = vec2<u32>(v4 & 15u, (v4 >> 4u) & 15u);
     vr.light_coords = 0.066666666666666 * vec2(f32(light_coords.x), f32(light_coords.y));
 
-    vr.blend = 0.0;
+    vr.blend = animation.blend;
 
     return vr;
 }
@@ -146,10 +184,10 @@ fn minecraft_sample_lighting(uv: vec2<f32>) -> vec3<f32> {
     return mix(uv.x * vec3(0.32156, 0.32156, 0.5) * 0.5 + uv.y * 0.5, vec3(1.0, 1.0, 1.0), uv.y);
 }
 
-@fragment
-fn frag(
+//The colour of a fragment, before each render layer's alpha test
+fn shade(
     in: VertexResult
-) -> @location(0) vec4<f32> {
+) -> vec4<f32> {
 //    var ao: f32 = (in.ao * 0.7) + 0.3;
 
     var lc = mix(mix(in.lc3, in.lc4, in.light_uv.x), mix(in.lc2, in.lc1, in.light_uv.x), in.light_uv.y);
@@ -158,12 +196,66 @@ fn frag(
 
     var light = max(lc.x, lc.y);
 
-    let col = vec4(light, light, light, 1.0) * vec4(ao, ao, ao, 1.0) * textureSample(t_texture, t_sampler, in.tex_coords);
+    let tile_size = in.tile_bounds.zw - in.tile_bounds.xy;
+    let tiled = tile_size.x > 0.0;
+    let grad_x = select(dpdx(in.tex_coords), dpdx(in.tile) * tile_size, tiled);
+    let grad_y = select(dpdy(in.tex_coords), dpdy(in.tile) * tile_size, tiled);
+
+    var tex_coords = in.tex_coords;
+    var tex_coords2 = in.tex_coords2;
+    if(tiled) {
+        tex_coords = in.tile_bounds.xy + fract(in.tile) * tile_size;
+        tex_coords2 = tex_coords + (in.tex_coords2 - in.tex_coords);
+    }
+
+    //Interpolated animations blend into their next frame
+    let sampled = mix(
+        textureSampleGrad(t_texture, t_sampler, tex_coords, grad_x, grad_y),
+        textureSampleGrad(t_texture, t_sampler, tex_coords2, grad_x, grad_y),
+        in.blend
+    );
+
+    let col = vec4(light, light, light, 1.0) * vec4(ao, ao, ao, 1.0) * sampled;
 
 //    let light = textureSample(lightmap_texture, lightmap_sampler, vec2(max(in.light_coords.x, in.light_coords.y), 0.0));
 
+    return col;
+}
+
+//The fragment entry points of the solid, cutout and transparent layers, picked by `frag_entry` in graph.yaml
+
+@fragment
+fn frag(
+    in: VertexResult
+) -> @location(0) vec4<f32> {
+    let col = shade(in);
+
     if(col.a == 0.0f){
         discard;
     }
     return col;
 }
+
+@fragment
+fn frag_cutout(
+    in: VertexResult
+) -> @location(0) vec4<f32> {
+    let col = shade(in);
+
+    if(col.a < 0.5f){
+        discard;
+    }
+    return vec4(col.rgb, 1.0);
+}
+
+@fragment
+fn frag_transparent(
+    in: VertexResult
+) -> @location(0) vec4<f32> {
+    let col = shade(in);
+
+    if(col.a == 0.0f){
+        discard;
+    }
+    return vec4(col.rgb * col.a, col.a);
+}
@@ -57,8 +57,11 @@ fn vert(
     var y: f32 = f32((v1 >> 8u) & 0xffu) * 0.0625;
     var z: f32 = f32((v1 >> 16u) & 0xffu) * 0.0625;
 
-    var u: f32 = f32((v2 >> 16u) & 0xffffu) * 0.00048828125;
-    var v: f32 = f32(v3 & 0xffffu) * 0.00048828125;
+    //UVs are in texels, the atlas grows as textures are added
+    var texel_size = 1.0 / vec2<f32>(textureDimensions(terrain_texture));
+
+    var u: f32 = f32((v2 >> 16u) & 0xffffu) * texel_size.x;
+    var v: f32 = f32(v3 & 0xffffu) * texel_size.y;
 
     if(((v3 >> 61u) & 1u) == 1u) {
         x = 16.0;
@@ -0,0 +1,33 @@
+@group(0) @binding(0) var t_scene: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> exposure: f32;
+
+struct VertexResult {
+    @builtin(position) pos: vec4<f32>
+};
+
+@vertex
+fn vert(
+    @location(0) pos_in: vec2<f32>
+) -> VertexResult {
+    var vr: VertexResult;
+    vr.pos = vec4<f32>(pos_in, 0.0, 1.0);
+
+    return vr;
+}
+
+//Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3(0.0), vec3(1.0));
+}
+
+@fragment
+fn frag(
+    in: VertexResult
+) -> @location(0) vec4<f32> {
+    let col = textureLoad(t_scene, vec2<i32>(in.pos.xy), 0);
+
+    //The scene is rendered with colours as they're displayed, so it's tonemapped in linear space and converted back
+    let linear = pow(max(col.rgb, vec3(0.0)), vec3(2.2)) * exposure;
+
+    return vec4(pow(aces(linear), vec3(1.0 / 2.2)), 1.0);
+}
@@ -0,0 +1,30 @@
+@group(0) @binding(0) var t_input: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> strength: f32;
+
+struct VertexResult {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) ndc: vec2<f32>
+};
+
+@vertex
+fn vert(
+    @location(0) pos_in: vec2<f32>
+) -> VertexResult {
+    var vr: VertexResult;
+    vr.pos = vec4<f32>(pos_in, 0.0, 1.0);
+    vr.ndc = pos_in;
+
+    return vr;
+}
+
+@fragment
+fn frag(
+    in: VertexResult
+) -> @location(0) vec4<f32> {
+    let col = textureLoad(t_input, vec2<i32>(in.pos.xy), 0);
+
+    //Darkens towards the corners, which are sqrt(2) from the centre
+    let falloff = smoothstep(0.5, 1.5, length(in.ndc));
+
+    return vec4(col.rgb * (1.0 - strength * falloff), 1.0);
+}
@@ -1,6 +1,7 @@
 use std::f32::consts::PI;
 
-use glam::{vec3, Mat4, Vec3};
+use glam::{ivec2, vec3, Mat4, Vec3};
+use wgpu_mc::render::camera as wm_camera;
 
 const DEG_TO_RAD: f32 = PI / 180.0;
 #[derive(Debug, Copy, Clone)]
@@ -50,4 +51,17 @@ impl Camera {
     pub fn build_perspective_matrix(&self) -> Mat4 {
         Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar)
     }
+
+    /// The renderer's view of this camera, terrain is rendered relative to the section the camera is in
+    pub fn to_wm_camera(self) -> wm_camera::Camera {
+        wm_camera::Camera {
+            section_pos: ivec2(
+                self.position.x.floor() as i32 >> 4,
+                self.position.z.floor() as i32 >> 4,
+            ),
+            model: Mat4::IDENTITY,
+            view: self.build_view_matrix(),
+            projection: self.build_perspective_matrix(),
+        }
+    }
 }
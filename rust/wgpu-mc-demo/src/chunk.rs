@@ -3,7 +3,7 @@ use std::time::Instant;
 
 use glam::IVec3;
 use wgpu_mc::mc::block::{BlockstateKey, ChunkBlockState};
-use wgpu_mc::mc::chunk::{bake_section, BlockStateProvider, LightLevel};
+use wgpu_mc::mc::chunk::{bake_section, BakeOptions, BlockStateProvider, LightLevel};
 use wgpu_mc::mc::Scene;
 use wgpu_mc::render::pipeline::BLOCK_ATLAS;
 use wgpu_mc::WmRenderer;
@@ -70,5 +70,13 @@ pub fn make_chunks(wm: &WmRenderer, pos: IVec3, _scene: &Scene) {
         augment,
     });
     let _time = Instant::now();
-    bake_section(pos, wm, &provider);
+    bake_section(
+        pos,
+        wm,
+        &provider,
+        BakeOptions {
+            greedy_meshing: true,
+            ..Default::default()
+        },
+    );
 }
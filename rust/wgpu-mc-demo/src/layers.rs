@@ -0,0 +1,142 @@
+use wgpu_mc::mc::chunk::RenderLayer;
+
+/// A guess at the render layer vanilla assigns to a block, keyed by its identifier (e.g. `minecraft:oak_leaves`).
+/// Block models don't carry this information and the demo has no game to ask, so it's derived from the name.
+pub fn block_render_layer(block_name: &str) -> RenderLayer {
+    let name = block_name
+        .split_once(':')
+        .map_or(block_name, |(_, name)| name);
+
+    if name.ends_with("stained_glass")
+        || name.ends_with("stained_glass_pane")
+        || matches!(
+            name,
+            "water"
+                | "ice"
+                | "frosted_ice"
+                | "slime_block"
+                | "honey_block"
+                | "tinted_glass"
+                | "nether_portal"
+                | "bubble_column"
+        )
+    {
+        RenderLayer::Transparent
+    } else if name.ends_with("leaves")
+        || name.ends_with("sapling")
+        || name.ends_with("glass")
+        || name.ends_with("glass_pane")
+        || name.ends_with("_door")
+        || name.ends_with("_trapdoor")
+        || name.ends_with("tulip")
+        || name.ends_with("_bars")
+        || name.ends_with("_mushroom")
+        || name.ends_with("_fungus")
+        || name.ends_with("_roots")
+        || name.ends_with("_vines")
+        || name.ends_with("_plant")
+        || name.ends_with("_coral")
+        || name.ends_with("_coral_fan")
+        || name.ends_with("_coral_wall_fan")
+        || name.starts_with("potted_")
+        || matches!(
+            name,
+            "grass_block"
+                | "grass"
+                | "short_grass"
+                | "tall_grass"
+                | "fern"
+                | "large_fern"
+                | "dead_bush"
+                | "dandelion"
+                | "poppy"
+                | "blue_orchid"
+                | "allium"
+                | "azure_bluet"
+                | "oxeye_daisy"
+                | "cornflower"
+                | "lily_of_the_valley"
+                | "wither_rose"
+                | "torchflower"
+                | "sunflower"
+                | "lilac"
+                | "rose_bush"
+                | "peony"
+                | "vine"
+                | "lily_pad"
+                | "sugar_cane"
+                | "wheat"
+                | "carrots"
+                | "potatoes"
+                | "beetroots"
+                | "nether_wart"
+                | "sweet_berry_bush"
+                | "cobweb"
+                | "ladder"
+                | "rail"
+                | "powered_rail"
+                | "detector_rail"
+                | "activator_rail"
+                | "torch"
+                | "wall_torch"
+                | "soul_torch"
+                | "soul_wall_torch"
+                | "redstone_torch"
+                | "redstone_wall_torch"
+                | "redstone_wire"
+                | "repeater"
+                | "comparator"
+                | "tripwire"
+                | "tripwire_hook"
+                | "lever"
+                | "fire"
+                | "soul_fire"
+                | "spawner"
+                | "scaffolding"
+                | "bamboo"
+                | "kelp"
+                | "seagrass"
+                | "tall_seagrass"
+                | "cactus"
+                | "melon_stem"
+                | "pumpkin_stem"
+                | "attached_melon_stem"
+                | "attached_pumpkin_stem"
+                | "cocoa"
+                | "chorus_flower"
+                | "lantern"
+                | "soul_lantern"
+                | "chain"
+                | "campfire"
+                | "soul_campfire"
+                | "beacon"
+                | "conduit"
+                | "hopper"
+                | "brewing_stand"
+                | "bell"
+                | "glow_lichen"
+                | "hanging_roots"
+                | "spore_blossom"
+                | "azalea"
+                | "flowering_azalea"
+                | "big_dripleaf"
+                | "big_dripleaf_stem"
+                | "small_dripleaf"
+                | "pointed_dripstone"
+                | "mangrove_propagule"
+                | "pink_petals"
+                | "pitcher_plant"
+                | "pitcher_crop"
+                | "torchflower_crop"
+                | "frogspawn"
+                | "sculk_vein"
+                | "sculk_shrieker"
+                | "sculk_sensor"
+                | "calibrated_sculk_sensor"
+        )
+    {
+        RenderLayer::Cutout
+    } else {
+        RenderLayer::Solid
+    }
+}
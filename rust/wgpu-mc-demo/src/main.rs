@@ -1,5 +1,5 @@
 use arrayvec::ArrayVec;
-use glam::{ivec2, ivec3, IVec3, Mat4};
+use glam::{ivec3, IVec3, Mat4};
 use parking_lot::lock_api::RwLock;
 use std::collections::HashMap;
 use std::fs;
@@ -16,29 +16,42 @@ use winit::keyboard::{KeyCode, PhysicalKey};
 
 use crate::camera::Camera;
 use crate::chunk::make_chunks;
+use crate::layers::block_render_layer;
 use wgpu_mc::mc::direction::Direction;
-use wgpu_mc::mc::resource::{ResourcePath, ResourceProvider};
+use wgpu_mc::mc::resource::{
+    DirectoryResourceProvider, LayeredResourceProvider, ResourcePath, ZipResourceProvider,
+};
 use wgpu_mc::mc::Scene;
 use wgpu_mc::render::graph::{RenderGraph, ResourceBacking};
 use wgpu_mc::render::shaderpack::ShaderPackConfig;
+use wgpu_mc::render::terrain::INDIRECT_DRAW_FEATURES;
 use wgpu_mc::wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu_mc::wgpu::{BufferBindingType, Extent3d, PresentMode};
-use wgpu_mc::{wgpu, Display, Frustum, WmRenderer};
+use wgpu_mc::{wgpu, Display, DisplayTarget, WmRenderer};
 
 mod camera;
 mod chunk;
+mod layers;
+
+//ResourceProvider is what wm uses to fetch resources. The demo's own assets are at the bottom, and resource packs
+//or a client jar given on the command line are stacked on top, the last one overriding the rest
+fn resource_provider(res: PathBuf) -> LayeredResourceProvider {
+    let mut provider = LayeredResourceProvider::new();
+    provider.push(DirectoryResourceProvider::new(res)).unwrap();
+
+    for pack in std::env::args_os().skip(1).map(PathBuf::from) {
+        let result = if pack.is_dir() {
+            provider.push(DirectoryResourceProvider::new(&pack))
+        } else {
+            ZipResourceProvider::open(&pack).and_then(|zip| provider.push(zip))
+        };
 
-struct FsResourceProvider {
-    pub asset_root: PathBuf,
-}
-
-//ResourceProvider is what wm uses to fetch resources. This is a basic implementation that's just backed by the filesystem
-impl ResourceProvider for FsResourceProvider {
-    fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>> {
-        let real_path = self.asset_root.join(id.0.replace(':', "/"));
-
-        fs::read(real_path).ok()
+        if let Err(error) = result {
+            println!("Failed to load resource pack {pack:?}: {error}");
+        }
     }
+
+    provider
 }
 
 struct Application {
@@ -48,6 +61,7 @@ struct Application {
     render_graph: Option<RenderGraph>,
     camera: Option<Camera>,
     last_frame: Instant,
+    start: Instant,
 }
 impl Application {
     pub fn new() -> Self {
@@ -58,6 +72,7 @@ impl Application {
             render_graph: None,
             camera: None,
             last_frame: Instant::now(),
+            start: Instant::now(),
         }
     }
 }
@@ -99,7 +114,7 @@ impl ApplicationHandler for Application {
                 required_features: wgpu::Features::default()
                     | wgpu::Features::DEPTH_CLIP_CONTROL
                     | wgpu::Features::PUSH_CONSTANTS
-                    | wgpu::Features::MULTI_DRAW_INDIRECT,
+                    | (adapter.features() & INDIRECT_DRAW_FEATURES),
                 required_limits,
                 memory_hints: wgpu::MemoryHints::Performance,
             },
@@ -131,23 +146,18 @@ impl ApplicationHandler for Application {
         surface.configure(&device, &surface_config);
 
         let display = Display {
-            surface,
             adapter,
             device,
             queue,
             size: RwLock::new(window.inner_size()),
-            window,
+            target: DisplayTarget::Window { window, surface },
             instance,
             config: RwLock::new(surface_config),
         };
 
-        let rsp = Arc::new(FsResourceProvider {
-            asset_root: crate_root::root()
-                .unwrap()
-                .join("wgpu-mc-demo")
-                .join("res")
-                .join("assets"),
-        });
+        let rsp = Arc::new(resource_provider(
+            crate_root::root().unwrap().join("wgpu-mc-demo").join("res"),
+        ));
 
         let _mc_root = crate_root::root()
             .unwrap()
@@ -185,14 +195,22 @@ impl ApplicationHandler for Application {
 
         wm.init();
 
-        wm.mc.bake_blocks(&wm, blocks.iter().map(|(a, b)| (a, b)));
+        for (block_name, error) in wm.mc.bake_blocks(
+            &wm,
+            blocks
+                .iter()
+                .map(|(block_name, path)| (block_name, path, block_render_layer(block_name))),
+        ) {
+            println!("Failed to bake {block_name}: {error}");
+        }
 
-        let pack = serde_yaml::from_str::<ShaderPackConfig>(
+        let pack = ShaderPackConfig::from_yaml(
             &wm.mc
                 .resource_provider
                 .get_string(&ResourcePath("wgpu_mc:graph.yaml".into()))
                 .unwrap(),
-        );
+        )
+        .unwrap_or_else(|diagnostic| panic!("graph.yaml:{diagnostic}"));
 
         let mat4_model_buffer = Arc::new(create_buffer(&wm, &[0; 64]));
         let mat4_view_buffer = Arc::new(create_buffer(&wm, &[0; 64]));
@@ -215,19 +233,20 @@ impl ApplicationHandler for Application {
         .into_iter()
         .collect::<HashMap<String, ResourceBacking>>();
 
-        self.render_graph = Some(RenderGraph::new(
-            &wm,
-            pack.unwrap(),
-            resource_backings,
-            None,
-            None,
-        ));
+        let render_graph = RenderGraph::new(&wm, pack, resource_backings, None, None)
+            .unwrap_or_else(|diagnostics| {
+                for diagnostic in diagnostics {
+                    println!("graph.yaml:{diagnostic}");
+                }
+                panic!("Invalid render graph");
+            });
+        self.render_graph = Some(render_graph);
 
         self.scene = Some(Scene::new(
             &wm,
             Extent3d {
-                width: wm.display.window.inner_size().width,
-                height: wm.display.window.inner_size().height,
+                width: wm.display.window().unwrap().inner_size().width,
+                height: wm.display.window().unwrap().inner_size().height,
                 depth_or_array_layers: 1,
             },
         ));
@@ -243,8 +262,8 @@ impl ApplicationHandler for Application {
         }
 
         self.camera = Some(Camera::new(
-            wm.display.window.inner_size().width as f32
-                / wm.display.window.inner_size().height as f32,
+            wm.display.window().unwrap().inner_size().width as f32
+                / wm.display.window().unwrap().inner_size().height as f32,
         ));
 
         self.wm = Some(wm);
@@ -268,7 +287,7 @@ impl ApplicationHandler for Application {
 
     fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
         let wm = self.wm.as_ref().unwrap();
-        wm.display.window.request_redraw()
+        wm.display.window().unwrap().request_redraw()
     }
 
     fn window_event(
@@ -278,7 +297,7 @@ impl ApplicationHandler for Application {
         event: WindowEvent,
     ) {
         let wm = self.wm.as_ref().unwrap();
-        if window_id == wm.display.window.id() {
+        if window_id == wm.display.window().unwrap().id() {
             match event {
                 WindowEvent::CloseRequested => event_loop.exit(),
                 WindowEvent::KeyboardInput { event, .. } => match event {
@@ -324,6 +343,55 @@ impl ApplicationHandler for Application {
                     } => {
                         self.forward = 0.0;
                     }
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F2),
+                        ..
+                    } => {
+                        //Take a screenshot at twice the window resolution
+                        let render_graph = self.render_graph.as_ref().unwrap();
+                        let scene = self.scene.as_ref().unwrap();
+
+                        let screenshot =
+                            wm.capture_frame_tiled(scene, 2, |camera, encoder, view| {
+                                if let ResourceBacking::Buffer(buffer, _) =
+                                    &render_graph.resources["@mat4_perspective"]
+                                {
+                                    wm.display.queue.write_buffer(
+                                        buffer,
+                                        0,
+                                        bytemuck::cast_slice(&camera.projection.to_cols_array()),
+                                    );
+                                }
+
+                                //Post-processing would run on each tile separately
+                                render_graph.render_scene(
+                                    wm,
+                                    encoder,
+                                    scene,
+                                    view,
+                                    [0; 3],
+                                    &mut HashMap::new(),
+                                );
+                            });
+
+                        match screenshot.map(|image| image.save("screenshot.png")) {
+                            Ok(Ok(())) => println!("Saved screenshot.png"),
+                            Ok(Err(error)) => println!("Couldn't save screenshot: {error}"),
+                            Err(error) => println!("Couldn't take screenshot: {error}"),
+                        }
+                    }
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(KeyCode::F5),
+                        ..
+                    } => {
+                        //Reload textures and models, sections are baked again as they're redrawn
+                        for (block_name, error) in wm.reload_resources(self.scene.as_ref().unwrap())
+                        {
+                            println!("Failed to bake {block_name}: {error}");
+                        }
+                    }
                     _ => {}
                 },
                 WindowEvent::Resized(physical_size) => {
@@ -350,10 +418,7 @@ impl ApplicationHandler for Application {
                             bytemuck::cast_slice(&Mat4::IDENTITY.to_cols_array()),
                         );
                     }
-                    *self.scene.as_mut().unwrap().camera_section_pos.write() = ivec2(
-                        camera.position.x.floor() as i32 >> 4,
-                        camera.position.z.floor() as i32 >> 4,
-                    );
+                    *self.scene.as_mut().unwrap().camera.write() = camera.to_wm_camera();
 
                     if let ResourceBacking::Buffer(buffer, _) =
                         &self.render_graph.as_ref().unwrap().resources["@mat4_perspective"]
@@ -375,22 +440,24 @@ impl ApplicationHandler for Application {
 
                     let mut config_guard = wm.display.config.write();
 
-                    let surface_texture =
-                        wm.display
-                            .surface
-                            .get_current_texture()
-                            .unwrap_or_else(|_| {
-                                //The surface is outdated, so we force an update. This can't be done on the window resize event for synchronization reasons.
-                                let size = wm.display.size.read();
-
-                                config_guard.width = size.width;
-                                config_guard.height = size.height;
-
-                                wm.display
-                                    .surface
-                                    .configure(&wm.display.device, &config_guard);
-                                wm.display.surface.get_current_texture().unwrap()
-                            });
+                    let surface_texture = wm
+                        .display
+                        .surface()
+                        .unwrap()
+                        .get_current_texture()
+                        .unwrap_or_else(|_| {
+                            //The surface is outdated, so we force an update. This can't be done on the window resize event for synchronization reasons.
+                            let size = wm.display.size.read();
+
+                            config_guard.width = size.width;
+                            config_guard.height = size.height;
+
+                            wm.display
+                                .surface()
+                                .unwrap()
+                                .configure(&wm.display.device, &config_guard);
+                            wm.display.surface().unwrap().get_current_texture().unwrap()
+                        });
 
                     let view = surface_texture
                         .texture
@@ -405,7 +472,18 @@ impl ApplicationHandler for Application {
                             array_layer_count: None,
                         });
 
-                    wm.submit_chunk_updates(self.scene.as_ref().unwrap());
+                    let scene = self.scene.as_ref().unwrap();
+                    let rebake_queue = scene.section_storage.write().take_rebake_queue();
+                    for pos in rebake_queue {
+                        make_chunks(wm, pos, scene);
+                    }
+
+                    wm.submit_chunk_updates(scene);
+                    wm.update_animated_textures((self.start.elapsed().as_millis() / 50) as u32);
+                    self.render_graph
+                        .as_mut()
+                        .unwrap()
+                        .update_texture_resources(wm);
 
                     let mut command_encoder = wm
                         .display
@@ -414,9 +492,6 @@ impl ApplicationHandler for Application {
 
                     let mut geometry = HashMap::new();
 
-                    let mvp = (camera.build_perspective_matrix() * camera.build_view_matrix())
-                        .to_cols_array_2d();
-
                     self.render_graph.as_ref().unwrap().render(
                         wm,
                         &mut command_encoder,
@@ -424,7 +499,6 @@ impl ApplicationHandler for Application {
                         &view,
                         [0; 3],
                         &mut geometry,
-                        &Frustum::from_modelview_projection(mvp),
                     );
 
                     wm.display.queue.submit([command_encoder.finish()]);
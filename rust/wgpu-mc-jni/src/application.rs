@@ -11,7 +11,7 @@ use wgpu_mc::{
         util::{BufferInitDescriptor, DeviceExt},
         BufferAddress, BufferBindingType, PresentMode,
     },
-    Display, WmRenderer,
+    Display, DisplayTarget, WmRenderer,
 };
 use winit::{
     application::ApplicationHandler,
@@ -31,13 +31,14 @@ use std::collections::HashMap;
 use wgpu_mc::render::{
     graph::{RenderGraph, ResourceBacking},
     shaderpack::ShaderPackConfig,
+    terrain::INDIRECT_DRAW_FEATURES,
 };
 
 pub static SHOULD_STOP: OnceCell<()> = OnceCell::new();
 
 pub fn load_shaders(wm: &WmRenderer) {
-    let shader_pack: ShaderPackConfig =
-        serde_yaml::from_str(include_str!("../graph.yaml")).unwrap();
+    let shader_pack = ShaderPackConfig::from_yaml(include_str!("../graph.yaml"))
+        .unwrap_or_else(|diagnostic| panic!("graph.yaml:{diagnostic}"));
 
     let mut render_resources = HashMap::new();
 
@@ -80,14 +81,27 @@ pub fn load_shaders(wm: &WmRenderer) {
         }],
     );
 
-    let render_graph = RenderGraph::new(
+    let render_graph = match RenderGraph::new(
         wm,
         shader_pack,
         render_resources,
         Some(custom_bind_groups),
         Some(custom_geometry),
-    );
-    
+    ) {
+        Ok(render_graph) => render_graph,
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                log::error!("graph.yaml:{diagnostic}");
+            }
+
+            //Keep rendering with the graph from before the reload
+            if RENDER_GRAPH.get().is_some() {
+                return;
+            }
+            panic!("Invalid render graph");
+        }
+    };
+
     match RENDER_GRAPH.get() {
         None => {
             RENDER_GRAPH.set(Mutex::new(render_graph)).unwrap();
@@ -187,7 +201,7 @@ impl ApplicationHandler for Application {
                     | wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY
                     | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
                     | wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY
-                    | wgpu::Features::MULTI_DRAW_INDIRECT,
+                    | (adapter.features() & INDIRECT_DRAW_FEATURES),
                 required_limits,
                 memory_hints: wgpu::MemoryHints::Performance,
             },
@@ -198,9 +212,8 @@ impl ApplicationHandler for Application {
         surface.configure(&device, &surface_config);
 
         let display = Display {
-            window,
+            target: DisplayTarget::Window { window, surface },
             size: RwLock::new(size),
-            surface,
             device,
             queue,
             config: RwLock::new(surface_config),
@@ -278,7 +291,7 @@ impl ApplicationHandler for Application {
         event: winit::event::WindowEvent,
     ) {
         let wm = RENDERER.get().unwrap();
-        if window_id == wm.display.window.id() {
+        if window_id == wm.display.window().unwrap().id() {
             match event {
                 WindowEvent::CloseRequested => event_loop.exit(),
                 WindowEvent::Resized(physical_size) => {
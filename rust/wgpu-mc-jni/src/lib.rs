@@ -10,7 +10,9 @@ use jni::objects::{
     AutoElements, GlobalRef, JByteArray, JClass, JFloatArray, JIntArray, JLongArray, JObject,
     JObjectArray, JPrimitiveArray, JString, JValue, JValueOwned, ReleaseMode, WeakRef,
 };
-use jni::sys::{jboolean, jbyte, jfloat, jint, jlong, jsize, jstring, JNI_FALSE, JNI_TRUE};
+use jni::sys::{
+    jboolean, jbyte, jfloat, jint, jintArray, jlong, jsize, jstring, JNI_FALSE, JNI_TRUE,
+};
 use jni::{JNIEnv, JavaVM};
 use jni_fn::jni_fn;
 use once_cell::sync::{Lazy, OnceCell};
@@ -19,7 +21,6 @@ use parking_lot::{Mutex, RwLock};
 use pia::PIA_STORAGE;
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use renderer::MATRICES;
-use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::io::{stdout, Cursor, Write};
@@ -33,8 +34,11 @@ use winit::dpi::PhysicalPosition;
 use winit::event::{ElementState, MouseButton};
 use winit::window::CursorGrabMode;
 
+use wgpu_mc::mc::biome::{Biome, BiomeColors, BlockTint, GrassColorModifier};
 use wgpu_mc::mc::block::{BlockstateKey, ChunkBlockState};
-use wgpu_mc::mc::chunk::{bake_section, BlockStateProvider, LightLevel};
+use wgpu_mc::mc::chunk::{
+    bake_section, BakeOptions, BlockStateProvider, LightLevel, LightingMode, RenderLayer,
+};
 use wgpu_mc::mc::resource::{ResourcePath, ResourceProvider};
 use wgpu_mc::mc::Scene;
 use wgpu_mc::minecraft_assets::schemas::blockstates::multipart::StateValue;
@@ -42,7 +46,7 @@ use wgpu_mc::render::pipeline::BLOCK_ATLAS;
 use wgpu_mc::texture::{BindableTexture, TextureAndView};
 use wgpu_mc::wgpu::ImageDataLayout;
 use wgpu_mc::wgpu::{self, TextureFormat};
-use wgpu_mc::{Frustum, WmRenderer};
+use wgpu_mc::WmRenderer;
 
 use crate::gl::{GLCommand, GlTexture, GL_ALLOC, GL_COMMANDS};
 use crate::lighting::DeserializedLightData;
@@ -130,16 +134,23 @@ static SCENE: Lazy<Scene> = Lazy::new(|| {
     Scene::new(
         wm,
         wgpu::Extent3d {
-            width: wm.display.window.inner_size().width,
-            height: wm.display.window.inner_size().height,
+            width: wm.display.window().unwrap().inner_size().width,
+            height: wm.display.window().unwrap().inner_size().height,
             depth_or_array_layers: 1,
         },
     )
 });
 
 static BLOCKS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Render layers of blocks as vanilla's `RenderLayers` assigns them, see [registerBlockLayer]
+static BLOCK_LAYERS: Lazy<Mutex<HashMap<String, RenderLayer>>> = Lazy::new(Default::default);
 static BLOCK_STATES: Mutex<Vec<(String, String, GlobalRef)>> = Mutex::new(Vec::new());
 pub static SETTINGS: RwLock<Option<Settings>> = RwLock::new(None);
+/// Indexed by the ids handed out to biomes on the Java side, see `registerBiome`
+static BIOMES: RwLock<Vec<Biome>> = RwLock::new(Vec::new());
+/// Indexed by [BlockstateKey::block]
+static BLOCK_TINTS: Lazy<ArcSwap<Vec<BlockTint>>> =
+    Lazy::new(|| ArcSwap::new(Arc::new(Vec::new())));
 
 pub static CLASSLOADER: OnceCell<WeakRef> = OnceCell::new();
 
@@ -175,12 +186,38 @@ pub fn call_static_from_class_loader<'env>(
 pub struct SectionHolder {
     pub block_data: Option<(JavaPalette, PackedIntegerArray)>,
     pub light_data: Option<DeserializedLightData>,
+    /// One biome per 4x4x4 cell, indexed like vanilla's biome containers
+    pub biomes: Option<Box<[Biome; 64]>>,
 }
 
 #[derive(Debug)]
 pub struct MinecraftBlockstateProvider {
     pub sections: [Option<SectionHolder>; 27],
     pub air: BlockstateKey,
+    /// World position of the block at (0, 0, 0) of the center section
+    pub origin: IVec3,
+    pub biome_colors: Arc<BiomeColors>,
+    pub block_tints: Arc<Vec<BlockTint>>,
+    pub biome_blend_radius: i32,
+}
+
+impl MinecraftBlockstateProvider {
+    fn get_biome(&self, pos: IVec3) -> Option<&Biome> {
+        let section_pos: IVec3 = (pos >> 4) + 1;
+
+        if section_pos.cmplt(IVec3::ZERO).any() || section_pos.cmpgt(IVec3::splat(2)).any() {
+            return None;
+        }
+
+        let biomes = self.sections[section_pos.dot(ivec3(1, 3, 9)) as usize]
+            .as_ref()?
+            .biomes
+            .as_ref()?;
+
+        let cell: IVec3 = (pos & 15) >> 2;
+
+        biomes.get((cell.x + cell.z * 4 + cell.y * 16) as usize)
+    }
 }
 impl BlockStateProvider for MinecraftBlockstateProvider {
     fn get_state(&self, pos: IVec3) -> ChunkBlockState {
@@ -247,8 +284,32 @@ impl BlockStateProvider for MinecraftBlockstateProvider {
         self.sections[(rel_pos + 1).dot(ivec3(1, 3, 9)) as usize].is_none()
     }
 
-    fn get_block_color(&self, _pos: IVec3, _tint_index: i32) -> u32 {
-        0xffffffff
+    fn get_block_color(&self, pos: IVec3, tint_index: i32) -> u32 {
+        //Faces without a tint index keep their texture's colour, even on tinted blocks
+        if tint_index < 0 {
+            return 0xffffffff;
+        }
+
+        let tint = match self.get_state(pos - self.origin) {
+            ChunkBlockState::Air => BlockTint::None,
+            ChunkBlockState::State(key) => self
+                .block_tints
+                .get(key.block as usize)
+                .copied()
+                .unwrap_or(BlockTint::None),
+        };
+
+        match self
+            .biome_colors
+            .blend(tint, pos, self.biome_blend_radius, |pos| {
+                self.get_biome(pos - self.origin)
+            }) {
+            //Minecraft colours are 0xRRGGBB, vertex colours are stored the other way around
+            Some(color) => {
+                ((color >> 16) & 0xff) | (color & 0xff00) | ((color & 0xff) << 16) | 0xff000000
+            }
+            None => 0xffffffff,
+        }
     }
 }
 
@@ -399,52 +460,38 @@ pub fn registerBlockState(
 
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
 pub fn reloadStorage(_env: JNIEnv, _class: JClass, clampedViewDistance: jint) {
-    let mut section_storage = SCENE.section_storage.write();
-    section_storage.clear();
-    section_storage.set_width(clampedViewDistance);
+    SCENE.set_render_distance(RENDERER.get().unwrap(), clampedViewDistance as u32);
 }
 
+/// Rebuild atlases and block models from the current resource packs, see [WmRenderer::reload_resources]
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
-pub fn setSectionPos(_env: JNIEnv, _class: JClass, x: jint, z: jint) {
-    *SCENE.camera_section_pos.write() = ivec2(x, z);
-}
-
-struct MinecraftBlockStateProviderWrapper<'a> {
-    internal: MinecraftBlockstateProvider,
-    env: RefCell<JNIEnv<'a>>,
-}
+pub fn reloadResources(_env: JNIEnv, _class: JClass) {
+    let wm = RENDERER.get().unwrap();
 
-impl<'a> BlockStateProvider for MinecraftBlockStateProviderWrapper<'a> {
-    fn get_state(&self, pos: IVec3) -> ChunkBlockState {
-        self.internal.get_state(pos)
-    }
-
-    fn get_light_level(&self, pos: IVec3) -> LightLevel {
-        self.internal.get_light_level(pos)
+    for (block_name, error) in wm.reload_resources(&SCENE) {
+        log::warn!("Failed to bake {block_name}: {error}");
     }
+}
 
-    fn is_section_empty(&self, rel_pos: IVec3) -> bool {
-        self.internal.is_section_empty(rel_pos)
-    }
+/// The sections which have to be baked again, as x, y, z section coordinates one after the other
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn takeSectionsToRebake(env: JNIEnv, _class: JClass) -> jintArray {
+    let sections = SCENE
+        .section_storage
+        .write()
+        .take_rebake_queue()
+        .into_iter()
+        .flat_map(|pos| pos.to_array())
+        .collect::<Vec<jint>>();
+
+    let array = env.new_int_array(sections.len() as jsize).unwrap();
+    env.set_int_array_region(&array, 0, &sections).unwrap();
+    array.into_raw()
+}
 
-    fn get_block_color(&self, pos: IVec3, tint_index: i32) -> u32 {
-        self.env
-            .borrow_mut()
-            .call_static_method(
-                "dev/birb/wgpu/render/Wgpu",
-                "helperGetBlockColor",
-                "(IIII)I",
-                &[
-                    JValue::Int(pos.x),
-                    JValue::Int(pos.y),
-                    JValue::Int(pos.z),
-                    JValue::Int(tint_index),
-                ],
-            )
-            .unwrap()
-            .i()
-            .unwrap() as u32
-    }
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn setSectionPos(_env: JNIEnv, _class: JClass, x: jint, z: jint) {
+    SCENE.camera.write().section_pos = ivec2(x, z);
 }
 
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
@@ -458,6 +505,8 @@ pub fn bakeSection(
     storageIndices: JLongArray,
     blockBytes: JObjectArray,
     skyBytes: JObjectArray,
+    biomeIndices: JObjectArray,
+    biomeBlendRadius: jint,
 ) {
     let palette_elements =
         unsafe { env.get_array_elements(&paletteIndices, ReleaseMode::NoCopyBack) }.unwrap();
@@ -471,8 +520,14 @@ pub fn bakeSection(
     let mut bsp = MinecraftBlockstateProvider {
         sections: [NONE; 27],
         air: *AIR,
+        origin: ivec3(x, y, z) * 16,
+        biome_colors: RENDERER.get().unwrap().mc.biome_colors.load_full(),
+        block_tints: BLOCK_TINTS.load_full(),
+        biome_blend_radius: biomeBlendRadius,
     };
 
+    let registered_biomes = BIOMES.read();
+
     for i in 0..27 {
         let mut palette_storage = PALETTE_STORAGE.write();
         let mut pia_storage = PIA_STORAGE.write();
@@ -506,6 +561,24 @@ pub fn bakeSection(
         let block_bytes =
             unsafe { env.get_array_elements(&block_array, ReleaseMode::NoCopyBack) }.unwrap();
 
+        let biome_array: JIntArray = env
+            .get_object_array_element(&biomeIndices, i as jsize)
+            .unwrap()
+            .into();
+        let biomes = if biome_array.is_null() {
+            None
+        } else {
+            let mut ids = [0; 64];
+            env.get_int_array_region(&biome_array, 0, &mut ids).unwrap();
+
+            Some(Box::new(ids.map(|id| {
+                registered_biomes
+                    .get(id as usize)
+                    .copied()
+                    .unwrap_or_default()
+            })))
+        };
+
         bsp.sections[i] = Some(SectionHolder {
             block_data,
             light_data: Some(DeserializedLightData {
@@ -520,6 +593,7 @@ pub fn bakeSection(
                         .unwrap(),
                 ),
             }),
+            biomes,
         });
     }
 
@@ -527,12 +601,21 @@ pub fn bakeSection(
     let wm = RENDERER.get().unwrap();
     // let env = jvm.attach_current_thread_as_daemon().unwrap();
 
-    let wrapper = MinecraftBlockStateProviderWrapper {
-        internal: bsp,
-        env: RefCell::new(env),
+    drop(registered_biomes);
+
+    let settings = SETTINGS.read();
+    let options = BakeOptions {
+        greedy_meshing: settings
+            .as_ref()
+            .is_some_and(|settings| settings.greedy_meshing.value),
+        lighting: match settings.as_ref() {
+            Some(settings) if settings.vanilla_lighting.value => LightingMode::Vanilla,
+            _ => LightingMode::Simple,
+        },
     };
+    drop(settings);
 
-    bake_section(ivec3(x, y, z), wm, &wrapper);
+    bake_section(ivec3(x, y, z), wm, &bsp, options);
     // })
 }
 
@@ -543,6 +626,57 @@ pub fn registerBlock(mut env: JNIEnv, _class: JClass, name: JString) {
     BLOCKS.lock().push(name);
 }
 
+/// `layer` is the index of a [RenderLayer]. Blocks have to be registered before [cacheBlockStates] bakes them,
+/// ones which weren't are baked as solid blocks.
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+pub fn registerBlockLayer(mut env: JNIEnv, _class: JClass, name: JString, layer: jint) {
+    let name: String = env.get_string(&name).unwrap().into();
+    let layer = RenderLayer::ALL
+        .get(layer as usize)
+        .copied()
+        .unwrap_or(RenderLayer::Solid);
+
+    BLOCK_LAYERS.lock().insert(name, layer);
+}
+
+/// Colour overrides are -1 if the biome doesn't have one. `grassColorModifier` is the ordinal of the modifier.
+#[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
+#[allow(clippy::too_many_arguments)]
+pub fn registerBiome(
+    _env: JNIEnv,
+    _class: JClass,
+    id: jint,
+    temperature: jfloat,
+    downfall: jfloat,
+    grassColor: jint,
+    foliageColor: jint,
+    waterColor: jint,
+    grassColorModifier: jint,
+) {
+    let color = |color: jint| (color != -1).then_some(color as u32 & 0xffffff);
+
+    let biome = Biome {
+        temperature,
+        downfall,
+        grass_color: color(grassColor),
+        foliage_color: color(foliageColor),
+        water_color: waterColor as u32 & 0xffffff,
+        grass_color_modifier: match grassColorModifier {
+            1 => GrassColorModifier::DarkForest,
+            2 => GrassColorModifier::Swamp,
+            _ => GrassColorModifier::None,
+        },
+    };
+
+    let mut biomes = BIOMES.write();
+
+    if biomes.len() <= id as usize {
+        biomes.resize(id as usize + 1, Biome::default());
+    }
+
+    biomes[id as usize] = biome;
+}
+
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
 pub fn startRendering(mut env: JNIEnv, _class: JClass, title: JString) {
     let title: String = env.get_string(&title).unwrap().into();
@@ -583,17 +717,35 @@ pub fn startRendering(mut env: JNIEnv, _class: JClass, title: JString) {
 }
 
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
-pub fn render(_env: JNIEnv, _class: JClass, _tick_delta: jfloat, _start_time: jlong, _tick: jlong) {
+pub fn render(
+    _env: JNIEnv,
+    _class: JClass,
+    _tick_delta: jfloat,
+    start_time: jlong,
+    _tick: jboolean,
+) {
     let wm = RENDERER.wait();
-    let render_graph = RENDER_GRAPH.get().unwrap().lock();
+    let mut render_graph = RENDER_GRAPH.get().unwrap().lock();
     let mut geometry = CUSTOM_GEOMETRY.get().unwrap().lock();
-    wm.display.window.request_redraw();
+    wm.display.window().unwrap().request_redraw();
+
+    //Block textures animate at 20 ticks per second, start_time is from System.nanoTime
+    wm.update_animated_textures((start_time / 50_000_000) as u32);
+    render_graph.update_texture_resources(wm);
+
+    let matrices = MATRICES.lock();
+    let camera = {
+        let mut camera = SCENE.camera.write();
+        camera.model = Mat4::from_cols_array_2d(&matrices.terrain_transformation);
+        camera.view = Mat4::from_cols_array_2d(&matrices.view);
+        camera.projection = Mat4::from_cols_array_2d(&matrices.projection);
+        *camera
+    };
+
     wm.submit_chunk_updates(&SCENE);
-    let pos = *SCENE.camera_section_pos.read();
-    SCENE.section_storage.write().trim(pos);
+    SCENE.section_storage.write().trim(camera.section_pos);
     *SCENE.entity_instances.lock() = ENTITY_INSTANCES.lock().clone();
 
-    let matrices = MATRICES.lock();
     if let ResourceBacking::Buffer(buffer, _) = &render_graph.resources["@mat4_perspective"] {
         wm.display
             .queue
@@ -614,7 +766,8 @@ pub fn render(_env: JNIEnv, _class: JClass, _tick_delta: jfloat, _start_time: jl
 
     let texture = wm
         .display
-        .surface
+        .surface()
+        .unwrap()
         .get_current_texture()
         .unwrap_or_else(|_| {
             //The surface is outdated, so we force an update. This can't be done on the window resize event for synchronization reasons.
@@ -625,9 +778,10 @@ pub fn render(_env: JNIEnv, _class: JClass, _tick_delta: jfloat, _start_time: jl
             surface_config.height = size.height;
 
             wm.display
-                .surface
+                .surface()
+                .unwrap()
                 .configure(&wm.display.device, &surface_config);
-            wm.display.surface.get_current_texture().unwrap()
+            wm.display.surface().unwrap().get_current_texture().unwrap()
         });
 
     let view = texture.texture.create_view(&wgpu::TextureViewDescriptor {
@@ -647,15 +801,7 @@ pub fn render(_env: JNIEnv, _class: JClass, _tick_delta: jfloat, _start_time: jl
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        render_graph.render(
-            wm,
-            &mut encoder,
-            &SCENE,
-            &view,
-            [0; 3],
-            &mut geometry,
-            &Frustum::from_modelview_projection([[0.0; 4]; 4]),
-        );
+        render_graph.render(wm, &mut encoder, &SCENE, &view, [0; 3], &mut geometry);
 
         wm.display.queue.submit([encoder.finish()]);
     }
@@ -668,6 +814,7 @@ pub fn cacheBlockStates(mut env: JNIEnv, _class: JClass) {
     let wm = RENDERER.get().unwrap();
     {
         let blocks = BLOCKS.lock();
+        let layers = BLOCK_LAYERS.lock();
 
         let blockstates = blocks
             .iter()
@@ -681,17 +828,79 @@ pub fn cacheBlockStates(mut env: JNIEnv, _class: JClass) {
             })
             .collect::<Vec<_>>();
 
-        wm.mc.bake_blocks(
+        let failures = wm.mc.bake_blocks(
             wm,
-            blockstates
-                .iter()
-                .map(|(string, resource)| (string, resource)),
+            blockstates.iter().map(|(string, resource)| {
+                let layer = layers.get(string).copied().unwrap_or(RenderLayer::Solid);
+                (string, resource, layer)
+            }),
         );
+
+        for (block_name, error) in failures {
+            log::warn!("Failed to bake {block_name}: {error}");
+        }
     }
 
+    wm.mc
+        .biome_colors
+        .store(Arc::new(BiomeColors::new(&*wm.mc.resource_provider)));
+
     let mut states = BLOCK_STATES.lock();
 
-    let block_manager = wm.mc.block_manager.write();
+    let mut block_manager = wm.mc.block_manager.write();
+
+    BLOCK_TINTS.store(Arc::new(
+        block_manager
+            .blocks
+            .keys()
+            .map(|block_name| BlockTint::for_block(block_name))
+            .collect(),
+    ));
+
+    {
+        //Every state of every block is known up front, so multipart blocks can be baked eagerly instead of lazily
+        let mut domains: HashMap<&str, Vec<(String, Vec<StateValue>)>> = HashMap::new();
+
+        for (block_name, state_key, _) in states.iter() {
+            let domain = domains.entry(&block_name[..]).or_default();
+
+            for (property, value) in state_key.split(',').filter_map(|kv| kv.split_once('=')) {
+                if property == "waterlogged" {
+                    continue;
+                }
+
+                let value = match value {
+                    "true" => StateValue::Bool(true),
+                    "false" => StateValue::Bool(false),
+                    other => StateValue::String(other.into()),
+                };
+
+                match domain.iter_mut().find(|(name, _)| name == property) {
+                    Some((_, values)) if !values.contains(&value) => values.push(value),
+                    Some(_) => {}
+                    None => domain.push((property.to_string(), vec![value])),
+                }
+            }
+        }
+
+        let atlases = wm.mc.texture_manager.atlases.read();
+        let mut failures = block_manager.bake_multiparts(
+            domains
+                .iter()
+                .map(|(block_name, domain)| (*block_name, &domain[..])),
+            &*wm.mc.resource_provider,
+            &atlases[BLOCK_ATLAS],
+        );
+        failures.extend(
+            wm.mc
+                .upload_block_atlas(wm, &mut block_manager, &atlases[BLOCK_ATLAS]),
+        );
+
+        for (block_name, error) in failures {
+            log::warn!("Failed to bake {block_name}: {error}");
+        }
+    }
+
     let mut mappings = Vec::new();
 
     let mut stdout = stdout().lock();
@@ -810,7 +1019,7 @@ pub fn runHelperThread(mut env: JNIEnv, _class: JClass) {
 
     for render_message in rx.iter() {
         match render_message {
-            RenderMessage::SetTitle(title) => wm.display.window.set_title(&title),
+            RenderMessage::SetTitle(title) => wm.display.window().unwrap().set_title(&title),
             RenderMessage::KeyPressed(_) => {}
             RenderMessage::MouseMove(x, y) => {
                 env.call_static_method(
@@ -900,7 +1109,7 @@ pub fn runHelperThread(mut env: JNIEnv, _class: JClass) {
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
 pub fn centerCursor(_env: JNIEnv, _class: JClass, _locked: jboolean) {
     if let Some(wm) = RENDERER.get() {
-        let window = &wm.display.window;
+        let window = wm.display.window().unwrap();
         let inner = window.inner_position().unwrap();
         let size = window.inner_size();
         window
@@ -916,7 +1125,7 @@ pub fn centerCursor(_env: JNIEnv, _class: JClass, _locked: jboolean) {
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
 pub fn setCursorLocked(_env: JNIEnv, _class: JClass, locked: jboolean) {
     if let Some(wm) = RENDERER.get() {
-        let window = &wm.display.window;
+        let window = wm.display.window().unwrap();
         if locked == JNI_TRUE {
             window.set_cursor_visible(false);
             window
@@ -1212,7 +1421,8 @@ pub fn getVideoMode(env: JNIEnv, _class: JClass) -> jstring {
         .get()
         .unwrap()
         .display
-        .window
+        .window()
+        .unwrap()
         .current_monitor()
         .unwrap()
         .video_modes()
@@ -1310,7 +1520,8 @@ pub fn setCursorPosition(_env: JNIEnv, _class: JClass, x: f64, y: f64) {
         .get()
         .unwrap()
         .display
-        .window
+        .window()
+        .unwrap()
         .set_cursor_position(PhysicalPosition { x, y })
         .unwrap();
 }
@@ -1322,7 +1533,7 @@ const GLFW_CURSOR_DISABLED: i32 = 212995;
 /// See https://www.glfw.org/docs/3.3/input_guide.html#cursor_mode
 #[jni_fn("dev.birb.wgpu.rust.WgpuNative")]
 pub fn setCursorMode(_env: JNIEnv, _class: JClass, mode: i32) {
-    let window = &RENDERER.get().unwrap().display.window;
+    let window = RENDERER.get().unwrap().display.window().unwrap();
     match mode {
         GLFW_CURSOR_NORMAL => {
             window.set_cursor_grab(CursorGrabMode::None).unwrap();
@@ -23,6 +23,8 @@ static RENDERER_CONFIG_JSON: OnceCell<PathBuf> = OnceCell::new();
 #[non_exhaustive]
 pub struct Settings {
     pub vsync: BoolSetting,
+    pub greedy_meshing: BoolSetting,
+    pub vanilla_lighting: BoolSetting,
     pub test_enum: EnumSetting,
     pub test_float: FloatSetting,
     pub test_int: IntSetting,
@@ -31,6 +33,8 @@ pub struct Settings {
 #[derive(Serialize)]
 pub struct SettingsInfo {
     vsync: SettingInfo,
+    greedy_meshing: SettingInfo,
+    vanilla_lighting: SettingInfo,
     test_enum: EnumSettingInfo<TestEnumSetting>,
     test_float: SettingInfo,
     test_int: SettingInfo,
@@ -43,6 +47,16 @@ lazy_static! {
             May reduce screen tearing, on the cost of added latency.",
             needs_restart: true,
         },
+        greedy_meshing: SettingInfo {
+            desc: "Merge identical neighbouring block faces into larger ones when building chunk meshes. \
+            Reduces the amount of geometry, applies to chunks rebuilt after changing it.",
+            needs_restart: false,
+        },
+        vanilla_lighting: SettingInfo {
+            desc: "Light blocks the same way as vanilla's smooth lighting, including its ambient occlusion \
+            and the shading of each side of a block. Applies to chunks rebuilt after changing it.",
+            needs_restart: false,
+        },
         test_enum: EnumSettingInfo::new("", true,),
         test_float: SettingInfo {
             desc: "test float - ignore this",
@@ -96,6 +110,8 @@ impl Default for Settings {
     fn default() -> Self {
         Settings {
             vsync: BoolSetting { value: true },
+            greedy_meshing: BoolSetting { value: false },
+            vanilla_lighting: BoolSetting { value: false },
             test_enum: EnumSetting::from_variant(TestEnumSetting::Off),
             test_float: FloatSetting {
                 min: 70.0,
@@ -16,9 +16,11 @@ in the future.
 # Setup
 
 wgpu-mc, as you could have probably guessed, uses the [wgpu](https://github.com/gfx-rs/wgpu) crate
-for communicating with the GPU. Assuming you aren't running wgpu-mc headless (if you are, I assume
-you already know what you're doing), wgpu-mc can handle surface and device setup for you, as long
-as you pass in a valid window handle. See [init_wgpu]
+for communicating with the GPU. Assuming you aren't running wgpu-mc headless, wgpu-mc can handle surface
+and device setup for you, as long as you pass in a valid window handle. See [init_wgpu]
+
+To render without a window, for example in tests or on a server, create a [Display] with [Display::headless].
+Frames are then rendered into [Display::offscreen_target] instead of a surface.
 
 # Rendering
 
@@ -43,9 +45,10 @@ use std::collections::HashMap;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 
+use bytemuck::Zeroable;
 use glam::IVec3;
-use mc::chunk::BakedLayer;
-use mc::Scene;
+use mc::chunk::{BakedSection, SectionStorage};
+use mc::{create_chunk_buffer, Scene};
 pub use minecraft_assets;
 use parking_lot::{Mutex, RwLock};
 pub use wgpu;
@@ -53,31 +56,170 @@ use wgpu::{BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BufferDescripto
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
+use crate::mc::biome::BiomeColors;
 use crate::mc::resource::ResourceProvider;
 use crate::mc::MinecraftState;
-use crate::render::atlas::Atlas;
+use crate::render::atlas::{AnimatedUV, Atlas};
 use crate::render::pipeline::{create_bind_group_layouts, BLOCK_ATLAS, ENTITY_ATLAS};
+use crate::texture::TextureAndView;
 
 pub mod mc;
 pub mod render;
 pub mod texture;
 pub mod util;
 
+#[cfg(test)]
+mod testing;
+
 pub use treeculler::Frustum;
 
 /// Provides access to most of the wgpu structs relating directly to communicating/getting
 /// information about the gpu.
 
 pub struct Display {
-    pub window: Arc<Window>,
+    pub target: DisplayTarget,
     pub instance: wgpu::Instance,
     pub adapter: wgpu::Adapter,
     pub size: RwLock<PhysicalSize<u32>>,
-    pub surface: Surface<'static>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    /// Size and format of what gets rendered to. When headless this describes the offscreen texture.
     pub config: RwLock<wgpu::SurfaceConfiguration>,
 }
+
+/// Where frames end up
+pub enum DisplayTarget {
+    Window {
+        window: Arc<Window>,
+        surface: Surface<'static>,
+    },
+    /// A texture to render into instead of a surface, see [Display::headless]
+    Offscreen(TextureAndView),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HeadlessError {
+    #[error("No graphics adapter is available")]
+    NoAdapter,
+    #[error(transparent)]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+}
+
+impl Display {
+    /// Create a display without a window which renders into an offscreen texture of the given size and format.
+    ///
+    /// Any adapter is accepted, falling back to software ones like llvmpipe or WARP if there is no GPU,
+    /// so this works in containers without a display server. Backends can be restricted with `WGPU_BACKEND`.
+    pub async fn headless(
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self, HeadlessError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all()),
+            ..Default::default()
+        });
+
+        let mut adapter = None;
+        for force_fallback_adapter in [false, true] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    force_fallback_adapter,
+                    compatible_surface: None,
+                })
+                .await;
+
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.ok_or(HeadlessError::NoAdapter)?;
+
+        //Take whatever the adapter offers of what the windowed setups ask for
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: adapter.features()
+                        & (wgpu::Features::DEPTH_CLIP_CONTROL
+                            | wgpu::Features::PUSH_CONSTANTS
+                            | render::terrain::INDIRECT_DRAW_FEATURES),
+                    required_limits: adapter.limits(),
+                    memory_hints: wgpu::MemoryHints::Performance,
+                },
+                None,
+            )
+            .await?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: config.usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Self {
+            target: DisplayTarget::Offscreen(TextureAndView {
+                texture,
+                view,
+                format,
+            }),
+            instance,
+            adapter,
+            size: RwLock::new(PhysicalSize::new(width, height)),
+            device,
+            queue,
+            config: RwLock::new(config),
+        })
+    }
+
+    /// None when headless
+    pub fn window(&self) -> Option<&Arc<Window>> {
+        match &self.target {
+            DisplayTarget::Window { window, .. } => Some(window),
+            DisplayTarget::Offscreen(_) => None,
+        }
+    }
+
+    /// None when headless
+    pub fn surface(&self) -> Option<&Surface<'static>> {
+        match &self.target {
+            DisplayTarget::Window { surface, .. } => Some(surface),
+            DisplayTarget::Offscreen(_) => None,
+        }
+    }
+
+    /// The texture frames are rendered into when headless
+    pub fn offscreen_target(&self) -> Option<&TextureAndView> {
+        match &self.target {
+            DisplayTarget::Window { .. } => None,
+            DisplayTarget::Offscreen(target) => Some(target),
+        }
+    }
+}
 /// The main wgpu-mc renderer struct
 /// Resources pertaining to Minecraft go in `MinecraftState`.
 ///
@@ -87,8 +229,8 @@ pub struct WmRenderer {
     pub bind_group_layouts: Arc<HashMap<String, BindGroupLayout>>,
     pub mc: MinecraftState,
     pub chunk_update_queue: (
-        Sender<(IVec3, Vec<BakedLayer>)>,
-        Mutex<Receiver<(IVec3, Vec<BakedLayer>)>>,
+        Sender<(IVec3, BakedSection)>,
+        Mutex<Receiver<(IVec3, BakedSection)>>,
     ),
 }
 
@@ -117,21 +259,38 @@ impl WmRenderer {
     pub fn init(&self) {
         let atlases = [BLOCK_ATLAS, ENTITY_ATLAS]
             .iter()
-            .map(|&name| (name.into(), Atlas::new(&self.display, false)))
+            .map(|&name| (name.into(), Atlas::new(&self.display, true)))
             .collect();
 
         *self.mc.texture_manager.atlases.write() = atlases;
+
+        //The render graph binds the animation buffer, so it has to exist before anything is animated
+        self.update_animated_textures(0);
     }
 
+    /// Advance block texture animations to `tick`, which counts game ticks, and upload the frames each animated
+    /// texture shows to the buffer the terrain shaders read them from
+    pub fn update_animated_textures(&self, tick: u32) {
+        let atlases = self.mc.texture_manager.atlases.read();
+        let uvs = match atlases.get(BLOCK_ATLAS) {
+            Some(atlas) => atlas.animated_uvs(tick),
+            None => vec![AnimatedUV::zeroed()],
+        };
+
+        self.upload_animated_block_buffer(bytemuck::cast_slice(&uvs).to_vec());
+    }
+
+    /// Write to the buffer bound as `@bg_ssbo_animated_uvs`, replacing it if it's too small
     pub fn upload_animated_block_buffer(&self, data: Vec<f32>) {
         let d = data.as_slice();
+        let size = std::mem::size_of_val(d) as wgpu::BufferAddress;
 
         let buf = self.mc.animated_block_buffer.borrow().load_full();
 
-        if buf.is_none() {
+        if (*buf).as_ref().is_none_or(|buf| buf.size() < size) {
             let animated_block_buffer = self.display.device.create_buffer(&BufferDescriptor {
                 label: None,
-                size: (d.len() * 8) as wgpu::BufferAddress,
+                size,
                 usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
                 mapped_at_creation: false,
             });
@@ -162,31 +321,474 @@ impl WmRenderer {
         );
     }
 
+    /// Rebuild everything loaded from resources from the current [ResourceProvider], e.g. after resource packs
+    /// were changed: the block atlas and baked block models, the other atlases and the biome colormaps.
+    ///
+    /// Everything is rebuilt next to the state in use, which keeps rendering while this runs. [mc::block::BlockstateKey]s
+    /// of blocks whose blockstates didn't change stay the same. Textures in the other atlases can move, so UVs looked
+    /// up from them have to be looked up again.
+    ///
+    /// Every loaded section of `scene` is queued to be baked again, see [mc::chunk::SectionStorage::take_rebake_queue].
+    /// Sections are baked with the new block models from then on, but the block atlas and block models are only
+    /// swapped in by [WmRenderer::submit_chunk_updates] once every loaded section has been baked with them, see
+    /// [mc::PendingReload]. Sections which were being baked while this ran are discarded and queued again when
+    /// they're submitted.
+    ///
+    /// Returns the blocks which failed to bake, like [MinecraftState::bake_blocks].
+    pub fn reload_resources(&self, scene: &Scene) -> Vec<(String, mc::block::MeshBakeError)> {
+        let (block_manager, block_atlas, failures) = self.mc.rebake_blocks(self);
+
+        let atlases = self
+            .mc
+            .texture_manager
+            .atlases
+            .read()
+            .iter()
+            .filter(|(name, _)| *name != BLOCK_ATLAS)
+            .map(|(name, atlas)| (name.clone(), self.reload_atlas(name, atlas)))
+            .collect::<Vec<_>>();
+
+        let biome_colors = BiomeColors::new(&*self.mc.resource_provider);
+
+        //Sections don't use the other atlases, and biome colours are baked into their vertices, so sections can mix
+        //old and new ones
+        self.mc.texture_manager.atlases.write().extend(atlases);
+        self.mc.biome_colors.store(Arc::new(biome_colors));
+
+        {
+            let mut pending_reload = self.mc.pending_reload.write();
+            let mut storage = scene.section_storage.write();
+
+            storage.queue_rebake_all();
+            *pending_reload = Some(mc::PendingReload {
+                block_manager,
+                block_atlas,
+                sections: Mutex::new(mc::PendingSections {
+                    waiting: storage.iter().map(|(pos, _)| *pos).collect(),
+                    baked: Vec::new(),
+                }),
+            });
+        }
+
+        self.finish_reload(scene);
+
+        failures
+    }
+
+    /// Swap in a pending [WmRenderer::reload_resources] once every section loaded when it ran has been baked with it,
+    /// along with those sections
+    fn finish_reload(&self, scene: &Scene) {
+        {
+            let pending_reload = self.mc.pending_reload.read();
+            let Some(pending) = &*pending_reload else {
+                return;
+            };
+            let storage = scene.section_storage.read();
+
+            let mut sections = pending.sections.lock();
+            //Sections unloaded in the meantime won't be baked again
+            sections.waiting.retain(|pos| storage.contains(*pos));
+            if !sections.waiting.is_empty() {
+                return;
+            }
+        }
+
+        let Some(pending) = self.mc.pending_reload.write().take() else {
+            return;
+        };
+
+        *self.mc.block_manager.write() = pending.block_manager;
+        self.mc
+            .texture_manager
+            .atlases
+            .write()
+            .insert(BLOCK_ATLAS.into(), pending.block_atlas);
+
+        let mut storage = scene.section_storage.write();
+        for (pos, baked_section) in pending.sections.into_inner().baked {
+            self.upload_section(scene, &mut storage, pos, &baked_section);
+        }
+    }
+
+    /// Allocate the textures of an atlas again from the current resource provider, into a new atlas
+    fn reload_atlas(&self, name: &str, atlas: &Atlas) -> Atlas {
+        let reloaded = Atlas::with_options(&self.display, true, atlas.options());
+
+        let textures = atlas
+            .uv_map
+            .read()
+            .keys()
+            .filter_map(|path| match self.mc.resource_provider.get_bytes(path) {
+                Some(bytes) => Some((path.clone(), bytes)),
+                None => {
+                    log::warn!("Texture {path} of atlas {name} no longer exists");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if let Err(error) = reloaded.allocate(
+            textures.iter().map(|(path, bytes)| (path, bytes)),
+            &*self.mc.resource_provider,
+        ) {
+            log::warn!("Failed to reload atlas {name}: {error}");
+        }
+        reloaded.upload(self);
+
+        reloaded
+    }
+
     pub fn submit_chunk_updates(&self, scene: &Scene) {
         let receiver = self.chunk_update_queue.1.lock();
         let updates = receiver.try_iter();
 
-        updates.for_each(|(pos, layers)| {
-            let mut storage = scene.section_storage.write();
-            let section = storage.replace(pos, &layers);
-            for (i, ranges) in section.layers.iter().enumerate() {
-                if let Some(ranges) = ranges {
-                    self.display.queue.write_buffer(
-                        &scene.chunk_buffer.buffer,
-                        ranges.vertex_range.start as u64 * 4,
-                        &layers[i].vertices,
-                    );
-                    self.display.queue.write_buffer(
-                        &scene.chunk_buffer.buffer,
-                        ranges.index_range.start as u64 * 4,
-                        &layers[i].indices,
-                    );
+        {
+            let pending_reload = self.mc.pending_reload.read();
+            let generation = match &*pending_reload {
+                Some(pending) => pending.block_manager.generation,
+                None => self.mc.block_manager.read().generation,
+            };
+
+            updates.for_each(|(pos, baked_section)| {
+                let mut storage = scene.section_storage.write();
+
+                //Baked before the last reload, with textures that may have moved since
+                if baked_section.generation != generation {
+                    storage.queue_rebake(pos);
+                    return;
+                }
+
+                //Held back until the reload is swapped in, it uses textures from the new atlas
+                if let Some(pending) = &*pending_reload {
+                    let mut sections = pending.sections.lock();
+                    sections.waiting.remove(&pos);
+                    sections.baked.push((pos, baked_section));
+                    return;
                 }
+
+                self.upload_section(scene, &mut storage, pos, &baked_section);
+            });
+        }
+
+        self.finish_reload(scene);
+        self.sort_translucent_sections(scene);
+    }
+
+    fn upload_section(
+        &self,
+        scene: &Scene,
+        storage: &mut SectionStorage,
+        pos: IVec3,
+        baked_section: &BakedSection,
+    ) {
+        let section = storage.replace(pos, baked_section);
+
+        let mut chunk_buffers = scene.chunk_buffers.write();
+        let page_size = storage.page_size() as u64 * 4;
+        chunk_buffers.truncate(storage.page_count());
+        while chunk_buffers.len() < storage.page_count() {
+            chunk_buffers.push(Arc::new(create_chunk_buffer(self, page_size)));
+        }
+
+        for (i, ranges) in section.layers.iter().enumerate() {
+            if let Some(ranges) = ranges {
+                let buffer = &chunk_buffers[ranges.page].buffer;
+                self.display.queue.write_buffer(
+                    buffer,
+                    ranges.vertex_range.start as u64 * 4,
+                    &baked_section.layers[i].vertices,
+                );
+                self.display.queue.write_buffer(
+                    buffer,
+                    ranges.index_range.start as u64 * 4,
+                    &baked_section.layers[i].indices,
+                );
             }
-        });
+        }
+    }
+
+    /// Re-sort translucent geometry back-to-front and upload the affected index ranges
+    pub fn sort_translucent_sections(&self, scene: &Scene) {
+        let camera = *scene.camera.read();
+
+        let mut section_storage = scene.section_storage.write();
+        let sorted = section_storage.sort_translucent(camera.section_pos, camera.position());
+        let chunk_buffers = scene.chunk_buffers.read();
+
+        for (page, index_start, indices) in sorted {
+            self.display.queue.write_buffer(
+                &chunk_buffers[page].buffer,
+                index_start as u64 * 4,
+                &indices,
+            );
+        }
     }
 
     pub fn get_backend_description(&self) -> String {
         format!("wgpu 0.20 ({:?})", self.display.adapter.get_info().backend)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_display() {
+        let Some(display) = testing::headless_display(64, 32) else {
+            return;
+        };
+
+        assert!(display.window().is_none());
+        assert!(display.surface().is_none());
+
+        let target = display.offscreen_target().unwrap();
+        assert_eq!(target.texture.width(), 64);
+        assert_eq!(target.texture.height(), 32);
+        assert_eq!(
+            display.config.read().format,
+            wgpu::TextureFormat::Rgba8Unorm
+        );
+    }
+
+    #[test]
+    fn reload_keeps_blockstate_keys() {
+        use mc::chunk::{BakedSection, RenderLayer, SectionVisibility};
+        use mc::resource::ResourcePath;
+        use mc::{Block, WeightedModel};
+        use testing::MemoryResources;
+
+        fn add_block(resources: &MemoryResources, name: &str, texture_size: u32) {
+            resources.insert(
+                format!("minecraft:blockstates/{name}.json"),
+                format!(r#"{{ "variants": {{ "": {{ "model": "block/{name}" }} }} }}"#),
+            );
+            resources.insert(
+                format!("minecraft:models/block/{name}.json"),
+                format!(
+                    r#"{{ "textures": {{ "all": "block/{name}" }}, "elements": [{{ "from": [0, 0, 0], "to": [16, 16, 16], "faces": {{ "up": {{ "texture": "block/{name}" }} }} }}] }}"#
+                ),
+            );
+            resources.insert(
+                format!("minecraft:textures/block/{name}.png"),
+                testing::png(&image::RgbaImage::new(texture_size, texture_size)),
+            );
+        }
+
+        let resources = Arc::new(MemoryResources::default());
+        add_block(&resources, "stone", 16);
+        add_block(&resources, "dirt", 16);
+
+        let Some(wm) = testing::headless_renderer(16, 16, resources.clone()) else {
+            return;
+        };
+
+        let blockstates = ["stone", "dirt"].map(|name| {
+            (
+                format!("minecraft:{name}"),
+                ResourcePath(format!("minecraft:blockstates/{name}.json")),
+            )
+        });
+        assert!(wm
+            .mc
+            .bake_blocks(
+                &wm,
+                blockstates
+                    .iter()
+                    .map(|(name, path)| (name, path, RenderLayer::Solid))
+            )
+            .is_empty());
+
+        let scene = Scene::new(
+            &wm,
+            wgpu::Extent3d {
+                width: 16,
+                height: 16,
+                depth_or_array_layers: 1,
+            },
+        );
+        let empty_section = |generation| BakedSection {
+            layers: Vec::new(),
+            visibility: SectionVisibility::ALL,
+            generation,
+        };
+        scene
+            .section_storage
+            .write()
+            .replace(IVec3::ZERO, &empty_section(0));
+
+        //A block which wasn't baked from a blockstate, made from the models of dirt
+        {
+            let mut block_manager = wm.mc.block_manager.write();
+            let Block::Variants(dirt) = &block_manager.blocks["minecraft:dirt"] else {
+                unreachable!()
+            };
+            let custom = Block::Variants(
+                dirt.iter()
+                    .map(|(key, models)| {
+                        (
+                            key.clone(),
+                            models
+                                .iter()
+                                .map(|model| WeightedModel {
+                                    mesh: model.mesh.clone(),
+                                    weight: model.weight,
+                                })
+                                .collect(),
+                        )
+                    })
+                    .collect(),
+            );
+            block_manager.blocks.insert("custom:dirt".into(), custom);
+        }
+
+        //A resource pack with a higher resolution dirt texture
+        add_block(&resources, "dirt", 32);
+        assert!(wm.reload_resources(&scene).is_empty());
+
+        let mut rebake_queue = scene.section_storage.write().take_rebake_queue();
+        assert_eq!(rebake_queue, [IVec3::ZERO]);
+
+        //Baked before the reload, so it's queued again instead of being uploaded
+        wm.chunk_update_queue
+            .0
+            .send((IVec3::X, empty_section(0)))
+            .unwrap();
+        wm.submit_chunk_updates(&scene);
+
+        {
+            let mut storage = scene.section_storage.write();
+            rebake_queue = storage.take_rebake_queue();
+            assert_eq!(rebake_queue, [IVec3::X]);
+            assert!(storage.iter().all(|(pos, _)| *pos != IVec3::X));
+        }
+
+        //Nothing is swapped in until the loaded section is baked with the reloaded blocks
+        assert_eq!(wm.mc.block_manager.read().generation, 0);
+
+        wm.chunk_update_queue
+            .0
+            .send((IVec3::ZERO, empty_section(1)))
+            .unwrap();
+        wm.submit_chunk_updates(&scene);
+        assert!(wm.mc.pending_reload.read().is_none());
+
+        let block_manager = wm.mc.block_manager.read();
+        assert_eq!(block_manager.generation, 1);
+        assert_eq!(
+            block_manager.blocks.get_index_of("minecraft:stone"),
+            Some(0)
+        );
+        assert_eq!(block_manager.blocks.get_index_of("minecraft:dirt"), Some(1));
+
+        let atlases = wm.mc.texture_manager.atlases.read();
+        let dirt_uv =
+            atlases[BLOCK_ATLAS].uv_map.read()[&ResourcePath::from("minecraft:block/dirt")];
+        let ((u1, _), (u2, _)) = dirt_uv;
+        assert_eq!(u2 - u1, 32);
+
+        //The block which couldn't be baked again was moved to the new atlas
+        let custom = block_manager.blocks["custom:dirt"].get_model(0, 0).unwrap();
+        assert!(custom.samples_any(&[dirt_uv]));
+    }
+
+    #[test]
+    fn post_processing_chain() {
+        use render::graph::RenderGraph;
+        use render::shaderpack::ShaderPackConfig;
+
+        const VERTEX: &str = r#"
+@vertex
+fn vert(@location(0) pos_in: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4(pos_in, 0.0, 1.0);
+}
+"#;
+        const READ_INPUT: &str = r#"
+@group(0) @binding(0) var t_input: texture_2d<f32>;
+
+fn input(pos: vec4<f32>) -> vec4<f32> {
+    return textureLoad(t_input, vec2<i32>(pos.xy), 0);
+}
+"#;
+
+        let shaders = [
+            ("fill", "return vec4(0.2, 0.4, 1.0, 1.0);"),
+            ("invert", "return vec4(1.0 - input(pos).rgb, 1.0);"),
+            ("copy", "return input(pos);"),
+        ]
+        .map(|(name, body)| {
+            let read_input = if name == "fill" { "" } else { READ_INPUT };
+            (
+                format!("wgpu_mc:shaders/{name}.wgsl"),
+                format!("{read_input}{VERTEX}\n@fragment\nfn frag(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {{\n    {body}\n}}\n")
+                    .into_bytes(),
+            )
+        });
+
+        let Some(wm) =
+            testing::headless_renderer(8, 8, Arc::new(testing::MemoryResources::new(shaders)))
+        else {
+            return;
+        };
+
+        let config = ShaderPackConfig::from_yaml(
+            r#"
+version: "0.0.1"
+support: wgsl
+resources: {}
+pipelines:
+  fill:
+    geometry: "@geo_quad"
+    output: ["@post_output"]
+  invert:
+    geometry: "@geo_quad"
+    output: ["@post_output"]
+    bind_groups:
+      0:
+        0: "@post_input"
+  copy:
+    geometry: "@geo_quad"
+    output: ["@framebuffer_texture"]
+    bind_groups:
+      0:
+        0: "@post_input"
+"#,
+        )
+        .unwrap();
+        let graph = RenderGraph::new(&wm, config, HashMap::new(), None, None).unwrap();
+
+        let scene = Scene::new(
+            &wm,
+            wgpu::Extent3d {
+                width: 8,
+                height: 8,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let image = wm
+            .capture_frame(|encoder, view| {
+                graph.render(&wm, encoder, &scene, view, [0; 3], &mut HashMap::new());
+            })
+            .unwrap();
+
+        for (channel, expected) in image.get_pixel(4, 4).0.into_iter().zip([204, 153, 0, 255]) {
+            assert!(channel.abs_diff(expected) <= 1, "{channel} != {expected}");
+        }
+
+        //Tiled captures leave out the post-processing passes, so every tile is what fill rendered
+        let image = wm
+            .capture_frame_tiled(&scene, 2, |_, encoder, view| {
+                graph.render_scene(&wm, encoder, &scene, view, [0; 3], &mut HashMap::new());
+            })
+            .unwrap();
+
+        assert_eq!(image.dimensions(), (16, 16));
+        for (x, y) in [(4, 4), (12, 4), (4, 12), (12, 12)] {
+            for (channel, expected) in image.get_pixel(x, y).0.into_iter().zip([51, 102, 255, 255])
+            {
+                assert!(channel.abs_diff(expected) <= 1, "{channel} != {expected}");
+            }
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! Biome dependent block colours, such as grass, foliage and water tinting.
+
+use glam::IVec3;
+
+use crate::mc::resource::{ResourcePath, ResourceProvider};
+
+/// Vanilla's fallback colours, used when a colormap is missing or doesn't cover a climate
+pub const DEFAULT_GRASS_COLOR: u32 = 0x91bd59;
+pub const DEFAULT_FOLIAGE_COLOR: u32 = 0x48b518;
+pub const DEFAULT_WATER_COLOR: u32 = 0x3f76e4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GrassColorModifier {
+    #[default]
+    None,
+    DarkForest,
+    Swamp,
+}
+
+/// The parts of a biome which affect block colours
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biome {
+    pub temperature: f32,
+    pub downfall: f32,
+    /// Overrides the grass colormap
+    pub grass_color: Option<u32>,
+    /// Overrides the foliage colormap
+    pub foliage_color: Option<u32>,
+    pub water_color: u32,
+    pub grass_color_modifier: GrassColorModifier,
+}
+
+impl Default for Biome {
+    /// The climate of `minecraft:plains`
+    fn default() -> Self {
+        Self {
+            temperature: 0.8,
+            downfall: 0.4,
+            grass_color: None,
+            foliage_color: None,
+            water_color: DEFAULT_WATER_COLOR,
+            grass_color_modifier: GrassColorModifier::None,
+        }
+    }
+}
+
+/// Which colour the tinted faces of a block take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockTint {
+    None,
+    Grass,
+    Foliage,
+    Water,
+    /// A colour which doesn't depend on the biome, like birch leaves
+    Fixed(u32),
+}
+
+impl BlockTint {
+    /// The tint vanilla's `BlockColors` assigns to a block, keyed by its identifier (e.g. `minecraft:oak_leaves`).
+    /// Blocks tinted by their state rather than the biome, like redstone wire, are not covered.
+    pub fn for_block(block_name: &str) -> Self {
+        let name = block_name
+            .split_once(':')
+            .map_or(block_name, |(_, name)| name);
+
+        match name {
+            "grass_block" | "grass" | "short_grass" | "fern" | "tall_grass" | "large_fern"
+            | "potted_fern" | "sugar_cane" => BlockTint::Grass,
+            "oak_leaves" | "jungle_leaves" | "acacia_leaves" | "dark_oak_leaves"
+            | "mangrove_leaves" | "vine" => BlockTint::Foliage,
+            "water" | "bubble_column" | "water_cauldron" => BlockTint::Water,
+            "birch_leaves" => BlockTint::Fixed(0x80a755),
+            "spruce_leaves" => BlockTint::Fixed(0x619961),
+            "lily_pad" => BlockTint::Fixed(0x208030),
+            _ => BlockTint::None,
+        }
+    }
+}
+
+/// A 256x256 colour lookup texture indexed by temperature and downfall, like `colormap/grass.png`
+#[derive(Debug, Clone)]
+pub struct Colormap {
+    pixels: Vec<u32>,
+}
+
+impl Colormap {
+    pub fn load(resource_provider: &dyn ResourceProvider, path: &ResourcePath) -> Option<Self> {
+        let bytes = resource_provider.get_bytes(path)?;
+        let image = image::load_from_memory(&bytes).ok()?.into_rgb8();
+
+        Some(Self {
+            pixels: image
+                .pixels()
+                .map(|pixel| ((pixel[0] as u32) << 16) | ((pixel[1] as u32) << 8) | pixel[2] as u32)
+                .collect(),
+        })
+    }
+
+    /// Look up a colour the same way vanilla's `GrassColors` and `FoliageColors` do
+    pub fn get(&self, temperature: f32, downfall: f32) -> Option<u32> {
+        let temperature = temperature.clamp(0.0, 1.0);
+        let downfall = downfall.clamp(0.0, 1.0) * temperature;
+
+        let x = ((1.0 - temperature) * 255.0) as usize;
+        let y = ((1.0 - downfall) * 255.0) as usize;
+
+        self.pixels.get((y << 8) | x).copied()
+    }
+}
+
+/// Java's `java.util.Random`, which vanilla's fixed noise is seeded with
+struct JavaRandom(u64);
+
+impl JavaRandom {
+    const MASK: u64 = (1 << 48) - 1;
+
+    fn new(seed: i64) -> Self {
+        Self((seed as u64 ^ 0x5deece66d) & Self::MASK)
+    }
+
+    fn next(&mut self, bits: u32) -> i32 {
+        self.0 = self.0.wrapping_mul(0x5deece66d).wrapping_add(0xb) & Self::MASK;
+        (self.0 >> (48 - bits)) as i32
+    }
+
+    fn next_int(&mut self, bound: i32) -> i32 {
+        if bound & (bound - 1) == 0 {
+            return ((bound as i64 * self.next(31) as i64) >> 31) as i32;
+        }
+
+        loop {
+            let bits = self.next(31);
+            let value = bits % bound;
+            if bits.wrapping_sub(value).wrapping_add(bound - 1) >= 0 {
+                return value;
+            }
+        }
+    }
+}
+
+/// A port of vanilla's 2d `SimplexNoiseSampler`, only used for the colour of swamp grass
+#[derive(Debug, Clone)]
+struct SimplexNoise {
+    permutation: [u8; 256],
+}
+
+impl SimplexNoise {
+    const GRADIENTS: [[f64; 2]; 12] = [
+        [1.0, 1.0],
+        [-1.0, 1.0],
+        [1.0, -1.0],
+        [-1.0, -1.0],
+        [1.0, 0.0],
+        [-1.0, 0.0],
+        [1.0, 0.0],
+        [-1.0, 0.0],
+        [0.0, 1.0],
+        [0.0, -1.0],
+        [0.0, 1.0],
+        [0.0, -1.0],
+    ];
+
+    fn new(random: &mut JavaRandom) -> Self {
+        //The sampler's origin, which 2d sampling doesn't use
+        for _ in 0..3 {
+            random.next(26);
+            random.next(27);
+        }
+
+        let mut permutation = [0u8; 256];
+        for (i, value) in permutation.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+        for i in 0..256 {
+            let j = random.next_int(256 - i as i32) as usize;
+            permutation.swap(i, i + j);
+        }
+
+        Self { permutation }
+    }
+
+    fn gradient(&self, hash: i32) -> i32 {
+        self.permutation[(hash & 0xff) as usize] as i32
+    }
+
+    fn corner(hash: i32, x: f64, y: f64) -> f64 {
+        let falloff = 0.5 - x * x - y * y;
+        if falloff < 0.0 {
+            return 0.0;
+        }
+
+        let [gx, gy] = Self::GRADIENTS[hash as usize];
+        falloff.powi(4) * (gx * x + gy * y)
+    }
+
+    fn sample(&self, x: f64, y: f64) -> f64 {
+        let sqrt_3 = 3.0f64.sqrt();
+        let skew = 0.5 * (sqrt_3 - 1.0);
+        let unskew = (3.0 - sqrt_3) / 6.0;
+
+        let d = (x + y) * skew;
+        let i = (x + d).floor() as i32;
+        let j = (y + d).floor() as i32;
+        let e = (i + j) as f64 * unskew;
+        let h = x - (i as f64 - e);
+        let k = y - (j as f64 - e);
+        let (l, m) = if h > k { (1, 0) } else { (0, 1) };
+
+        let (r, s) = (i & 0xff, j & 0xff);
+        let t = self.gradient(r + self.gradient(s)) % 12;
+        let u = self.gradient(r + l + self.gradient(s + m)) % 12;
+        let v = self.gradient(r + 1 + self.gradient(s + 1)) % 12;
+
+        70.0 * (Self::corner(t, h, k)
+            + Self::corner(u, h - l as f64 + unskew, k - m as f64 + unskew)
+            + Self::corner(v, h - 1.0 + 2.0 * unskew, k - 1.0 + 2.0 * unskew))
+    }
+}
+
+impl Default for SimplexNoise {
+    /// Vanilla's `Biome.FOLIAGE_NOISE`
+    fn default() -> Self {
+        Self::new(&mut JavaRandom::new(2345))
+    }
+}
+
+/// Resolves biome colours from the colormaps of the current resource packs.
+/// Colours are `0xRRGGBB`, like in Minecraft.
+#[derive(Debug, Clone, Default)]
+pub struct BiomeColors {
+    grass: Option<Colormap>,
+    foliage: Option<Colormap>,
+    foliage_noise: SimplexNoise,
+}
+
+impl BiomeColors {
+    pub fn new(resource_provider: &dyn ResourceProvider) -> Self {
+        Self {
+            grass: Colormap::load(
+                resource_provider,
+                &"minecraft:textures/colormap/grass.png".into(),
+            ),
+            foliage: Colormap::load(
+                resource_provider,
+                &"minecraft:textures/colormap/foliage.png".into(),
+            ),
+            foliage_noise: SimplexNoise::default(),
+        }
+    }
+
+    pub fn grass_color(&self, biome: &Biome, pos: IVec3) -> u32 {
+        let color = biome.grass_color.unwrap_or_else(|| {
+            self.grass
+                .as_ref()
+                .and_then(|colormap| colormap.get(biome.temperature, biome.downfall))
+                .unwrap_or(DEFAULT_GRASS_COLOR)
+        });
+
+        match biome.grass_color_modifier {
+            GrassColorModifier::None => color,
+            GrassColorModifier::DarkForest => ((color & 0xfefefe) + 0x28340a) >> 1,
+            GrassColorModifier::Swamp => {
+                let noise = self
+                    .foliage_noise
+                    .sample(pos.x as f64 * 0.0225, pos.z as f64 * 0.0225);
+
+                if noise < -0.1 {
+                    0x4c763c
+                } else {
+                    0x6a7039
+                }
+            }
+        }
+    }
+
+    pub fn foliage_color(&self, biome: &Biome) -> u32 {
+        biome.foliage_color.unwrap_or_else(|| {
+            self.foliage
+                .as_ref()
+                .and_then(|colormap| colormap.get(biome.temperature, biome.downfall))
+                .unwrap_or(DEFAULT_FOLIAGE_COLOR)
+        })
+    }
+
+    /// The colour of a tint at `pos` in a single biome, or None if it isn't tinted at all
+    pub fn get(&self, tint: BlockTint, biome: &Biome, pos: IVec3) -> Option<u32> {
+        match tint {
+            BlockTint::None => None,
+            BlockTint::Grass => Some(self.grass_color(biome, pos)),
+            BlockTint::Foliage => Some(self.foliage_color(biome)),
+            BlockTint::Water => Some(biome.water_color),
+            BlockTint::Fixed(color) => Some(color),
+        }
+    }
+
+    /// Average the colour of a tint over the biomes within `radius` blocks horizontally of `pos`, like vanilla's
+    /// biome blend option. Positions where `biome_at` returns None are left out of the average.
+    pub fn blend<'a>(
+        &self,
+        tint: BlockTint,
+        pos: IVec3,
+        radius: i32,
+        biome_at: impl Fn(IVec3) -> Option<&'a Biome>,
+    ) -> Option<u32> {
+        if let BlockTint::None | BlockTint::Fixed(_) = tint {
+            return self.get(tint, &Biome::default(), pos);
+        }
+
+        let mut sum = [0u32; 3];
+        let mut count = 0;
+
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                let sample_pos = pos + IVec3::new(x, 0, z);
+                let Some(biome) = biome_at(sample_pos) else {
+                    continue;
+                };
+                let color = self.get(tint, biome, sample_pos)?;
+
+                sum[0] += (color >> 16) & 0xff;
+                sum[1] += (color >> 8) & 0xff;
+                sum[2] += color & 0xff;
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            return self.get(tint, &Biome::default(), pos);
+        }
+
+        Some(((sum[0] / count) << 16) | ((sum[1] / count) << 8) | (sum[2] / count))
+    }
+}
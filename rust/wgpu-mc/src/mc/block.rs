@@ -1,14 +1,18 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use crate::mc::chunk::RenderLayer;
 use glam::{vec3, Mat3, Vec3};
 use itertools::Itertools;
 use minecraft_assets::api::ModelResolver;
 use minecraft_assets::schemas;
 use minecraft_assets::schemas::blockstates::ModelProperties;
+use parking_lot::RwLock;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::mc::direction::Direction;
 use crate::mc::resource::{ResourcePath, ResourceProvider};
-use crate::render::atlas::Atlas;
+use crate::render::atlas::{Atlas, AtlasError};
 use crate::texture::UV;
 
 /// A block position: x, y, z
@@ -71,63 +75,139 @@ pub struct BlockModelFace {
     pub animation_uv_offset: u32,
 }
 
-fn recurse_model_parents(
-    model: &schemas::Model,
-    resource_provider: &dyn ResourceProvider,
-    models: &mut Vec<ResourcePath>,
-) {
-    match &model.parent {
-        Some(parent_path_string) => {
-            let parent_path: ResourcePath = ResourcePath::from(parent_path_string)
-                .prepend("models/")
-                .append(".json");
-            recurse_model_parents(
-                &serde_json::from_str(
-                    &resource_provider
-                        .get_string(&parent_path)
-                        .expect(&parent_path.0),
-                )
-                .unwrap(),
-                resource_provider,
-                models,
-            );
-            models.push(parent_path);
-        }
-        None => {}
+impl BlockModelFace {
+    /// Whether the middle of the face's UVs is within `region` of the atlas
+    fn is_within(&self, region: &UV) -> bool {
+        let (u, v) = self.vertices.iter().fold((0, 0), |(u, v), vertex| {
+            (
+                u + vertex.tex_coords[0] as u32,
+                v + vertex.tex_coords[1] as u32,
+            )
+        });
+        let (u, v) = ((u / 4) as u16, (v / 4) as u16);
+        let ((u1, v1), (u2, v2)) = *region;
+
+        (u1..=u2).contains(&u) && (v1..=v2).contains(&v)
     }
 }
 
-fn resolve_model(
-    model: schemas::Model,
+/// The texture used by [ModelMesh::missing]
+pub const MISSING_TEXTURE: &str = "wgpu_mc:missing";
+
+/// Read and parse a JSON resource, such as a block model or blockstate definition
+pub fn load_json<T: serde::de::DeserializeOwned>(
+    path: &ResourcePath,
     resource_provider: &dyn ResourceProvider,
-) -> schemas::Model {
-    if model.parent.is_none() {
-        return model;
+) -> Result<T, MeshBakeError> {
+    let string = resource_provider
+        .get_string(path)
+        .ok_or_else(|| MeshBakeError::UnresolvedResourcePath(path.clone()))?;
+
+    serde_json::from_str(&string).map_err(|source| MeshBakeError::JsonError {
+        path: path.clone(),
+        line: source.line(),
+        column: source.column(),
+        source,
+    })
+}
+
+/// Parsed and resolved block models, shared by the blocks baked together so that every model (and especially common
+/// parents like `block/cube_all`) is only read and parsed once. Multipart blocks keep it for the states they bake later.
+#[derive(Debug, Default)]
+pub struct ModelCache {
+    parsed: RwLock<HashMap<ResourcePath, Arc<schemas::Model>>>,
+    resolved: RwLock<HashMap<ResourcePath, Arc<schemas::Model>>>,
+}
+
+impl ModelCache {
+    /// Get the model at `path` as it is defined in its JSON file
+    pub fn get(
+        &self,
+        path: &ResourcePath,
+        resource_provider: &dyn ResourceProvider,
+    ) -> Result<Arc<schemas::Model>, MeshBakeError> {
+        if let Some(model) = self.parsed.read().get(path) {
+            return Ok(model.clone());
+        }
+
+        let model = Arc::new(load_json::<schemas::Model>(path, resource_provider)?);
+        self.parsed.write().insert(path.clone(), model.clone());
+        Ok(model)
     }
 
-    let mut parent_paths = Vec::new();
-    recurse_model_parents(&model, resource_provider, &mut parent_paths);
+    /// Get the model at `path` merged with all of its parents, with texture references resolved
+    pub fn resolve(
+        &self,
+        path: &ResourcePath,
+        resource_provider: &dyn ResourceProvider,
+    ) -> Result<Arc<schemas::Model>, MeshBakeError> {
+        if let Some(model) = self.resolved.read().get(path) {
+            return Ok(model.clone());
+        }
 
-    let parents: Vec<schemas::Model> = parent_paths
-        .iter()
-        .map(|parent_path| {
-            serde_json::from_str(&resource_provider.get_string(parent_path).unwrap()).unwrap()
-        })
-        .collect();
+        let model = self.get(path, resource_provider)?;
 
-    let mut schema = ModelResolver::resolve_model([&model].into_iter().chain(parents.iter()));
+        let resolved = if model.parent.is_none() {
+            model
+        } else {
+            Arc::new(self.resolve_parents(path, &model, resource_provider)?)
+        };
 
-    if let Some(textures) = &mut schema.textures {
-        let copy = textures.clone();
+        self.resolved.write().insert(path.clone(), resolved.clone());
+        Ok(resolved)
+    }
 
-        textures.iter_mut().for_each(|(_key, texture)| {
-            if texture.reference().is_some() {
-                texture.0 = texture.resolve(&copy).unwrap().to_string();
+    fn resolve_parents(
+        &self,
+        path: &ResourcePath,
+        model: &schemas::Model,
+        resource_provider: &dyn ResourceProvider,
+    ) -> Result<schemas::Model, MeshBakeError> {
+        //Walk up the parents, keeping track of the chain so that cycles can be reported
+        let mut chain = vec![path.clone()];
+        let mut parents = Vec::new();
+        let mut current = model;
+
+        while let Some(parent_path_string) = &current.parent {
+            let parent_path: ResourcePath = ResourcePath::from(parent_path_string)
+                .prepend("models/")
+                .append(".json");
+
+            let is_cycle = chain.contains(&parent_path);
+            chain.push(parent_path.clone());
+            if is_cycle {
+                return Err(MeshBakeError::CyclicParent(chain));
             }
-        })
-    }
 
-    schema
+            parents.push(self.get(&parent_path, resource_provider)?);
+            current = parents.last().unwrap();
+        }
+
+        let mut schema = ModelResolver::resolve_model(
+            [model]
+                .into_iter()
+                .chain(parents.iter().rev().map(|parent| &**parent)),
+        );
+
+        if let Some(textures) = &mut schema.textures {
+            let copy = textures.clone();
+
+            for (key, texture) in textures.iter_mut() {
+                if texture.reference().is_some() {
+                    texture.0 = texture
+                        .resolve(&copy)
+                        .ok_or_else(|| MeshBakeError::UnresolvedTextureReference {
+                            model: path.clone(),
+                            key: key.clone(),
+                            reference: texture.0.clone(),
+                        })?
+                        .to_string();
+                }
+            }
+        }
+
+        Ok(schema)
+    }
 }
 
 fn get_atlas_uv(face: &schemas::models::ElementFace, block_atlas: &Atlas) -> Option<UV> {
@@ -143,6 +223,7 @@ fn get_atlas_uv(face: &schemas::models::ElementFace, block_atlas: &Atlas) -> Opt
                 90 => ((tw.1 - uv[1], uv[0]), (tw.1 - uv[3], uv[2])),
                 180 => ((tw.0 - uv[0], tw.1 - uv[1]), (tw.0 - uv[2], tw.1 - uv[3])),
                 270 => ((uv[1], tw.0 - uv[0]), (uv[3], tw.0 - uv[2])),
+                //Checked by ModelMesh::bake
                 _ => unreachable!(),
             };
             (
@@ -156,12 +237,29 @@ pub struct RenderSettings {
     pub opaque: bool,
 }
 
-/// TODO: Use actual error handling library
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum MeshBakeError {
-    UnresolvedTextureReference(String),
+    #[error("model {model} has an unresolved texture reference {key}: {reference}")]
+    UnresolvedTextureReference {
+        model: ResourcePath,
+        key: String,
+        reference: String,
+    },
+    #[error("missing resource {0}")]
     UnresolvedResourcePath(ResourcePath),
-    JsonError(serde_json::Error),
+    #[error("invalid JSON in {path} at line {line}, column {column}: {source}")]
+    JsonError {
+        path: ResourcePath,
+        line: usize,
+        column: usize,
+        source: serde_json::Error,
+    },
+    #[error("cyclic model parents: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(" -> "))]
+    CyclicParent(Vec<ResourcePath>),
+    #[error("model {model} has an invalid rotation of {rotation} degrees")]
+    InvalidRotation { model: ResourcePath, rotation: u32 },
+    #[error(transparent)]
+    Atlas(#[from] AtlasError),
 }
 
 /// A block model which has been baked into a mesh and is ready for rendering
@@ -174,15 +272,174 @@ pub struct ModelMesh {
     pub up: Vec<BlockModelFace>,
     pub down: Vec<BlockModelFace>,
     pub any: Vec<BlockModelFace>,
+    /// One bit per [Direction], set if that side of the block is completely covered
     pub cull: u8,
     pub layer: RenderLayer,
 }
 
+/// Allocate the textures of a resolved model in the block atlas, unless they already are
+fn allocate_model_textures(
+    model: &schemas::Model,
+    model_resource_path: &ResourcePath,
+    resource_provider: &dyn ResourceProvider,
+    block_atlas: &Atlas,
+) -> Result<(), MeshBakeError> {
+    if let Some(textures) = &model.textures {
+        //Make sure the textures in the model are fully resolved with no references
+        if let Some((key, reference)) = textures
+            .iter()
+            .find(|(_key, value)| value.reference().is_some())
+        {
+            return Err(MeshBakeError::UnresolvedTextureReference {
+                model: model_resource_path.clone(),
+                key: key.clone(),
+                reference: reference.0.clone(),
+            });
+        }
+
+        let uv_map = block_atlas.uv_map.read();
+
+        let unallocated_textures: Vec<ResourcePath> = textures
+            .iter()
+            .filter_map(|(_, texture)| {
+                let texture_id: ResourcePath = (&texture.0).into();
+                if !uv_map.contains_key(&texture_id) {
+                    //Block UV atlas doesn't contain a texture, so we add it
+                    Some(texture_id)
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        drop(uv_map);
+
+        let unallocated_textures: Vec<(&ResourcePath, Vec<u8>)> = unallocated_textures
+            .iter()
+            .map(|path| {
+                let texture_path = path.prepend("textures/").append(".png");
+                resource_provider
+                    .get_bytes(&texture_path)
+                    .map(|bytes| (path, bytes))
+                    .ok_or(MeshBakeError::UnresolvedResourcePath(texture_path))
+            })
+            .collect::<Result<_, _>>()?;
+
+        if !unallocated_textures.is_empty() {
+            block_atlas.allocate(
+                unallocated_textures
+                    .iter()
+                    .map(|(path, data)| (*path, data)),
+                resource_provider,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
 impl ModelMesh {
+    /// Whether every side of the block is completely covered
+    pub fn is_full_cube(&self) -> bool {
+        self.cull == 0b111111
+    }
+
+    /// Whether this block is a full cube which can't be seen through, such as stone but not glass
+    pub fn is_opaque_full_cube(&self) -> bool {
+        self.is_full_cube() && self.layer == RenderLayer::Solid
+    }
+
+    /// Whether any face is textured from within one of the given atlas regions, such as the old UVs of textures
+    /// moved by a repack (see [crate::render::atlas::AtlasUpload::remapped])
+    pub fn samples_any(&self, regions: &[UV]) -> bool {
+        self.faces()
+            .any(|face| regions.iter().any(|region| face.is_within(region)))
+    }
+
+    /// A copy of this mesh for another atlas. Faces textured from one of `sprites`, the names and UVs of textures in
+    /// the atlas this was baked with, are moved to where `atlas` has the same texture.
+    pub fn moved(&self, sprites: &[(ResourcePath, UV)], atlas: &Atlas) -> Self {
+        let uv_map = atlas.uv_map.read();
+        let animated_texture_offsets = atlas.animated_texture_offsets.read();
+
+        let move_face = |face: &BlockModelFace| {
+            let mut face = *face;
+
+            let Some((path, ((u1, v1), (u2, v2)))) =
+                sprites.iter().find(|(_, uv)| face.is_within(uv))
+            else {
+                return face;
+            };
+            let Some(&((new_u1, new_v1), (new_u2, new_v2))) = uv_map.get(path) else {
+                return face;
+            };
+
+            //The texture may have a different resolution in the new atlas
+            let rescale = |value: u16, min: u16, max: u16, new_min: u16, new_max: u16| {
+                let scaled = (value as i32 - min as i32) * (new_max as i32 - new_min as i32)
+                    / (max as i32 - min as i32).max(1);
+                (new_min as i32 + scaled).clamp(0, u16::MAX as i32) as u16
+            };
+            for vertex in &mut face.vertices {
+                vertex.tex_coords = [
+                    rescale(vertex.tex_coords[0], *u1, *u2, new_u1, new_u2),
+                    rescale(vertex.tex_coords[1], *v1, *v2, new_v1, new_v2),
+                ];
+            }
+            face.animation_uv_offset = animated_texture_offsets.get(path).copied().unwrap_or(0);
+
+            face
+        };
+
+        Self {
+            north: self.north.iter().map(move_face).collect(),
+            south: self.south.iter().map(move_face).collect(),
+            west: self.west.iter().map(move_face).collect(),
+            east: self.east.iter().map(move_face).collect(),
+            up: self.up.iter().map(move_face).collect(),
+            down: self.down.iter().map(move_face).collect(),
+            any: self.any.iter().map(move_face).collect(),
+            cull: self.cull,
+            layer: self.layer,
+        }
+    }
+
+    fn faces(&self) -> impl Iterator<Item = &BlockModelFace> {
+        [
+            &self.north,
+            &self.south,
+            &self.west,
+            &self.east,
+            &self.up,
+            &self.down,
+            &self.any,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Allocate the textures of a model in the block atlas without baking it, so that it can be baked later
+    /// without changing the atlas
+    pub fn allocate_textures(
+        model_properties: &ModelProperties,
+        resource_provider: &dyn ResourceProvider,
+        model_cache: &ModelCache,
+        block_atlas: &Atlas,
+    ) -> Result<(), MeshBakeError> {
+        let model_resource_path = ResourcePath::from(&model_properties.model)
+            .prepend("models/")
+            .append(".json");
+        let model = model_cache.resolve(&model_resource_path, resource_provider)?;
+
+        allocate_model_textures(&model, &model_resource_path, resource_provider, block_atlas)
+    }
+
     pub fn bake<'a>(
         model_properties: impl IntoIterator<Item = &'a ModelProperties>,
         resource_provider: &dyn ResourceProvider,
+        model_cache: &ModelCache,
         block_atlas: &Atlas,
+        layer: RenderLayer,
     ) -> Result<Self, MeshBakeError> {
         let mesh = model_properties
             .into_iter()
@@ -191,70 +448,39 @@ impl ModelMesh {
                     .prepend("models/")
                     .append(".json");
 
+                if let Some(&rotation) = [model_properties.x, model_properties.y]
+                    .iter()
+                    .find(|rotation| !matches!(rotation, 0 | 90 | 180 | 270))
+                {
+                    return Err(MeshBakeError::InvalidRotation {
+                        model: model_resource_path,
+                        rotation,
+                    });
+                }
+
                 //Recursively resolve the model using it's parents if it has any
-                let model: schemas::Model = resolve_model(
-                    //Parse the JSON into the model schema
-                    serde_json::from_str(
-                        //Get the model JSON
-                        &resource_provider
-                            .get_string(&model_resource_path)
-                            .ok_or_else(|| {
-                                MeshBakeError::UnresolvedResourcePath(model_resource_path)
-                            })?,
-                    )
-                    .map_err(MeshBakeError::JsonError)?,
+                let model = model_cache.resolve(&model_resource_path, resource_provider)?;
+
+                if let Some(rotation) = model
+                    .elements
+                    .iter()
+                    .flatten()
+                    .flat_map(|element| element.faces.values())
+                    .map(|face| face.rotation)
+                    .find(|rotation| !matches!(rotation, 0 | 90 | 180 | 270))
+                {
+                    return Err(MeshBakeError::InvalidRotation {
+                        model: model_resource_path,
+                        rotation,
+                    });
+                }
+
+                allocate_model_textures(
+                    &model,
+                    &model_resource_path,
                     resource_provider,
-                );
-                if let Some(textures) = model.textures {
-                    //Make sure the textures in the model are fully resolved with no references
-                    if let Some(reference) = textures
-                        .iter()
-                        .find(|(_key, value)| value.reference().is_some())
-                    {
-                        return Err(MeshBakeError::UnresolvedTextureReference(format!(
-                            "key: {} value: {:?}",
-                            reference.0, reference.1
-                        )));
-                    }
-
-                    let uv_map = block_atlas.uv_map.read();
-
-                    let unallocated_textures: Vec<ResourcePath> = textures
-                        .iter()
-                        .filter_map(|(_, texture)| {
-                            let texture_id: ResourcePath = (&texture.0).into();
-                            if !uv_map.contains_key(&texture_id) {
-                                //Block UV atlas doesn't contain a texture, so we add it
-                                Some(texture_id)
-                            } else {
-                                None
-                            }
-                        })
-                        .collect();
-
-                    drop(uv_map);
-
-                    let unallocated_textures: Vec<(&ResourcePath, Vec<u8>)> = unallocated_textures
-                        .iter()
-                        .map(|path| {
-                            (
-                                path,
-                                resource_provider
-                                    .get_bytes(&path.prepend("textures/").append(".png"))
-                                    .unwrap(),
-                            )
-                        })
-                        .collect();
-
-                    if !unallocated_textures.is_empty() {
-                        block_atlas.allocate(
-                            unallocated_textures
-                                .iter()
-                                .map(|(path, data)| (*path, data)),
-                            resource_provider,
-                        );
-                    }
-                };
+                    block_atlas,
+                )?;
 
                 Ok(model
                     .elements
@@ -401,7 +627,7 @@ impl ModelMesh {
                                 90 => vec3(v.x, 1.0 - v.z, v.y),
                                 180 => vec3(v.x, 1.0 - v.y, 1.0 - v.z),
                                 270 => vec3(v.x, v.z, 1.0 - v.y),
-                                _ => panic!("invalid rotation"),
+                                _ => unreachable!(),
                             };
                             let v = matrix * (v - vec_origin) + vec_origin;
 
@@ -410,7 +636,7 @@ impl ModelMesh {
                                 90 => vec3(1.0 - v.z, v.y, v.x),
                                 180 => vec3(1.0 - v.x, v.y, 1.0 - v.z),
                                 270 => vec3(v.z, v.y, 1.0 - v.x),
-                                _ => panic!("invalid rotation"),
+                                _ => unreachable!(),
                             }
                         };
 
@@ -601,8 +827,137 @@ impl ModelMesh {
             })
             .flatten_ok()
             .collect::<Result<Vec<BlockModelFace>, MeshBakeError>>()?;
+
+        Ok(Self::from_faces(mesh, layer))
+    }
+
+    /// A full cube using the [MISSING_TEXTURE], substituted for models which failed to bake
+    pub fn missing(block_atlas: &Atlas, layer: RenderLayer) -> Self {
+        let missing_texture = ResourcePath::from(MISSING_TEXTURE);
+
+        if !block_atlas.uv_map.read().contains_key(&missing_texture) {
+            let image = image::RgbaImage::from_fn(16, 16, |x, y| {
+                if (x < 8) == (y < 8) {
+                    image::Rgba([248, 0, 248, 255])
+                } else {
+                    image::Rgba([0, 0, 0, 255])
+                }
+            });
+
+            let mut png = Vec::new();
+            image
+                .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+                .unwrap();
+
+            struct NoResources;
+
+            impl ResourceProvider for NoResources {
+                fn get_bytes(&self, _id: &ResourcePath) -> Option<Vec<u8>> {
+                    None
+                }
+            }
+
+            block_atlas
+                .allocate([(&missing_texture, &png)], &NoResources)
+                .expect("the missing texture should fit in the block atlas");
+        }
+
+        let uv = block_atlas.uv_map.read()[&missing_texture];
+
+        Self::cube(uv, layer)
+    }
+
+    /// A full cube with the same texture region on every face
+    pub fn cube(((u1, v1), (u2, v2)): UV, layer: RenderLayer) -> Self {
+        let face = |positions: [Vec3; 4], normal: Vec3| BlockModelFace {
+            vertices: [
+                BlockMeshVertex {
+                    position: positions[0],
+                    tex_coords: [u2, v2],
+                },
+                BlockMeshVertex {
+                    position: positions[1],
+                    tex_coords: [u2, v1],
+                },
+                BlockMeshVertex {
+                    position: positions[2],
+                    tex_coords: [u1, v1],
+                },
+                BlockMeshVertex {
+                    position: positions[3],
+                    tex_coords: [u1, v2],
+                },
+            ],
+            normal,
+            tint_index: -1,
+            animation_uv_offset: 0,
+        };
+
+        Self::from_faces(
+            [
+                face(
+                    [
+                        vec3(1.0, 0.0, 1.0),
+                        vec3(1.0, 1.0, 1.0),
+                        vec3(0.0, 1.0, 1.0),
+                        vec3(0.0, 0.0, 1.0),
+                    ],
+                    vec3(0.0, 0.0, 1.0),
+                ),
+                face(
+                    [
+                        vec3(0.0, 0.0, 1.0),
+                        vec3(0.0, 1.0, 1.0),
+                        vec3(0.0, 1.0, 0.0),
+                        vec3(0.0, 0.0, 0.0),
+                    ],
+                    vec3(-1.0, 0.0, 0.0),
+                ),
+                face(
+                    [
+                        vec3(0.0, 0.0, 0.0),
+                        vec3(0.0, 1.0, 0.0),
+                        vec3(1.0, 1.0, 0.0),
+                        vec3(1.0, 0.0, 0.0),
+                    ],
+                    vec3(0.0, 0.0, -1.0),
+                ),
+                face(
+                    [
+                        vec3(1.0, 0.0, 0.0),
+                        vec3(1.0, 1.0, 0.0),
+                        vec3(1.0, 1.0, 1.0),
+                        vec3(1.0, 0.0, 1.0),
+                    ],
+                    vec3(1.0, 0.0, 0.0),
+                ),
+                face(
+                    [
+                        vec3(0.0, 1.0, 0.0),
+                        vec3(0.0, 1.0, 1.0),
+                        vec3(1.0, 1.0, 1.0),
+                        vec3(1.0, 1.0, 0.0),
+                    ],
+                    vec3(0.0, 1.0, 0.0),
+                ),
+                face(
+                    [
+                        vec3(0.0, 0.0, 0.0),
+                        vec3(1.0, 0.0, 0.0),
+                        vec3(1.0, 0.0, 1.0),
+                        vec3(0.0, 0.0, 1.0),
+                    ],
+                    vec3(0.0, -1.0, 0.0),
+                ),
+            ],
+            layer,
+        )
+    }
+
+    /// Sort faces into the direction they're facing, and figure out which sides are fully covered for culling
+    fn from_faces(faces: impl IntoIterator<Item = BlockModelFace>, layer: RenderLayer) -> Self {
         let mut result = Self {
-            layer: RenderLayer::Solid,
+            layer,
             north: vec![],
             south: vec![],
             west: vec![],
@@ -612,7 +967,7 @@ impl ModelMesh {
             any: vec![],
             cull: 0,
         };
-        mesh.iter().for_each(|face| {
+        faces.into_iter().for_each(|face| {
             let full_face = (face.vertices[0].position.fract() == vec3(0.0, 0.0, 0.0)
                 && face.vertices[1].position.fract() == vec3(0.0, 0.0, 0.0)
                 && face.vertices[2].position.fract() == vec3(0.0, 0.0, 0.0)
@@ -622,42 +977,42 @@ impl ModelMesh {
                 && face.vertices[1].position.x == 0.0
                 && face.vertices[2].position.x == 0.0
             {
-                result.west.push(*face);
+                result.west.push(face);
                 result.cull |= full_face << Direction::West as u8;
             } else if face.vertices[0].position.x == 1.0
                 && face.vertices[1].position.x == 1.0
                 && face.vertices[2].position.x == 1.0
             {
-                result.east.push(*face);
+                result.east.push(face);
                 result.cull |= full_face << Direction::East as u8;
             } else if face.vertices[0].position.y == 0.0
                 && face.vertices[1].position.y == 0.0
                 && face.vertices[2].position.y == 0.0
             {
-                result.down.push(*face);
+                result.down.push(face);
                 result.cull |= full_face << Direction::Down as u8;
             } else if face.vertices[0].position.y == 1.0
                 && face.vertices[1].position.y == 1.0
                 && face.vertices[2].position.y == 1.0
             {
-                result.up.push(*face);
+                result.up.push(face);
                 result.cull |= full_face << Direction::Up as u8;
             } else if face.vertices[0].position.z == 0.0
                 && face.vertices[1].position.z == 0.0
                 && face.vertices[2].position.z == 0.0
             {
-                result.north.push(*face);
+                result.north.push(face);
                 result.cull |= full_face << Direction::North as u8;
             } else if face.vertices[0].position.z == 1.0
                 && face.vertices[1].position.z == 1.0
                 && face.vertices[2].position.z == 1.0
             {
-                result.south.push(*face);
+                result.south.push(face);
                 result.cull |= full_face << Direction::South as u8;
             } else {
-                result.any.push(*face);
+                result.any.push(face);
             }
         });
-        Ok(result)
+        result
     }
 }
@@ -7,16 +7,16 @@
 //! Minecraft splits chunks into 16-block tall pieces called chunk sections, for
 //! rendering purposes.
 use arrayvec::ArrayVec;
-use glam::{ivec3, vec3, IVec2, IVec3, Vec3Swizzles};
+use glam::{ivec3, vec3, IVec2, IVec3, Vec3, Vec3Swizzles};
 use range_alloc::RangeAllocator;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
 use std::ops::{Not, Range};
 use std::sync::Arc;
 
 use crate::mc::block::{BlockModelFace, ChunkBlockState, ModelMesh};
 use crate::mc::direction::Direction;
-use crate::mc::BlockManager;
+use crate::mc::{position_seed, BlockManager};
 use crate::render::pipeline::Vertex;
 use crate::WmRenderer;
 
@@ -65,29 +65,100 @@ pub enum RenderLayer {
     Transparent = 2,
 }
 
+impl RenderLayer {
+    pub const ALL: [RenderLayer; 3] = [
+        RenderLayer::Solid,
+        RenderLayer::Cutout,
+        RenderLayer::Transparent,
+    ];
+}
+
+/// Where a baked layer lives in the chunk buffers. Ranges are in u32s, relative to the start of the page.
 #[derive(Clone)]
 pub struct SectionRanges {
+    /// The chunk buffer page holding both the vertices and the indices of the layer
+    pub page: usize,
     pub vertex_range: Range<u32>,
     pub index_range: Range<u32>,
 }
 
+/// How much of the chunk buffers is in use, in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    pub sections: usize,
+    pub pages: usize,
+    pub capacity: u64,
+    pub allocated: u64,
+    pub free: u64,
+    /// The largest allocation which would currently fit without adding a page
+    pub largest_free_range: u64,
+    /// Number of separate free ranges across all pages, higher means more fragmented
+    pub free_ranges: usize,
+}
+
+impl AllocationStats {
+    /// The share of free space which is unusable for an allocation of `largest_free_range` bytes, from 0 to 1
+    pub fn fragmentation(&self) -> f32 {
+        if self.free == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_range as f32 / self.free as f32
+        }
+    }
+}
+
 ///The struct representing a Chunk section, with various render layers, split into sections
+///
+/// Geometry is allocated from pages of equal size, each backed by its own chunk buffer on the GPU.
+/// A new page is added whenever a layer doesn't fit into any of the existing ones.
 pub struct SectionStorage {
     storage: HashMap<IVec3, Section>,
-    allocator: RangeAllocator<u32>,
+    pages: Vec<RangeAllocator<u32>>,
+    /// In u32s
+    page_size: u32,
     width: i32,
+    /// Camera section and the block the camera was in when translucent geometry was last sorted
+    last_translucency_sort: Option<(IVec2, IVec3)>,
+    /// Camera section the visible set was found from, or None for the visible set if the camera section isn't loaded
+    visible_sections: Option<(IVec3, Option<HashSet<IVec3>>)>,
+    /// Sections whose geometry is out of date and have to be baked again, see [SectionStorage::take_rebake_queue]
+    rebake_queue: HashSet<IVec3>,
 }
 impl SectionStorage {
-    pub fn new(range: u32) -> Self {
+    /// `page_size` is in u32s
+    pub fn new(page_size: u32) -> Self {
         SectionStorage {
             storage: HashMap::new(),
             width: 0,
-            allocator: RangeAllocator::new(0..range),
+            pages: vec![RangeAllocator::new(0..page_size)],
+            page_size,
+            last_translucency_sort: None,
+            visible_sections: None,
+            rebake_queue: HashSet::new(),
         }
     }
+    /// Removes every section and every page but the first
     pub fn clear(&mut self) {
-        self.allocator.reset();
+        self.pages.truncate(1);
+        self.pages[0].reset();
+        self.storage.clear();
+        self.visible_sections = None;
+        self.rebake_queue.clear();
+    }
+    /// Removes every section and re-creates the pages with a new size, in u32s
+    pub fn set_page_size(&mut self, page_size: u32) {
         self.storage.clear();
+        self.visible_sections = None;
+        self.rebake_queue.clear();
+        self.pages = vec![RangeAllocator::new(0..page_size)];
+        self.page_size = page_size;
+    }
+    /// In u32s
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
     }
     pub fn set_width(&mut self, w: i32) {
         self.width = w;
@@ -99,42 +170,94 @@ impl SectionStorage {
             let radius = self.width + 2; //temp fix until proper sync
             if dist.x > radius || dist.y > radius {
                 to_remove.push(*k);
-                for layer in &section.layers {
-                    if let Some(l) = layer.as_ref() {
-                        self.allocator.free_range(l.vertex_range.clone());
-                        self.allocator.free_range(l.index_range.clone());
-                    }
-                }
+                Self::free_section(&mut self.pages, section);
             }
         }
         to_remove.iter().for_each(|pos| {
             self.storage.remove(pos);
+            self.rebake_queue.remove(pos);
         });
+        if !to_remove.is_empty() {
+            self.visible_sections = None;
+        }
+
+        //Give trailing pages which emptied out back, the first one always stays around
+        while self.pages.len() > 1 && self.pages.last().unwrap().is_empty() {
+            self.pages.pop();
+        }
     }
-    pub fn replace(&mut self, pos: IVec3, baked_layers: &Vec<BakedLayer>) -> Section {
-        if let Some(previous_section) = self.storage.get(&pos) {
-            for layer in &previous_section.layers {
-                if let Some(l) = layer.as_ref() {
-                    self.allocator.free_range(l.vertex_range.clone());
-                    self.allocator.free_range(l.index_range.clone());
+    fn free_section(pages: &mut [RangeAllocator<u32>], section: &Section) {
+        for l in section.layers.iter().flatten() {
+            pages[l.page].free_range(l.vertex_range.clone());
+            pages[l.page].free_range(l.index_range.clone());
+        }
+    }
+    /// Allocate the vertices and indices of a layer from the same page, adding a page if none of them have room
+    fn allocate(&mut self, vertices: u32, indices: u32) -> Option<SectionRanges> {
+        let try_page = |page: &mut RangeAllocator<u32>| {
+            let vertex_range = page.allocate_range(vertices).ok()?;
+            match page.allocate_range(indices) {
+                Ok(index_range) => Some((vertex_range, index_range)),
+                Err(_) => {
+                    page.free_range(vertex_range);
+                    None
                 }
             }
+        };
+
+        let existing = self
+            .pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(page, allocator)| Some((page, try_page(allocator)?)));
+
+        let (page, (vertex_range, index_range)) = match existing {
+            Some(allocation) => allocation,
+            None => {
+                let mut allocator = RangeAllocator::new(0..self.page_size);
+                let Some(ranges) = try_page(&mut allocator) else {
+                    log::error!(
+                        "Section layer of {} bytes doesn't fit into a chunk buffer page of {} bytes",
+                        (vertices + indices) as u64 * 4,
+                        self.page_size as u64 * 4
+                    );
+                    return None;
+                };
+                self.pages.push(allocator);
+                (self.pages.len() - 1, ranges)
+            }
+        };
+
+        Some(SectionRanges {
+            page,
+            vertex_range,
+            index_range,
+        })
+    }
+    pub fn replace(&mut self, pos: IVec3, baked_section: &BakedSection) -> Section {
+        self.rebake_queue.remove(&pos);
+        if let Some(previous_section) = self.storage.get(&pos) {
+            Self::free_section(&mut self.pages, previous_section);
         }
+        self.visible_sections = None;
         let section = Section {
-            layers: baked_layers
+            translucent_quads: Arc::new(
+                baked_section
+                    .layers
+                    .get(RenderLayer::Transparent as usize)
+                    .map_or_else(Vec::new, |layer| layer.quad_centers.clone()),
+            ),
+            needs_translucency_sort: true,
+            visibility: baked_section.visibility,
+            layers: baked_section
+                .layers
                 .iter()
                 .map(|layer| {
                     if !layer.indices.is_empty() {
-                        Some(SectionRanges {
-                            vertex_range: self
-                                .allocator
-                                .allocate_range(layer.vertices.len() as u32 / 4)
-                                .unwrap(),
-                            index_range: self
-                                .allocator
-                                .allocate_range(layer.indices.len() as u32 / 4)
-                                .unwrap(),
-                        })
+                        self.allocate(
+                            layer.vertices.len() as u32 / 4,
+                            layer.indices.len() as u32 / 4,
+                        )
                     } else {
                         None
                     }
@@ -144,14 +267,255 @@ impl SectionStorage {
         self.storage.insert(pos, section.clone());
         section
     }
+    pub fn stats(&self) -> AllocationStats {
+        let mut stats = AllocationStats {
+            sections: self.storage.len(),
+            pages: self.pages.len(),
+            capacity: self.pages.len() as u64 * self.page_size as u64 * 4,
+            ..Default::default()
+        };
+
+        for page in &self.pages {
+            //The gaps between allocated ranges are the free ranges
+            let mut free_start = 0;
+            for allocated in page
+                .allocated_ranges()
+                .chain(std::iter::once(self.page_size..self.page_size))
+            {
+                let free = (allocated.start - free_start) as u64 * 4;
+                if free > 0 {
+                    stats.free += free;
+                    stats.free_ranges += 1;
+                    stats.largest_free_range = stats.largest_free_range.max(free);
+                }
+                stats.allocated += (allocated.end - allocated.start) as u64 * 4;
+                free_start = allocated.end;
+            }
+        }
+
+        stats
+    }
+    pub fn contains(&self, pos: IVec3) -> bool {
+        self.storage.contains_key(&pos)
+    }
+
     pub fn iter(&self) -> std::collections::hash_map::Iter<IVec3, Section> {
         self.storage.iter()
     }
+
+    /// Mark a section as needing to be baked again. It keeps its current geometry until it is
+    pub fn queue_rebake(&mut self, pos: IVec3) {
+        self.rebake_queue.insert(pos);
+    }
+
+    /// Mark every loaded section as needing to be baked again
+    pub fn queue_rebake_all(&mut self) {
+        self.rebake_queue.extend(self.storage.keys());
+    }
+
+    /// The sections which were marked as needing to be baked again since the last call. Whoever owns the block
+    /// data of the world has to bake them with [bake_section]
+    pub fn take_rebake_queue(&mut self) -> Vec<IVec3> {
+        self.rebake_queue.drain().collect()
+    }
+
+    /// Find the sections which can be seen from `camera_section` by a breadth-first search through the
+    /// [SectionVisibility] of each section, like vanilla's chunk occlusion culling. The search never turns back towards
+    /// the camera, and only crosses a section if the face it entered through can see the face it leaves through.
+    ///
+    /// The result is kept until the camera moves into another section or sections are added or removed.
+    pub fn update_visibility(&mut self, camera_section: IVec3) {
+        if matches!(&self.visible_sections, Some((pos, _)) if *pos == camera_section) {
+            return;
+        }
+
+        //Without a starting point there's nothing to search from, so nothing gets culled
+        if !self.storage.contains_key(&camera_section) {
+            self.visible_sections = Some((camera_section, None));
+            return;
+        }
+
+        let mut visible = HashSet::from([camera_section]);
+        //Section, the face it was entered through, and every direction travelled to reach it
+        let mut queue = VecDeque::from([(camera_section, None::<Direction>, 0u8)]);
+
+        while let Some((pos, entered_through, travelled)) = queue.pop_front() {
+            let visibility = self.storage[&pos].visibility;
+
+            for dir in Direction::ALL {
+                if travelled & (1 << dir.opposite() as u8) != 0 {
+                    continue;
+                }
+
+                if let Some(from) = entered_through {
+                    if !visibility.is_visible_through(from, dir) {
+                        continue;
+                    }
+                }
+
+                let next = pos + dir.to_vec();
+                if self.storage.contains_key(&next) && visible.insert(next) {
+                    queue.push_back((next, Some(dir.opposite()), travelled | (1 << dir as u8)));
+                }
+            }
+        }
+
+        self.visible_sections = Some((camera_section, Some(visible)));
+    }
+
+    /// Whether a section was found to be visible by the last [SectionStorage::update_visibility]
+    pub fn is_visible(&self, pos: IVec3) -> bool {
+        match &self.visible_sections {
+            Some((_, Some(visible))) => visible.contains(&pos),
+            _ => true,
+        }
+    }
+
+    /// Re-orders the indices of every section's [RenderLayer::Transparent] layer back-to-front relative to the camera.
+    ///
+    /// `camera_offset` is the camera position relative to the origin of the camera section, which is the space
+    /// terrain is rendered in. Sections are only re-sorted if they were rebaked or if the camera moved into another block.
+    /// Returns the page and offset (in u32s) of each index range which changed along with the new index data.
+    pub fn sort_translucent(
+        &mut self,
+        camera_section_pos: IVec2,
+        camera_offset: Vec3,
+    ) -> Vec<(usize, u32, Vec<u8>)> {
+        let camera_block = camera_offset.floor().as_ivec3();
+        let camera_moved = self.last_translucency_sort != Some((camera_section_pos, camera_block));
+        self.last_translucency_sort = Some((camera_section_pos, camera_block));
+
+        self.storage
+            .iter_mut()
+            .filter_map(|(pos, section)| {
+                if !(camera_moved || section.needs_translucency_sort) {
+                    return None;
+                }
+                section.needs_translucency_sort = false;
+
+                let ranges = section
+                    .layers
+                    .get(RenderLayer::Transparent as usize)?
+                    .as_ref()?;
+
+                let section_origin = Vec3::new(
+                    ((pos.x - camera_section_pos.x) * 16) as f32,
+                    (pos.y * 16) as f32,
+                    ((pos.z - camera_section_pos.y) * 16) as f32,
+                );
+
+                Some((
+                    ranges.page,
+                    ranges.index_range.start,
+                    sort_quad_indices(&section.translucent_quads, camera_offset - section_origin),
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Generate indices for the provided quads, ordered from furthest to nearest to `camera`.
+pub fn sort_quad_indices(quad_centers: &[Vec3], camera: Vec3) -> Vec<u8> {
+    let mut order: Vec<(f32, u32)> = quad_centers
+        .iter()
+        .enumerate()
+        .map(|(index, center)| (center.distance_squared(camera), index as u32))
+        .collect();
+
+    order.sort_unstable_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    order
+        .iter()
+        .flat_map(|(_, quad)| QUAD_INDICES.iter().map(move |index| index + quad * 4))
+        .flat_map(u32::to_ne_bytes)
+        .collect()
+}
+
+/// Which faces of a section can see each other through the blocks in it, one bit per pair of [Direction]s
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SectionVisibility(u64);
+
+impl SectionVisibility {
+    pub const ALL: Self = Self((1 << 36) - 1);
+    pub const NONE: Self = Self(0);
+
+    /// Sections with fewer opaque blocks than this can't separate any of their faces
+    const MIN_OPAQUE_BLOCKS: usize = 256;
+
+    fn bit(from: Direction, to: Direction) -> u64 {
+        1 << (from as u32 * 6 + to as u32)
+    }
+
+    pub fn connect(&mut self, a: Direction, b: Direction) {
+        self.0 |= Self::bit(a, b) | Self::bit(b, a);
+    }
+
+    pub fn is_visible_through(&self, from: Direction, to: Direction) -> bool {
+        self.0 & Self::bit(from, to) != 0
+    }
+
+    /// Flood fill the non-opaque blocks of a section, connecting every pair of faces touched by the same region.
+    /// `opaque` is indexed by `x | z << 4 | y << 8`.
+    pub fn from_opaque_blocks(opaque: &[bool; SECTION_VOLUME]) -> Self {
+        if opaque.iter().filter(|opaque| **opaque).count() < Self::MIN_OPAQUE_BLOCKS {
+            return Self::ALL;
+        }
+
+        let mut visibility = Self::NONE;
+        let mut visited = [false; SECTION_VOLUME];
+        let mut stack = Vec::new();
+
+        for start in 0..SECTION_VOLUME {
+            if opaque[start] || visited[start] {
+                continue;
+            }
+
+            visited[start] = true;
+            stack.push(start);
+            let mut faces = 0u8;
+
+            while let Some(index) = stack.pop() {
+                let pos = ivec3(
+                    (index & 15) as i32,
+                    (index >> 8) as i32,
+                    ((index >> 4) & 15) as i32,
+                );
+
+                for dir in Direction::ALL {
+                    let next = pos + dir.to_vec();
+                    if next.cmplt(IVec3::ZERO).any() || next.cmpge(IVec3::splat(16)).any() {
+                        faces |= 1 << dir as u8;
+                        continue;
+                    }
+
+                    let next_index = (next.x | (next.z << 4) | (next.y << 8)) as usize;
+                    if !opaque[next_index] && !visited[next_index] {
+                        visited[next_index] = true;
+                        stack.push(next_index);
+                    }
+                }
+            }
+
+            for a in Direction::ALL {
+                for b in Direction::ALL {
+                    if faces & (1 << a as u8) != 0 && faces & (1 << b as u8) != 0 {
+                        visibility.connect(a, b);
+                    }
+                }
+            }
+        }
+
+        visibility
+    }
 }
 
 #[derive(Clone)]
 pub struct Section {
     pub layers: Vec<Option<SectionRanges>>,
+    pub visibility: SectionVisibility,
+    /// Centers of the quads in the [RenderLayer::Transparent] layer, relative to the section origin
+    pub translucent_quads: Arc<Vec<Vec3>>,
+    needs_translucency_sort: bool,
 }
 
 impl Default for Section {
@@ -162,12 +526,21 @@ impl Default for Section {
 
 impl Section {
     pub fn new() -> Self {
-        Self { layers: Vec::new() }
+        Self {
+            layers: Vec::new(),
+            visibility: SectionVisibility::ALL,
+            translucent_quads: Arc::new(Vec::new()),
+            needs_translucency_sort: false,
+        }
     }
 }
 
 #[inline]
-fn get_block(block_manager: &BlockManager, state: ChunkBlockState) -> Option<Arc<ModelMesh>> {
+fn get_block(
+    block_manager: &BlockManager,
+    state: ChunkBlockState,
+    world_pos: IVec3,
+) -> Option<Arc<ModelMesh>> {
     let key = match state {
         ChunkBlockState::Air => return None,
         ChunkBlockState::State(key) => key,
@@ -177,36 +550,442 @@ fn get_block(block_manager: &BlockManager, state: ChunkBlockState) -> Option<Arc
         .blocks
         .get_index(key.block as usize)?
         .1
-        .get_model(key.augment, 0)
+        .get_model(key.augment, position_seed(world_pos))
+}
+
+/// Options controlling how [bake_section] meshes a section
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BakeOptions {
+    /// Merge adjacent coplanar faces which share a texture, color, light level and AO into larger quads.
+    /// The texture is repeated across merged quads by the terrain shaders
+    pub greedy_meshing: bool,
+    pub lighting: LightingMode,
+}
+
+/// How light levels and ambient occlusion are computed for the vertices of a section
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LightingMode {
+    /// Counts solid neighbours for AO, which the terrain shaders darken vertices with
+    #[default]
+    Simple,
+    /// Vanilla's smooth lighting, including its corner occlusion rules and the shading of each face direction.
+    /// Brightness is baked into the vertex color instead of the AO value
+    Vanilla,
+}
+
+/// Vanilla's directional shading, which makes the sides of blocks darker than the top
+fn face_shade(dir: Direction) -> f32 {
+    match dir {
+        Direction::Down => 0.5,
+        Direction::Up => 1.0,
+        Direction::North | Direction::South => 0.8,
+        Direction::West | Direction::East => 0.6,
+    }
 }
 
-pub fn bake_section<Provider: BlockStateProvider>(pos: IVec3, wm: &WmRenderer, bsp: &Provider) {
-    let bm = wm.mc.block_manager.read();
+/// Multiply the RGB channels of a vertex color
+fn shade_color(color: u32, brightness: f32) -> u32 {
+    let channel =
+        |shift: u32| ((((color >> shift) & 0xff) as f32 * brightness).round() as u32) << shift;
 
-    let baked_section = bake_layers(pos, &bm, bsp);
+    channel(0) | channel(8) | channel(16) | (color & 0xff000000)
+}
+
+/// Which blocks are full cubes, for a section and the two blocks around it which smooth lighting samples
+struct BlockShapes {
+    flags: Vec<u8>,
+}
+
+impl BlockShapes {
+    const FULL_CUBE: u8 = 1;
+    const OPAQUE: u8 = 2;
+    /// From -2 to 17 on every axis
+    const SIZE: i32 = 20;
+
+    fn new<Provider: BlockStateProvider>(
+        block_manager: &BlockManager,
+        state_provider: &Provider,
+        section_offset: IVec3,
+    ) -> Self {
+        let flags = (0..Self::SIZE * Self::SIZE * Self::SIZE)
+            .map(|index| {
+                let pos = ivec3(
+                    index % Self::SIZE,
+                    index / (Self::SIZE * Self::SIZE),
+                    (index / Self::SIZE) % Self::SIZE,
+                ) - 2;
+
+                match get_block(
+                    block_manager,
+                    state_provider.get_state(pos),
+                    pos + section_offset,
+                ) {
+                    Some(mesh) if mesh.is_opaque_full_cube() => Self::FULL_CUBE | Self::OPAQUE,
+                    Some(mesh) if mesh.is_full_cube() => Self::FULL_CUBE,
+                    _ => 0,
+                }
+            })
+            .collect();
+
+        Self { flags }
+    }
+
+    fn get(&self, pos: IVec3) -> u8 {
+        let pos = pos + 2;
+
+        if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(IVec3::splat(Self::SIZE)).any() {
+            return 0;
+        }
+
+        self.flags[(pos.x + pos.z * Self::SIZE + pos.y * Self::SIZE * Self::SIZE) as usize]
+    }
+
+    /// Vanilla's `getAmbientOcclusionLightLevel`
+    fn ao(&self, pos: IVec3) -> f32 {
+        if self.get(pos) & Self::FULL_CUBE != 0 {
+            0.2
+        } else {
+            1.0
+        }
+    }
+
+    fn is_opaque(&self, pos: IVec3) -> bool {
+        self.get(pos) & Self::OPAQUE != 0
+    }
+}
+
+/// Vanilla's smooth lighting for one vertex of a face. Returns the brightness to multiply the vertex color by,
+/// and the light level of the vertex.
+///
+/// Vanilla keeps a quarter of a level of precision when averaging light, which the vertex format can't store,
+/// so it's rounded to the nearest level instead.
+fn smooth_light<Provider: BlockStateProvider>(
+    state_provider: &Provider,
+    shapes: &BlockShapes,
+    pos: IVec3,
+    dir: Direction,
+    on_boundary: bool,
+    vertex: Vec3,
+) -> (f32, LightLevel) {
+    let (_, a, b) = face_axes(dir);
+    let dir_vec = dir.to_vec();
+    let center = if on_boundary { pos + dir_vec } else { pos };
+
+    let side = |axis: usize| {
+        let mut side = IVec3::ZERO;
+        side[axis] = if vertex[axis] < 0.5 { -1 } else { 1 };
+        side
+    };
+
+    //When the corner is hidden, vanilla uses the side along this axis in its place
+    let first_axis = match dir {
+        Direction::Down | Direction::Up | Direction::South => 0,
+        Direction::West | Direction::East | Direction::North => 1,
+    };
+    let (side1, side2) = if first_axis == a {
+        (side(a), side(b))
+    } else {
+        (side(b), side(a))
+    };
+
+    let sample = |pos: IVec3| (shapes.ao(pos), state_provider.get_light_level(pos));
+
+    let (ao1, light1) = sample(center + side1);
+    let (ao2, light2) = sample(center + side2);
+    let (ao3, light3) = if !shapes.is_opaque(center + side1 + dir_vec)
+        || !shapes.is_opaque(center + side2 + dir_vec)
+    {
+        sample(center + side1 + side2)
+    } else {
+        (ao1, light1)
+    };
+
+    let center_ao = shapes.ao(center);
+    let center_light = if on_boundary || !shapes.is_opaque(pos + dir_vec) {
+        state_provider.get_light_level(pos + dir_vec)
+    } else {
+        state_provider.get_light_level(pos)
+    };
+
+    //Unlit neighbours, usually solid blocks, take the light of the center instead of darkening the vertex
+    let lights = [light1, light2, light3, center_light].map(|light| {
+        if light.byte == 0 {
+            center_light
+        } else {
+            light
+        }
+    });
+
+    let sky = lights.iter().map(|light| light.get_sky_level()).sum::<u8>();
+    let block = lights
+        .iter()
+        .map(|light| light.get_block_level())
+        .sum::<u8>();
+
+    (
+        (ao1 + ao2 + ao3 + center_ao) * 0.25 * face_shade(dir),
+        LightLevel::from_sky_and_block((sky + 2) / 4, (block + 2) / 4),
+    )
+}
+
+pub fn bake_section<Provider: BlockStateProvider>(
+    pos: IVec3,
+    wm: &WmRenderer,
+    bsp: &Provider,
+    options: BakeOptions,
+) {
+    //Block models from a reload are used as soon as it happens, see [crate::mc::PendingReload]
+    let baked_section = match &*wm.mc.pending_reload.read() {
+        Some(pending) => bake_layers(pos, &pending.block_manager, bsp, options),
+        None => bake_layers(pos, &wm.mc.block_manager.read(), bsp, options),
+    };
 
     wm.chunk_update_queue.0.send((pos, baked_section)).unwrap();
 }
 
+const QUAD_INDICES: [u32; 6] = [1, 3, 0, 2, 3, 1];
+
+pub struct BakedSection {
+    /// Indexed by [RenderLayer]
+    pub layers: Vec<BakedLayer>,
+    pub visibility: SectionVisibility,
+    /// The [BlockManager::generation] this was baked from
+    pub generation: u64,
+}
+
 #[derive(Clone, Default)]
 pub struct BakedLayer {
     pub vertices: Vec<u8>,
     pub indices: Vec<u8>,
+    /// Only populated for [RenderLayer::Transparent], used for sorting
+    pub quad_centers: Vec<Vec3>,
+}
+
+impl BakedLayer {
+    fn push_quad(&mut self, vertices: [Vertex; 4], layer: RenderLayer) {
+        let vec_index = (self.vertices.len() / Vertex::VERTEX_LENGTH) as u32;
+
+        if layer == RenderLayer::Transparent {
+            self.quad_centers.push(
+                vertices
+                    .iter()
+                    .fold(Vec3::ZERO, |acc, vertex| acc + Vec3::from(vertex.position))
+                    / 4.0,
+            );
+        }
+
+        self.vertices
+            .extend(vertices.into_iter().flat_map(Vertex::compressed));
+        self.indices.extend(
+            QUAD_INDICES
+                .iter()
+                .flat_map(|index| (index + vec_index).to_ne_bytes()),
+        );
+    }
+}
+
+/// The axis a face points along, followed by the two axes spanning its plane
+fn face_axes(dir: Direction) -> (usize, usize, usize) {
+    match dir {
+        Direction::West | Direction::East => (0, 1, 2),
+        Direction::Down | Direction::Up => (1, 0, 2),
+        Direction::North | Direction::South => (2, 0, 1),
+    }
+}
+
+/// Which corner of the texture region each vertex of a quad samples, or None if the UVs aren't a
+/// (possibly rotated or flipped) rectangle
+fn sprite_corners(vertices: &[Vertex; 4]) -> Option<[IVec2; 4]> {
+    let min = vertices.iter().fold([u16::MAX; 2], |acc, v| {
+        [acc[0].min(v.uv[0]), acc[1].min(v.uv[1])]
+    });
+    let max = vertices
+        .iter()
+        .fold([0; 2], |acc, v| [acc[0].max(v.uv[0]), acc[1].max(v.uv[1])]);
+
+    if min[0] == max[0] || min[1] == max[1] {
+        return None;
+    }
+
+    let corners =
+        vertices.map(|v| IVec2::new((v.uv[0] == max[0]) as i32, (v.uv[1] == max[1]) as i32));
+
+    let is_rectangle = vertices.iter().all(|v| {
+        (v.uv[0] == min[0] || v.uv[0] == max[0]) && (v.uv[1] == min[1] || v.uv[1] == max[1])
+    }) && (corners[0] - corners[2]).abs() == IVec2::ONE
+        && (corners[1] - corners[3]).abs() == IVec2::ONE
+        && corners[0] + corners[2] == corners[1] + corners[3];
+
+    is_rectangle.then_some(corners)
+}
+
+/// A face covering a whole side of a block, which greedy meshing may merge with its neighbours
+#[derive(Clone, Copy)]
+struct MergeableFace {
+    vertices: [Vertex; 4],
+    /// Vertex positions within the block
+    corners: [Vec3; 4],
+}
+
+impl MergeableFace {
+    fn new(face: &BlockModelFace, vertices: [Vertex; 4], dir: Direction) -> Option<Self> {
+        let (normal_axis, a, b) = face_axes(dir);
+        let corners = face.vertices.map(|vertex| vertex.position);
+        let plane = corners.map(|corner| IVec2::new(corner[a] as i32, corner[b] as i32));
+
+        let full_face = corners.iter().all(|corner| {
+            corner[normal_axis] == corners[0][normal_axis]
+                && (corner[a] == 0.0 || corner[a] == 1.0)
+                && (corner[b] == 0.0 || corner[b] == 1.0)
+        }) && (plane[0] - plane[2]).abs() == IVec2::ONE
+            && (plane[1] - plane[3]).abs() == IVec2::ONE
+            && (plane[1] - plane[0]).abs().element_sum() == 1;
+
+        let uniform = vertices.iter().all(|vertex| {
+            vertex.lightmap_coords == vertices[0].lightmap_coords
+                && vertex.ao == vertices[0].ao
+                && vertex.color == vertices[0].color
+        });
+
+        (full_face && uniform && sprite_corners(&vertices).is_some())
+            .then_some(Self { vertices, corners })
+    }
+
+    fn can_merge(&self, other: &Self) -> bool {
+        self.corners == other.corners
+            && self
+                .vertices
+                .iter()
+                .zip(other.vertices.iter())
+                .all(|(a, b)| {
+                    a.uv == b.uv
+                        && a.color == b.color
+                        && a.uv_offset == b.uv_offset
+                        && a.lightmap_coords == b.lightmap_coords
+                        && a.ao == b.ao
+                })
+    }
+
+    /// Stretch this face over `width` by `height` blocks, repeating the texture once per block
+    fn merged(&self, dir: Direction, width: i32, height: i32) -> [Vertex; 4] {
+        let (_, a, b) = face_axes(dir);
+        let mut vertices = self.vertices;
+
+        for (vertex, corner) in vertices.iter_mut().zip(self.corners) {
+            if corner[a] == 1.0 {
+                vertex.position[a] += (width - 1) as f32;
+            }
+            if corner[b] == 1.0 {
+                vertex.position[b] += (height - 1) as f32;
+            }
+        }
+
+        if width == 1 && height == 1 {
+            return vertices;
+        }
+
+        let sprite = sprite_corners(&self.vertices).unwrap();
+        let plane = |position: [f32; 3]| IVec2::new(position[a] as i32, position[b] as i32);
+
+        //The UV corners change by f1 along edge 0-1 and by f3 along edge 0-3, which are one block long
+        let e1 = plane(self.corners[1].to_array()) - plane(self.corners[0].to_array());
+        let e3 = plane(self.corners[3].to_array()) - plane(self.corners[0].to_array());
+        let f1 = sprite[1] - sprite[0];
+        let f3 = sprite[3] - sprite[0];
+
+        let tiles = vertices.map(|vertex| {
+            let d = plane(vertex.position) - plane(vertices[0].position);
+            sprite[0] + d.dot(e1) * f1 + d.dot(e3) * f3
+        });
+        let min = tiles.iter().fold(IVec2::MAX, |acc, tile| acc.min(*tile));
+
+        for (vertex, tile) in vertices.iter_mut().zip(tiles) {
+            let tile = tile - min;
+            vertex.tile = [tile.x as u8, tile.y as u8];
+        }
+
+        vertices
+    }
+}
+
+/// Faces of one render layer lying in the same plane of a section, indexed by their position within it
+struct MergePlane {
+    dir: Direction,
+    layer: RenderLayer,
+    cells: Vec<Option<MergeableFace>>,
+}
+
+impl MergePlane {
+    /// Greedily merge runs of identical faces into rectangles, row by row
+    fn bake(mut self, layers: &mut [BakedLayer]) {
+        for y in 0..16 {
+            for x in 0..16 {
+                let Some(face) = self.cells[y * 16 + x].take() else {
+                    continue;
+                };
+
+                let mergeable = |cell: &Option<MergeableFace>| {
+                    cell.as_ref().is_some_and(|other| face.can_merge(other))
+                };
+
+                let mut width = 1;
+                while x + width < 16 && mergeable(&self.cells[y * 16 + x + width]) {
+                    width += 1;
+                }
+
+                let mut height = 1;
+                while y + height < 16
+                    && (x..x + width).all(|x| mergeable(&self.cells[(y + height) * 16 + x]))
+                {
+                    height += 1;
+                }
+
+                for row in y..y + height {
+                    self.cells[row * 16 + x..row * 16 + x + width].fill(None);
+                }
+
+                layers[self.layer as usize].push_quad(
+                    face.merged(self.dir, width as i32, height as i32),
+                    self.layer,
+                );
+            }
+        }
+    }
 }
 
 fn bake_layers<Provider: BlockStateProvider>(
     section_pos: IVec3,
     block_manager: &BlockManager,
     state_provider: &Provider,
-) -> Vec<BakedLayer> {
-    let mut layers = vec![BakedLayer::default(); 3];
+    options: BakeOptions,
+) -> BakedSection {
+    let mut layers = vec![BakedLayer::default(); RenderLayer::ALL.len()];
 
     let section_offset = 16 * section_pos;
 
     if state_provider.is_section_empty(ivec3(0, 0, 0)) {
-        return layers;
+        return BakedSection {
+            layers,
+            visibility: SectionVisibility::ALL,
+            generation: block_manager.generation,
+        };
     }
 
+    //Same layout as the block indices below
+    let mut opaque = Box::new([false; SECTION_VOLUME]);
+
+    let shapes = match options.lighting {
+        LightingMode::Simple => None,
+        LightingMode::Vanilla => Some(BlockShapes::new(
+            block_manager,
+            state_provider,
+            section_offset,
+        )),
+    };
+
+    //Keyed by direction, render layer, and the depth of the plane, so the output is deterministic
+    let mut merge_planes: BTreeMap<(u8, u8, i32, u32), MergePlane> = BTreeMap::new();
+
     for block_index in 0..16 * 16 * 16 {
         let pos = ivec3(block_index & 15, block_index >> 8, (block_index & 255) >> 4);
 
@@ -214,124 +993,162 @@ fn bake_layers<Provider: BlockStateProvider>(
 
         let block_state: ChunkBlockState = state_provider.get_state(pos);
 
-        if let Some(model_mesh) = get_block(block_manager, block_state) {
-            const INDICES: [u32; 6] = [1, 3, 0, 2, 3, 1];
-            let mut add_quad =
-                |face: &BlockModelFace, _light_level: LightLevel, dir: Direction, color: u32| {
-                    let baked_layer = &mut layers[RenderLayer::Solid as usize];
-                    let vec_index = baked_layer.vertices.len() / Vertex::VERTEX_LENGTH;
-
-                    let dir_vec = dir.to_vec();
-
-                    baked_layer.vertices.extend(
-                        (0..4)
-                            .map(|vert_index| {
-                                let model_vertex = face.vertices[vert_index as usize];
-
-                                let (b1, b2, b3, light_level) = if model_mesh.any.is_empty() {
-                                    let vertex_biases = ivec3(
-                                        if model_vertex.position.x as i32 == 0 {
-                                            -1
-                                        } else {
-                                            1
-                                        },
-                                        if model_vertex.position.y as i32 == 0 {
-                                            -1
-                                        } else {
-                                            1
-                                        },
-                                        if model_vertex.position.z as i32 == 0 {
-                                            -1
-                                        } else {
-                                            1
-                                        },
-                                    );
-
-                                    let axis = dir_vec - vertex_biases; //equivalent to -(vertex_biases - dir_vec)
-
-                                    let mut axes: ArrayVec<IVec3, 2> = ArrayVec::new_const();
-
-                                    if axis.x != 0 {
-                                        axes.push(ivec3(axis.x, 0, 0));
-                                    }
-
-                                    if axis.y != 0 {
-                                        axes.push(ivec3(0, axis.y, 0));
-                                    }
-
-                                    if axis.z != 0 {
-                                        axes.push(ivec3(0, 0, axis.z));
-                                    }
-
-                                    let p1 = vertex_biases + pos;
-                                    let p2 = p1 + axes[0];
-                                    let p3 = p1 + axes[1];
-
-                                    let b1 = state_provider.get_state(p1).is_air().not() as u8;
-                                    let b2 = state_provider.get_state(p2).is_air().not() as u8;
-                                    let b3 = state_provider.get_state(p3).is_air().not() as u8;
-
-                                    let l1 = state_provider.get_light_level(p1);
-                                    let l2 = state_provider.get_light_level(p2);
-                                    let l3 = state_provider.get_light_level(p3);
-                                    let l4 = state_provider.get_light_level(pos + dir_vec);
-
-                                    let average_sky = ((l1.get_sky_level()
-                                        + l2.get_sky_level()
-                                        + l3.get_sky_level()
-                                        + l4.get_sky_level())
-                                        as f32
-                                        / 4.0)
-                                        as u8;
-                                    let average_block = ((l1.get_block_level()
-                                        + l2.get_block_level()
-                                        + l3.get_block_level()
-                                        + l4.get_block_level())
-                                        as f32
-                                        / 4.0)
-                                        as u8;
-
-                                    let light_level =
-                                        LightLevel::from_sky_and_block(average_sky, average_block);
-
-                                    (b1, b2, b3, light_level)
-                                } else {
-                                    (0, 0, 0, state_provider.get_light_level(pos))
-                                };
-
-                                Vertex {
-                                    position: [
-                                        fpos.x + model_vertex.position[0],
-                                        fpos.y + model_vertex.position[1],
-                                        fpos.z + model_vertex.position[2],
-                                    ],
-                                    uv: model_vertex.tex_coords,
-                                    normal: face.normal.to_array(),
-                                    color,
-                                    uv_offset: 0,
-                                    lightmap_coords: light_level.byte,
-                                    ao: 3 - (b1 + b2 + b3),
-                                }
-                            })
-                            .flat_map(Vertex::compressed),
-                    );
-                    baked_layer.indices.extend(
-                        INDICES
-                            .iter()
-                            .flat_map(|index| (index + (vec_index as u32)).to_ne_bytes()),
-                    );
-                };
+        if let Some(model_mesh) = get_block(block_manager, block_state, pos + section_offset) {
+            let layer = model_mesh.layer;
+            opaque[block_index as usize] = model_mesh.is_opaque_full_cube();
+
+            let mut add_quad = |face: &BlockModelFace,
+                                _light_level: LightLevel,
+                                dir: Direction,
+                                color: u32,
+                                greedy: bool| {
+                let dir_vec = dir.to_vec();
+
+                let (normal_axis, _, _) = face_axes(dir);
+                let boundary = if dir_vec[normal_axis] > 0 { 1.0 } else { 0.0 };
+                let on_boundary = face
+                    .vertices
+                    .iter()
+                    .all(|vertex| vertex.position[normal_axis] == boundary);
+
+                let vertices: [Vertex; 4] = std::array::from_fn(|vert_index| {
+                    let model_vertex = face.vertices[vert_index];
+
+                    let (color, ao, light_level) = if let (true, Some(shapes)) =
+                        (model_mesh.any.is_empty(), &shapes)
+                    {
+                        let (brightness, light_level) = smooth_light(
+                            state_provider,
+                            shapes,
+                            pos,
+                            dir,
+                            on_boundary,
+                            model_vertex.position,
+                        );
+
+                        (shade_color(color, brightness), 3, light_level)
+                    } else if model_mesh.any.is_empty() {
+                        let vertex_biases = ivec3(
+                            if model_vertex.position.x as i32 == 0 {
+                                -1
+                            } else {
+                                1
+                            },
+                            if model_vertex.position.y as i32 == 0 {
+                                -1
+                            } else {
+                                1
+                            },
+                            if model_vertex.position.z as i32 == 0 {
+                                -1
+                            } else {
+                                1
+                            },
+                        );
 
-            let mut add_face = |face: &BlockModelFace, dir: Direction| {
-                let color = if face.tint_index != -1 {
+                        let axis = dir_vec - vertex_biases; //equivalent to -(vertex_biases - dir_vec)
+
+                        let mut axes: ArrayVec<IVec3, 2> = ArrayVec::new_const();
+
+                        if axis.x != 0 {
+                            axes.push(ivec3(axis.x, 0, 0));
+                        }
+
+                        if axis.y != 0 {
+                            axes.push(ivec3(0, axis.y, 0));
+                        }
+
+                        if axis.z != 0 {
+                            axes.push(ivec3(0, 0, axis.z));
+                        }
+
+                        let p1 = vertex_biases + pos;
+                        let p2 = p1 + axes[0];
+                        let p3 = p1 + axes[1];
+
+                        let b1 = state_provider.get_state(p1).is_air().not() as u8;
+                        let b2 = state_provider.get_state(p2).is_air().not() as u8;
+                        let b3 = state_provider.get_state(p3).is_air().not() as u8;
+
+                        let l1 = state_provider.get_light_level(p1);
+                        let l2 = state_provider.get_light_level(p2);
+                        let l3 = state_provider.get_light_level(p3);
+                        let l4 = state_provider.get_light_level(pos + dir_vec);
+
+                        let average_sky = ((l1.get_sky_level()
+                            + l2.get_sky_level()
+                            + l3.get_sky_level()
+                            + l4.get_sky_level()) as f32
+                            / 4.0) as u8;
+                        let average_block = ((l1.get_block_level()
+                            + l2.get_block_level()
+                            + l3.get_block_level()
+                            + l4.get_block_level())
+                            as f32
+                            / 4.0) as u8;
+
+                        let light_level =
+                            LightLevel::from_sky_and_block(average_sky, average_block);
+
+                        (color, 3 - (b1 + b2 + b3), light_level)
+                    } else {
+                        (color, 3, state_provider.get_light_level(pos))
+                    };
+
+                    Vertex {
+                        position: [
+                            fpos.x + model_vertex.position[0],
+                            fpos.y + model_vertex.position[1],
+                            fpos.z + model_vertex.position[2],
+                        ],
+                        uv: model_vertex.tex_coords,
+                        normal: face.normal.to_array(),
+                        color,
+                        uv_offset: face.animation_uv_offset,
+                        lightmap_coords: light_level.byte,
+                        ao,
+                        tile: [0, 0],
+                    }
+                });
+
+                if greedy {
+                    if let Some(face) = MergeableFace::new(face, vertices, dir) {
+                        let (normal_axis, a, b) = face_axes(dir);
+
+                        let plane = merge_planes
+                            .entry((
+                                dir as u8,
+                                layer as u8,
+                                pos[normal_axis],
+                                face.corners[0][normal_axis].to_bits(),
+                            ))
+                            .or_insert_with(|| MergePlane {
+                                dir,
+                                layer,
+                                cells: vec![None; 16 * 16],
+                            });
+
+                        plane.cells[(pos[b] * 16 + pos[a]) as usize] = Some(face);
+                        return;
+                    }
+                }
+
+                layers[layer as usize].push_quad(vertices, layer);
+            };
+
+            let mut add_face = |face: &BlockModelFace, dir: Direction, greedy: bool| {
+                let color = if face.tint_index >= 0 {
                     state_provider.get_block_color(pos + section_offset, face.tint_index)
                 } else {
                     0xffffffff
                 };
 
-                let cull = if let Some(mesh) =
-                    get_block(block_manager, state_provider.get_state(pos + dir.to_vec()))
-                {
+                let neighbor_pos = pos + dir.to_vec();
+                let cull = if let Some(mesh) = get_block(
+                    block_manager,
+                    state_provider.get_state(neighbor_pos),
+                    neighbor_pos + section_offset,
+                ) {
                     (mesh.cull >> dir.opposite() as u8) & 1 == 1
                 } else {
                     false
@@ -340,40 +1157,443 @@ fn bake_layers<Provider: BlockStateProvider>(
                 if !cull {
                     let light_level: LightLevel =
                         state_provider.get_light_level(pos + dir.to_vec());
-                    add_quad(face, light_level, dir, color);
+                    add_quad(face, light_level, dir, color, greedy);
                 }
             };
 
-            model_mesh.west.iter().for_each(|face| {
-                add_face(face, Direction::West);
-            });
-            model_mesh.east.iter().for_each(|face| {
-                add_face(face, Direction::East);
-            });
-            model_mesh.down.iter().for_each(|face| {
-                add_face(face, Direction::Down);
-            });
-            model_mesh.up.iter().for_each(|face| {
-                add_face(face, Direction::Up);
-            });
-            model_mesh.north.iter().for_each(|face| {
-                add_face(face, Direction::North);
-            });
-            model_mesh.south.iter().for_each(|face| {
-                add_face(face, Direction::South);
-            });
+            for (faces, dir) in [
+                (&model_mesh.west, Direction::West),
+                (&model_mesh.east, Direction::East),
+                (&model_mesh.down, Direction::Down),
+                (&model_mesh.up, Direction::Up),
+                (&model_mesh.north, Direction::North),
+                (&model_mesh.south, Direction::South),
+            ] {
+                //Overlapping faces (like the grass block's side overlay) must keep their draw order
+                let greedy =
+                    options.greedy_meshing && model_mesh.any.is_empty() && faces.len() == 1;
+
+                faces.iter().for_each(|face| {
+                    add_face(face, dir, greedy);
+                });
+            }
             model_mesh.any.iter().for_each(|face| {
                 let light_level: LightLevel = state_provider.get_light_level(pos);
 
-                let color = if face.tint_index != -1 {
+                let color = if face.tint_index >= 0 {
                     state_provider.get_block_color(pos + section_offset, face.tint_index)
                 } else {
                     0xffffffff
                 };
 
-                add_quad(face, light_level, Direction::Up, color);
+                add_quad(face, light_level, Direction::Up, color, false);
             });
         }
     }
-    layers
+
+    merge_planes
+        .into_values()
+        .for_each(|plane| plane.bake(&mut layers));
+
+    BakedSection {
+        layers,
+        visibility: SectionVisibility::from_opaque_blocks(&opaque),
+        generation: block_manager.generation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mc::block::BlockstateKey;
+    use crate::mc::{Block, WeightedModel};
+    use indexmap::IndexMap;
+
+    /// A section where every block at y = 0 comes from `floor`, and everything else is air
+    struct FlatWorld<F: Fn(IVec3) -> ChunkBlockState>(F);
+
+    impl<F: Fn(IVec3) -> ChunkBlockState> BlockStateProvider for FlatWorld<F> {
+        fn get_state(&self, pos: IVec3) -> ChunkBlockState {
+            if pos.y == 0 && pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(16)).all() {
+                (self.0)(pos)
+            } else {
+                ChunkBlockState::Air
+            }
+        }
+
+        fn get_light_level(&self, _pos: IVec3) -> LightLevel {
+            LightLevel::from_sky_and_block(15, 0)
+        }
+
+        fn is_section_empty(&self, _rel_pos: IVec3) -> bool {
+            false
+        }
+
+        fn get_block_color(&self, _pos: IVec3, _tint_index: i32) -> u32 {
+            0xffffffff
+        }
+    }
+
+    /// Two full cube blocks with different textures
+    fn block_manager() -> BlockManager {
+        let block = |u: u16| {
+            let mesh = Arc::new(ModelMesh::cube(((u, 0), (u + 16, 16)), RenderLayer::Solid));
+            Block::Variants(IndexMap::from([(
+                vec![],
+                vec![WeightedModel { mesh, weight: 1 }],
+            )]))
+        };
+
+        BlockManager {
+            blocks: IndexMap::from([
+                ("minecraft:stone".into(), block(0)),
+                ("minecraft:dirt".into(), block(16)),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    fn state(block: u16) -> ChunkBlockState {
+        ChunkBlockState::State(BlockstateKey { block, augment: 0 })
+    }
+
+    fn vertex_count(layers: &[BakedLayer]) -> usize {
+        layers
+            .iter()
+            .map(|layer| layer.vertices.len() / Vertex::VERTEX_LENGTH)
+            .sum()
+    }
+
+    #[test]
+    fn greedy_flat_world() {
+        let block_manager = block_manager();
+        let world = FlatWorld(|_| state(0));
+
+        let simple = bake_layers(IVec3::ZERO, &block_manager, &world, BakeOptions::default());
+        let greedy = bake_layers(
+            IVec3::ZERO,
+            &block_manager,
+            &world,
+            BakeOptions {
+                greedy_meshing: true,
+                ..Default::default()
+            },
+        );
+
+        //Top and bottom of every block, plus the sides along the edges of the section
+        assert_eq!(vertex_count(&simple.layers), (256 + 256 + 4 * 16) * 4);
+        //One quad per side of the slab
+        assert_eq!(vertex_count(&greedy.layers), 6 * 4);
+        assert_eq!(
+            greedy.layers[RenderLayer::Solid as usize].indices.len(),
+            6 * 6 * 4
+        );
+
+        //The top and bottom repeat the texture across the whole section
+        let max_tile = greedy.layers[RenderLayer::Solid as usize]
+            .vertices
+            .chunks(Vertex::VERTEX_LENGTH)
+            .flat_map(|vertex| [vertex[14], vertex[15]])
+            .max();
+        assert_eq!(max_tile, Some(16));
+    }
+
+    #[test]
+    fn greedy_flat_world_keeps_textures_apart() {
+        let block_manager = block_manager();
+        let world = FlatWorld(|pos| state((pos.x >= 8) as u16));
+
+        let simple = bake_layers(IVec3::ZERO, &block_manager, &world, BakeOptions::default());
+        let greedy = bake_layers(
+            IVec3::ZERO,
+            &block_manager,
+            &world,
+            BakeOptions {
+                greedy_meshing: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(vertex_count(&simple.layers), (256 + 256 + 4 * 16) * 4);
+        //Top, bottom, north and south are split in half, west and east are a single texture
+        assert_eq!(vertex_count(&greedy.layers), (4 * 2 + 2) * 4);
+    }
+
+    /// Stone blocks at the given positions, with sky light everywhere except inside blocks unless `light` says otherwise
+    struct World<L: Fn(IVec3) -> Option<LightLevel>> {
+        blocks: Vec<IVec3>,
+        light: L,
+    }
+
+    impl<L: Fn(IVec3) -> Option<LightLevel>> BlockStateProvider for World<L> {
+        fn get_state(&self, pos: IVec3) -> ChunkBlockState {
+            if self.blocks.contains(&pos) {
+                state(0)
+            } else {
+                ChunkBlockState::Air
+            }
+        }
+
+        fn get_light_level(&self, pos: IVec3) -> LightLevel {
+            (self.light)(pos).unwrap_or(if self.blocks.contains(&pos) {
+                LightLevel::from_sky_and_block(0, 0)
+            } else {
+                LightLevel::from_sky_and_block(15, 0)
+            })
+        }
+
+        fn is_section_empty(&self, _rel_pos: IVec3) -> bool {
+            false
+        }
+
+        fn get_block_color(&self, _pos: IVec3, _tint_index: i32) -> u32 {
+            0xffffffff
+        }
+    }
+
+    /// Position, normal bits, red channel, AO and light of every vertex
+    fn decode(layers: &[BakedLayer]) -> Vec<(Vec3, u8, u8, u8, u8)> {
+        layers[RenderLayer::Solid as usize]
+            .vertices
+            .chunks(Vertex::VERTEX_LENGTH)
+            .map(|vertex| {
+                let position = vec3(vertex[0] as f32, vertex[1] as f32, vertex[2] as f32) / 16.0;
+                (
+                    position,
+                    (vertex[11] >> 2) & 0b111,
+                    vertex[3],
+                    vertex[13],
+                    vertex[12],
+                )
+            })
+            .collect()
+    }
+
+    const UP: u8 = 0b001;
+    const DOWN: u8 = 0b101;
+    const EAST: u8 = 0b000;
+    const NORTH: u8 = 0b110;
+
+    fn bake_vanilla(world: &impl BlockStateProvider) -> Vec<(Vec3, u8, u8, u8, u8)> {
+        decode(
+            &bake_layers(
+                IVec3::ZERO,
+                &block_manager(),
+                world,
+                BakeOptions {
+                    lighting: LightingMode::Vanilla,
+                    ..Default::default()
+                },
+            )
+            .layers,
+        )
+    }
+
+    #[test]
+    fn vanilla_lighting_face_shading() {
+        let vertices = bake_vanilla(&World {
+            blocks: vec![ivec3(8, 8, 8)],
+            light: |_| None,
+        });
+
+        assert_eq!(vertices.len(), 6 * 4);
+
+        for (_, normal, red, ao, light) in vertices {
+            let expected = match normal {
+                UP => 255,
+                DOWN => 128,
+                NORTH | 0b010 => 204,
+                _ => 153,
+            };
+
+            assert_eq!(red, expected, "normal {normal:03b}");
+            assert_eq!(ao, 3);
+            assert_eq!(light, 0xf0);
+        }
+    }
+
+    #[test]
+    fn vanilla_lighting_occlusion_next_to_wall() {
+        //A floor, with a wall along the east side of the column x = 8
+        let mut blocks: Vec<IVec3> = (0..16 * 16).map(|i| ivec3(i & 15, 0, i >> 4)).collect();
+        blocks.extend((0..16).map(|z| ivec3(9, 1, z)));
+
+        let vertices = bake_vanilla(&World {
+            blocks,
+            light: |_| None,
+        });
+
+        let top_of = |x: f32, z: f32| {
+            vertices
+                .iter()
+                .filter(move |(position, normal, ..)| {
+                    *normal == UP && position.y == 1.0 && position.x == x && position.z == z
+                })
+                .map(|&(_, _, red, _, light)| (red, light))
+                .collect::<Vec<_>>()
+        };
+
+        //Against the wall, a side and the corner are full cubes: (0.2 + 1.0 + 0.2 + 1.0) / 4.
+        //The wall is unlit, but that doesn't darken the floor next to it
+        assert_eq!(top_of(9.0, 8.0), vec![(153, 0xf0); 2]);
+        //One block away from the wall nothing is occluded, for any of the four floor blocks sharing the vertex
+        assert_eq!(top_of(8.0, 8.0), vec![(255, 0xf0); 4]);
+    }
+
+    #[test]
+    fn vanilla_lighting_averages_light() {
+        let vertices = bake_vanilla(&World {
+            blocks: vec![ivec3(8, 8, 8)],
+            light: |pos: IVec3| {
+                let block = match pos.to_array() {
+                    //Around the top face's vertex at (8, 9, 8)
+                    [8, 9, 8] | [7, 9, 7] => 4,
+                    [7, 9, 8] | [8, 9, 7] => 3,
+                    //Around the east face's vertex at (9, 9, 9)
+                    [9, 8, 8] => 6,
+                    [9, 9, 8] => 0,
+                    [9, 8, 9] => 5,
+                    [9, 9, 9] => 4,
+                    _ => return None,
+                };
+
+                Some(LightLevel::from_sky_and_block(0, block))
+            },
+        });
+
+        let light_at = |normal: u8, position: Vec3| {
+            vertices
+                .iter()
+                .find(|vertex| vertex.1 == normal && vertex.0 == position)
+                .unwrap()
+                .4
+        };
+
+        //(4 + 3 + 3 + 4) / 4 = 3.5
+        assert_eq!(
+            light_at(UP, vec3(8.0, 9.0, 8.0)),
+            LightLevel::from_sky_and_block(0, 4).byte
+        );
+        //The unlit sample takes the center's light: (6 + 6 + 5 + 4) / 4 = 5.25
+        assert_eq!(
+            light_at(EAST, vec3(9.0, 9.0, 9.0)),
+            LightLevel::from_sky_and_block(0, 5).byte
+        );
+    }
+
+    fn layer(vertex_bytes: usize, index_bytes: usize) -> BakedLayer {
+        BakedLayer {
+            vertices: vec![0; vertex_bytes],
+            indices: vec![0; index_bytes],
+            quad_centers: Vec::new(),
+        }
+    }
+
+    fn baked_section(layers: Vec<BakedLayer>) -> BakedSection {
+        BakedSection {
+            layers,
+            visibility: SectionVisibility::ALL,
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn section_storage_adds_pages_when_full() {
+        let mut storage = SectionStorage::new(100);
+
+        let first = storage.replace(IVec3::ZERO, &baked_section(vec![layer(200, 100)]));
+        let second = storage.replace(IVec3::X, &baked_section(vec![layer(200, 100)]));
+        let third = storage.replace(IVec3::Z, &baked_section(vec![layer(32, 8)]));
+
+        let page = |section: &Section| section.layers[0].as_ref().unwrap().page;
+        assert_eq!(page(&first), 0);
+        assert_eq!(page(&second), 1);
+        //Leftover space in the first page gets reused
+        assert_eq!(page(&third), 0);
+
+        let stats = storage.stats();
+        assert_eq!(stats.sections, 3);
+        assert_eq!(stats.pages, 2);
+        assert_eq!(stats.capacity, 800);
+        assert_eq!(stats.allocated, 640);
+        assert_eq!(stats.free, 160);
+        assert_eq!(stats.largest_free_range, 100);
+    }
+
+    #[test]
+    fn section_storage_skips_layers_larger_than_a_page() {
+        let mut storage = SectionStorage::new(100);
+
+        let section = storage.replace(
+            IVec3::ZERO,
+            &baked_section(vec![layer(400, 100), layer(16, 8)]),
+        );
+
+        assert!(section.layers[0].is_none());
+        assert!(section.layers[1].is_some());
+        assert_eq!(storage.stats().pages, 1);
+    }
+
+    #[test]
+    fn section_storage_trim_releases_pages() {
+        let mut storage = SectionStorage::new(100);
+        storage.set_width(0);
+
+        storage.replace(IVec3::ZERO, &baked_section(vec![layer(200, 100)]));
+        storage.replace(ivec3(10, 0, 0), &baked_section(vec![layer(200, 100)]));
+        assert_eq!(storage.page_count(), 2);
+
+        storage.trim(IVec2::ZERO);
+
+        let stats = storage.stats();
+        assert_eq!(stats.sections, 1);
+        assert_eq!(stats.pages, 1);
+        assert_eq!(stats.allocated, 300);
+    }
+
+    #[test]
+    fn section_visibility_split_by_floor() {
+        let mut opaque = [false; SECTION_VOLUME];
+        opaque[8 << 8..9 << 8].fill(true);
+
+        let visibility = SectionVisibility::from_opaque_blocks(&opaque);
+
+        assert!(!visibility.is_visible_through(Direction::Down, Direction::Up));
+        assert!(visibility.is_visible_through(Direction::West, Direction::East));
+        assert!(visibility.is_visible_through(Direction::Up, Direction::North));
+        assert!(visibility.is_visible_through(Direction::Down, Direction::South));
+    }
+
+    #[test]
+    fn section_visibility_sparse_blocks_hide_nothing() {
+        let mut opaque = [false; SECTION_VOLUME];
+        opaque[..255].fill(true);
+
+        assert_eq!(
+            SectionVisibility::from_opaque_blocks(&opaque),
+            SectionVisibility::ALL
+        );
+    }
+
+    #[test]
+    fn occluded_sections_are_culled() {
+        let mut storage = SectionStorage::new(100);
+        let solid = BakedSection {
+            layers: Vec::new(),
+            visibility: SectionVisibility::NONE,
+            generation: 0,
+        };
+
+        storage.replace(IVec3::ZERO, &baked_section(Vec::new()));
+        storage.replace(IVec3::X, &solid);
+        storage.replace(ivec3(2, 0, 0), &baked_section(Vec::new()));
+
+        storage.update_visibility(IVec3::ZERO);
+        assert!(storage.is_visible(IVec3::X));
+        assert!(!storage.is_visible(ivec3(2, 0, 0)));
+
+        //Nothing is culled when the camera is outside of the loaded sections
+        storage.update_visibility(ivec3(0, 30, 0));
+        assert!(storage.is_visible(ivec3(2, 0, 0)));
+    }
 }
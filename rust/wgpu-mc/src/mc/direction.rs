@@ -18,6 +18,15 @@ pub enum Direction {
     South = 5,
 }
 impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::West,
+        Direction::East,
+        Direction::Down,
+        Direction::Up,
+        Direction::North,
+        Direction::South,
+    ];
+
     pub fn to_vec(&self) -> IVec3 {
         VECTOR[*self as usize]
     }
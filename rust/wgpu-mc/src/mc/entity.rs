@@ -26,9 +26,8 @@ pub struct EntityManager {
 impl EntityManager {
     pub fn new(wgpu_state: &Display) -> Self {
         Self {
-            mob_texture_atlas: RwLock::new(Atlas::new(wgpu_state, false)),
-            //TODO: support resizing the atlas
-            player_texture_atlas: RwLock::new(Atlas::new(wgpu_state, false)),
+            mob_texture_atlas: RwLock::new(Atlas::new(wgpu_state, true)),
+            player_texture_atlas: RwLock::new(Atlas::new(wgpu_state, true)),
             entity_types: RwLock::new(Vec::new()),
             entity_vertex_buffers: Default::default(),
         }
@@ -1,26 +1,33 @@
 //! Rust implementations of minecraft concepts that are important to us.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use arc_swap::ArcSwap;
-use chunk::SectionStorage;
-use glam::{ivec2, IVec2};
+use chunk::{BakedSection, RenderLayer, SectionStorage, CHUNK_HEIGHT, CHUNK_SECTION_HEIGHT};
+use glam::IVec3;
 use indexmap::map::IndexMap;
+use itertools::Itertools;
 use minecraft_assets::schemas;
 use minecraft_assets::schemas::blockstates::multipart::StateValue;
 use parking_lot::{Mutex, RwLock};
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 use crate::mc::entity::{BundledEntityInstances, Entity};
 use crate::mc::resource::ResourceProvider;
 use crate::render::atlas::{Atlas, TextureManager};
+use crate::render::camera::Camera;
 use crate::render::pipeline::BLOCK_ATLAS;
+use crate::render::terrain::TerrainDrawBuffers;
+use crate::texture::UV;
 use crate::util::BindableBuffer;
 use crate::{Display, WmRenderer};
 
-use self::block::ModelMesh;
+use self::biome::BiomeColors;
+use self::block::{load_json, MeshBakeError, ModelCache, ModelMesh};
 use self::resource::ResourcePath;
 
+pub mod biome;
 pub mod block;
 pub mod chunk;
 pub mod direction;
@@ -30,57 +37,184 @@ pub mod resource;
 /// for example, `minecraft:anvil[facing=north]` or `Block{minecraft:anvil}[facing=north]`
 pub type BlockVariantFormatter = dyn Fn(&str, Option<&str>) -> String;
 
+#[derive(Default)]
 pub struct BlockManager {
     /// This maps block state keys to either a [VariantMesh] or a [Multipart] struct. How the keys are formatted
     /// is defined by the user of wgpu-mc. For example `Block{minecraft:anvil}[facing=west]` or `minecraft:anvil#facing=west`
     pub blocks: IndexMap<String, Block>,
+    /// The blockstates each block was baked from by [MinecraftState::bake_blocks], so that
+    /// [WmRenderer::reload_resources] can bake them again
+    pub blockstates: HashMap<String, ResourcePath>,
+    /// The render layer each block was baked into, given to [MinecraftState::bake_blocks]
+    pub layers: HashMap<String, RenderLayer>,
+    /// Incremented by every [WmRenderer::reload_resources]. Sections baked from an older generation use textures
+    /// from an atlas which has since been replaced
+    pub generation: u64,
+}
+
+impl BlockManager {
+    /// Eagerly bake every reachable state of the given multipart blocks in parallel, so that
+    /// [Block::get_model_by_key] doesn't have to bake them lazily on whichever thread first encounters them.
+    /// Blocks which aren't multipart blocks are ignored. Textures allocated while baking aren't uploaded until
+    /// [MinecraftState::upload_block_atlas].
+    pub fn bake_multiparts<'a>(
+        &self,
+        domains: impl IntoIterator<Item = (&'a str, &'a PropertyDomain)>,
+        resource_provider: &dyn ResourceProvider,
+        block_atlas: &Atlas,
+    ) -> Vec<(String, MeshBakeError)> {
+        let multiparts = domains
+            .into_iter()
+            .filter_map(|(block_name, domain)| match self.blocks.get(block_name)? {
+                Block::Multipart(multipart) => Some((block_name, multipart, domain)),
+                Block::Variants(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        multiparts
+            .par_iter()
+            .flat_map_iter(|(block_name, multipart, domain)| {
+                multipart
+                    .bake_combinations(domain, resource_provider, block_atlas)
+                    .into_iter()
+                    .map(|error| (block_name.to_string(), error))
+            })
+            .collect()
+    }
+}
+
+/// One of the models a blockstate variant can randomly pick from
+#[derive(Debug)]
+pub struct WeightedModel {
+    pub mesh: Arc<ModelMesh>,
+    pub weight: u32,
+}
+
+/// The seed vanilla uses to pick a random model variant for the block at `pos` (`MathHelper.hashCode`)
+pub fn position_seed(pos: IVec3) -> i64 {
+    let l = (pos.x.wrapping_mul(3129871) as i64)
+        ^ (pos.z as i64).wrapping_mul(116129781)
+        ^ pos.y as i64;
+    let l = l
+        .wrapping_mul(l)
+        .wrapping_mul(42317861)
+        .wrapping_add(l.wrapping_mul(11));
+    l >> 16
+}
+
+/// Pick a model the same way vanilla's `WeightedBakedModel` does, seeding a `java.util.Random` with `seed`
+fn pick_weighted(models: &[WeightedModel], seed: i64) -> Option<&Arc<ModelMesh>> {
+    if models.len() <= 1 {
+        return models.first().map(|model| &model.mesh);
+    }
+
+    let total_weight: i32 = models.iter().map(|model| model.weight as i32).sum();
+    if total_weight <= 0 {
+        return models.first().map(|model| &model.mesh);
+    }
+
+    const MULTIPLIER: i64 = 0x5DEECE66D;
+    const MASK: i64 = (1 << 48) - 1;
+    let mut state = (seed ^ MULTIPLIER) & MASK;
+    let mut next = || {
+        state = (state.wrapping_mul(MULTIPLIER).wrapping_add(0xB)) & MASK;
+        (state >> 16) as i32
+    };
+    let next_long = ((next() as i64) << 32).wrapping_add(next() as i64);
+
+    let mut choice = (next_long as i32).wrapping_abs() % total_weight;
+    models
+        .iter()
+        .find(|model| {
+            choice -= model.weight as i32;
+            choice < 0
+        })
+        .map(|model| &model.mesh)
 }
 
 #[derive(Debug)]
 pub enum Block {
     Multipart(Multipart),
-    Variants(IndexMap<Vec<(String, StateValue)>, Vec<Arc<ModelMesh>>>),
+    Variants(IndexMap<Vec<(String, StateValue)>, Vec<WeightedModel>>),
 }
 
 impl Block {
-    pub fn get_model(&self, key: u16, _seed: u8) -> Option<Arc<ModelMesh>> {
+    /// Get the model for a blockstate augment. `seed` picks between weighted variants, see [position_seed]
+    pub fn get_model(&self, key: u16, seed: i64) -> Option<Arc<ModelMesh>> {
         Some(match &self {
             Block::Multipart(multipart) => multipart.keys.read().get_index(key as usize)?.1.clone(),
-            //TODO, random variant selection through weight and seed
-            Block::Variants(variants) => variants.get_index(key as usize)?.1[0].clone(),
+            Block::Variants(variants) => {
+                pick_weighted(variants.get_index(key as usize)?.1, seed)?.clone()
+            }
         })
     }
 
+    /// Whether any model this block has baked is textured from within one of the given atlas regions, see
+    /// [ModelMesh::samples_any]
+    pub fn samples_any(&self, regions: &[UV]) -> bool {
+        match self {
+            Block::Multipart(multipart) => multipart
+                .keys
+                .read()
+                .values()
+                .any(|mesh| mesh.samples_any(regions)),
+            Block::Variants(variants) => variants
+                .values()
+                .flatten()
+                .any(|model| model.mesh.samples_any(regions)),
+        }
+    }
+
+    /// A copy of this block for another atlas, see [ModelMesh::moved]
+    pub fn moved(&self, sprites: &[(ResourcePath, UV)], atlas: &Atlas) -> Block {
+        let move_mesh = |mesh: &Arc<ModelMesh>| Arc::new(mesh.moved(sprites, atlas));
+
+        match self {
+            Block::Multipart(multipart) => Block::Multipart(Multipart {
+                cases: multipart.cases.clone(),
+                layer: multipart.layer,
+                keys: RwLock::new(
+                    multipart
+                        .keys
+                        .read()
+                        .iter()
+                        .map(|(key, mesh)| (key.clone(), move_mesh(mesh)))
+                        .collect(),
+                ),
+                model_cache: multipart.model_cache.clone(),
+            }),
+            Block::Variants(variants) => Block::Variants(
+                variants
+                    .iter()
+                    .map(|(key, models)| {
+                        (
+                            key.clone(),
+                            models
+                                .iter()
+                                .map(|model| WeightedModel {
+                                    mesh: move_mesh(&model.mesh),
+                                    weight: model.weight,
+                                })
+                                .collect(),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The mesh for a state of the block. States of multipart blocks which weren't baked yet are baked here, with
+    /// the textures [MinecraftState::bake_blocks] allocated up front so that the block atlas doesn't change.
     pub fn get_model_by_key<'a>(
         &self,
         key: impl IntoIterator<Item = (&'a str, &'a StateValue)> + Clone,
         resource_provider: &dyn ResourceProvider,
         block_atlas: &Atlas,
-        //TODO use this
-        _seed: u8,
+        seed: i64,
     ) -> Option<(Arc<ModelMesh>, u16)> {
         let key_map: HashMap<&str, &StateValue> = key.clone().into_iter().collect();
 
-        let key_string = key
-            .clone()
-            .into_iter()
-            .map(|(key, value)| {
-                format!(
-                    "{}={}",
-                    key,
-                    match value {
-                        StateValue::Bool(bool) =>
-                            if *bool {
-                                "true"
-                            } else {
-                                "false"
-                            },
-                        StateValue::String(string) => string,
-                    }
-                )
-            })
-            .collect::<Vec<String>>()
-            .join(",");
+        let key_string = multipart_key(key.clone());
 
         match &self {
             Block::Multipart(multipart) => {
@@ -90,12 +224,16 @@ impl Block {
                     }
                 }
 
-                let mesh = multipart.generate_mesh(key, resource_provider, block_atlas);
+                let mesh = multipart
+                    .generate_mesh(key, resource_provider, block_atlas)
+                    .unwrap_or_else(|error| {
+                        log::warn!("Failed to bake multipart model {key_string}: {error}");
+                        Arc::new(ModelMesh::missing(block_atlas, multipart.layer))
+                    });
 
-                let mut multipart_write = multipart.keys.write();
-                multipart_write.insert(key_string, mesh.clone());
+                let (index, _) = multipart.keys.write().insert_full(key_string, mesh.clone());
 
-                Some((mesh, multipart_write.len() as u16 - 1))
+                Some((mesh, index as u16))
             }
             Block::Variants(variants) => {
                 let full =
@@ -112,7 +250,7 @@ impl Block {
                             )
                         })?;
 
-                Some((full.1 .1[0].clone(), full.0 as u16))
+                Some((pick_weighted(full.1 .1, seed)?.clone(), full.0 as u16))
             }
         }
     }
@@ -121,9 +259,28 @@ impl Block {
 #[derive(Debug)]
 pub struct Multipart {
     pub cases: Vec<schemas::blockstates::multipart::Case>,
+    pub layer: RenderLayer,
     pub keys: RwLock<IndexMap<String, Arc<ModelMesh>>>,
+    /// The models parsed while the block was baked, so states baked later don't have to parse them again
+    pub model_cache: Arc<ModelCache>,
+}
+
+/// The key a multipart state is stored under in [Multipart::keys]. Properties are sorted so that the order they're
+/// provided in doesn't matter.
+fn multipart_key<'a>(state: impl IntoIterator<Item = (&'a str, &'a StateValue)>) -> String {
+    state
+        .into_iter()
+        .sorted_by_key(|(key, _)| *key)
+        .map(|(key, value)| match value {
+            StateValue::Bool(bool) => format!("{key}={bool}"),
+            StateValue::String(string) => format!("{key}={string}"),
+        })
+        .join(",")
 }
 
+/// Every value each of a block's properties can take, e.g. `[("east", [false, true]), ("north", [false, true])]`
+pub type PropertyDomain = [(String, Vec<StateValue>)];
+
 impl Multipart {
     pub fn generate_mesh<'a>(
         &self,
@@ -131,23 +288,192 @@ impl Multipart {
             + Clone,
         resource_provider: &dyn ResourceProvider,
         block_atlas: &Atlas,
-    ) -> Arc<ModelMesh> {
-        let apply_variants = self.cases.iter().filter_map(|case| {
-            if case.applies(key.clone()) {
-                Some(case.apply.models())
-            } else {
-                None
-            }
-        });
+    ) -> Result<Arc<ModelMesh>, MeshBakeError> {
+        let applied_cases = self.cases.iter().filter(|case| case.applies(key.clone()));
+
+        self.bake_cases(applied_cases, resource_provider, block_atlas)
+    }
 
+    fn bake_cases<'a>(
+        &self,
+        cases: impl IntoIterator<Item = &'a schemas::blockstates::multipart::Case>,
+        resource_provider: &dyn ResourceProvider,
+        block_atlas: &Atlas,
+    ) -> Result<Arc<ModelMesh>, MeshBakeError> {
         let mesh = ModelMesh::bake(
-            apply_variants.into_iter().flatten(),
+            cases.into_iter().flat_map(|case| case.apply.models()),
             resource_provider,
+            &self.model_cache,
             block_atlas,
-        )
-        .unwrap();
+            self.layer,
+        )?;
+
+        Ok(Arc::new(mesh))
+    }
+
+    /// Allocate the textures of every model any case applies, so that states baked later on, possibly lazily by
+    /// [Block::get_model_by_key] on a section baking thread, don't have to change the block atlas
+    pub fn allocate_textures(
+        &self,
+        resource_provider: &dyn ResourceProvider,
+        block_atlas: &Atlas,
+    ) -> Vec<MeshBakeError> {
+        self.cases
+            .iter()
+            .flat_map(|case| case.apply.models())
+            .unique_by(|model_properties| &model_properties.model)
+            .filter_map(|model_properties| {
+                ModelMesh::allocate_textures(
+                    model_properties,
+                    resource_provider,
+                    &self.model_cache,
+                    block_atlas,
+                )
+                .err()
+            })
+            .collect()
+    }
+
+    /// Every combination of the values in `domain`
+    pub fn combinations(domain: &PropertyDomain) -> Vec<Vec<(String, StateValue)>> {
+        domain
+            .iter()
+            .fold(vec![vec![]], |combinations, (property, values)| {
+                combinations
+                    .iter()
+                    .flat_map(|combination| {
+                        values.iter().map(move |value| {
+                            let mut combination = combination.clone();
+                            combination.push((property.clone(), value.clone()));
+                            combination
+                        })
+                    })
+                    .collect()
+            })
+    }
+
+    /// Bake a mesh for every combination of the values in `domain` which isn't already baked.
+    /// Combinations which apply the same set of cases share a mesh, so each distinct mesh is only baked once.
+    pub fn bake_combinations(
+        &self,
+        domain: &PropertyDomain,
+        resource_provider: &dyn ResourceProvider,
+        block_atlas: &Atlas,
+    ) -> Vec<MeshBakeError> {
+        let states = {
+            let keys = self.keys.read();
+
+            Self::combinations(domain)
+                .into_iter()
+                .map(|combination| {
+                    let key = multipart_key(
+                        combination
+                            .iter()
+                            .map(|(property, value)| (&property[..], value)),
+                    );
+                    (key, combination)
+                })
+                .filter(|(key, _)| !keys.contains_key(key))
+                .collect()
+        };
+
+        self.bake_states(states, resource_provider, block_atlas)
+    }
+
+    /// Bake the states `keys` were created for, e.g. by another [Multipart] for the same block, and store them in
+    /// the same order so that they keep the indices they had there
+    pub fn bake_keys<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a String>,
+        resource_provider: &dyn ResourceProvider,
+        block_atlas: &Atlas,
+    ) -> Vec<MeshBakeError> {
+        let states = keys
+            .into_iter()
+            .map(|key| {
+                let state = key
+                    .split(',')
+                    .filter_map(|kv_pair| {
+                        let (property, value) = kv_pair.split_once('=')?;
+
+                        Some((
+                            property.to_string(),
+                            match value {
+                                "true" => StateValue::Bool(true),
+                                "false" => StateValue::Bool(false),
+                                other => StateValue::String(other.into()),
+                            },
+                        ))
+                    })
+                    .collect();
+
+                (key.clone(), state)
+            })
+            .collect();
 
-        Arc::new(mesh)
+        self.bake_states(states, resource_provider, block_atlas)
+    }
+
+    /// Bake and store each state under its key, in the order given, unless the key is already baked
+    fn bake_states(
+        &self,
+        states: Vec<(String, Vec<(String, StateValue)>)>,
+        resource_provider: &dyn ResourceProvider,
+        block_atlas: &Atlas,
+    ) -> Vec<MeshBakeError> {
+        let mut reachable: IndexMap<Vec<bool>, Vec<usize>> = IndexMap::new();
+
+        for (index, (_, state)) in states.iter().enumerate() {
+            let state = state.iter().map(|(property, value)| (&property[..], value));
+
+            let applied = self
+                .cases
+                .iter()
+                .map(|case| case.applies(state.clone()))
+                .collect::<Vec<bool>>();
+
+            reachable.entry(applied).or_default().push(index);
+        }
+
+        let baked = reachable
+            .into_iter()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|(applied, indices)| {
+                let cases = self
+                    .cases
+                    .iter()
+                    .zip(&applied)
+                    .filter_map(|(case, applies)| applies.then_some(case));
+
+                (
+                    indices,
+                    self.bake_cases(cases, resource_provider, block_atlas),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut errors = Vec::new();
+        let mut meshes = vec![None; states.len()];
+
+        for (indices, mesh) in baked {
+            let mesh = mesh.unwrap_or_else(|error| {
+                errors.push(error);
+                Arc::new(ModelMesh::missing(block_atlas, self.layer))
+            });
+
+            for index in indices {
+                meshes[index] = Some(mesh.clone());
+            }
+        }
+
+        let mut multipart_keys = self.keys.write();
+
+        for ((key, _), mesh) in states.into_iter().zip(meshes) {
+            multipart_keys.entry(key).or_insert_with(|| mesh.unwrap());
+        }
+
+        errors
     }
 }
 
@@ -183,10 +509,13 @@ pub struct RenderEffectsData {
 
 pub struct Scene {
     pub section_storage: RwLock<SectionStorage>,
-    pub camera_section_pos: RwLock<IVec2>,
-    pub chunk_buffer: Arc<BindableBuffer>,
+    pub camera: RwLock<Camera>,
+    /// One buffer per page of [SectionStorage], holding both vertices and indices.
+    /// New pages are created by [WmRenderer::submit_chunk_updates] as the storage grows.
+    pub chunk_buffers: RwLock<Vec<Arc<BindableBuffer>>>,
 
-    pub indirect_buffer: Arc<wgpu::Buffer>,
+    /// Indirect draw commands and per-draw data for terrain, refilled every frame
+    pub terrain_draws: Mutex<TerrainDrawBuffers>,
 
     pub entity_instances: Mutex<HashMap<String, BundledEntityInstances>>,
     pub sky_state: SkyState,
@@ -201,26 +530,13 @@ pub struct Scene {
 
 impl Scene {
     pub fn new(wm: &WmRenderer, framebuffer_size: wgpu::Extent3d) -> Self {
-        let indirect_buffer = wm.display.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: 4 * 5 * 10000,
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::INDIRECT,
-            mapped_at_creation: false,
-        });
-        let buffer_size = 100000000u64;
+        let page_size =
+            chunk_buffer_page_size(DEFAULT_RENDER_DISTANCE, &wm.display.device.limits());
         Self {
-            section_storage: RwLock::new(SectionStorage::new((buffer_size / 4) as u32)),
-            camera_section_pos: RwLock::new(ivec2(0, 0)),
-            chunk_buffer: Arc::new(BindableBuffer::new_deferred(
-                wm,
-                buffer_size,
-                wgpu::BufferUsages::COPY_DST
-                    | wgpu::BufferUsages::VERTEX
-                    | wgpu::BufferUsages::STORAGE
-                    | wgpu::BufferUsages::INDEX,
-                "ssbo",
-            )),
-            indirect_buffer: Arc::new(indirect_buffer),
+            section_storage: RwLock::new(SectionStorage::new((page_size / 4) as u32)),
+            camera: RwLock::new(Camera::default()),
+            chunk_buffers: RwLock::new(vec![Arc::new(create_chunk_buffer(wm, page_size))]),
+            terrain_draws: Mutex::new(TerrainDrawBuffers::new(wm, 10000)),
 
             entity_instances: Default::default(),
             sky_state: Default::default(),
@@ -240,6 +556,78 @@ impl Scene {
             }),
         }
     }
+
+    /// Drops every section and sizes the chunk buffer pages for a new render distance, in chunks
+    pub fn set_render_distance(&self, wm: &WmRenderer, render_distance: u32) {
+        let page_size = chunk_buffer_page_size(render_distance, &wm.display.device.limits());
+
+        let mut section_storage = self.section_storage.write();
+        let mut chunk_buffers = self.chunk_buffers.write();
+
+        section_storage.set_width(render_distance as i32);
+
+        if section_storage.page_size() as u64 * 4 == page_size {
+            section_storage.clear();
+            chunk_buffers.truncate(1);
+        } else {
+            section_storage.set_page_size((page_size / 4) as u32);
+            *chunk_buffers = vec![Arc::new(create_chunk_buffer(wm, page_size))];
+        }
+    }
+}
+
+/// The render distance, in chunks, used to size the chunk buffer until [Scene::set_render_distance] is called
+pub const DEFAULT_RENDER_DISTANCE: u32 = 12;
+
+/// Rough size of an average baked section, most of the loaded sections are either air or buried underground
+const AVERAGE_SECTION_SIZE: u64 = 8 * 1024;
+
+/// Large enough for the worst case of a section filled with full cubes
+const MIN_CHUNK_BUFFER_PAGE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Picks a chunk buffer page size, in bytes, big enough to hold every section within `render_distance` chunks in a
+/// single page while staying within the buffer size and storage binding limits of the device
+pub fn chunk_buffer_page_size(render_distance: u32, limits: &wgpu::Limits) -> wgpu::BufferAddress {
+    let diameter = render_distance as u64 * 2 + 1;
+    let sections = diameter * diameter * (CHUNK_HEIGHT / CHUNK_SECTION_HEIGHT) as u64;
+
+    let max_size = limits
+        .max_buffer_size
+        .min(limits.max_storage_buffer_binding_size as u64);
+
+    (sections * AVERAGE_SECTION_SIZE)
+        .max(MIN_CHUNK_BUFFER_PAGE_SIZE)
+        .min(max_size)
+        & !0b11
+}
+
+pub(crate) fn create_chunk_buffer(wm: &WmRenderer, size: wgpu::BufferAddress) -> BindableBuffer {
+    BindableBuffer::new_deferred(
+        wm,
+        size,
+        wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::VERTEX
+            | wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::INDEX,
+        "ssbo",
+    )
+}
+
+/// Block models and a block atlas rebuilt by [WmRenderer::reload_resources] which haven't been swapped in yet.
+/// Sections are baked with them in the meantime and held back, so that every section switches to the new atlas at
+/// the same time as the atlas itself.
+pub struct PendingReload {
+    pub block_manager: BlockManager,
+    pub block_atlas: Atlas,
+    pub(crate) sections: Mutex<PendingSections>,
+}
+
+#[derive(Default)]
+pub(crate) struct PendingSections {
+    /// Sections which haven't been baked with the reloaded resources yet
+    pub waiting: HashSet<IVec3>,
+    /// Sections baked with the reloaded resources, which are uploaded when they're swapped in
+    pub baked: Vec<(IVec3, BakedSection)>,
 }
 
 /// Minecraft-specific state and data structures go in here
@@ -253,6 +641,12 @@ pub struct MinecraftState {
 
     pub animated_block_buffer: ArcSwap<Option<wgpu::Buffer>>,
     pub animated_block_bind_group: ArcSwap<Option<wgpu::BindGroup>>,
+
+    /// Colormaps used to tint blocks by biome, see [BiomeColors::new]
+    pub biome_colors: ArcSwap<BiomeColors>,
+
+    /// The result of a [WmRenderer::reload_resources] waiting for sections to be baked with it
+    pub pending_reload: RwLock<Option<PendingReload>>,
 }
 
 impl MinecraftState {
@@ -263,21 +657,29 @@ impl MinecraftState {
 
             texture_manager: TextureManager::new(wgpu_state),
 
-            block_manager: RwLock::new(BlockManager {
-                blocks: IndexMap::new(),
-            }),
+            block_manager: RwLock::new(BlockManager::default()),
             resource_provider,
 
             animated_block_buffer: ArcSwap::new(Arc::new(None)),
             animated_block_bind_group: ArcSwap::new(Arc::new(None)),
+
+            biome_colors: ArcSwap::new(Arc::new(BiomeColors::default())),
+
+            pending_reload: RwLock::new(None),
         }
     }
 
-    /// Bake blocks from their blockstates
+    /// Bake blocks from their blockstates into the given render layer. Block models don't say which layer a block
+    /// belongs to, the game decides that (see vanilla's `RenderLayers`).
+    ///
+    /// Blocks or variants which fail to bake are given a [ModelMesh::missing] mesh instead, and the errors are returned
+    /// alongside the name of the block they belong to. If the block atlas had to be repacked to fit the new textures,
+    /// every block with a texture which moved is baked again once the atlas is final.
     ///
     /// # Example
     ///
     ///```ignore
+    /// # use wgpu_mc::mc::chunk::RenderLayer;
     /// # use wgpu_mc::mc::MinecraftState;
     /// # use wgpu_mc::mc::resource::ResourcePath;
     /// # use wgpu_mc::WmRenderer;
@@ -285,90 +687,570 @@ impl MinecraftState {
     /// # let minecraft_state: MinecraftState;
     /// # let wm: WmRenderer;
     ///
-    /// minecraft_state.bake_blocks(
+    /// let failures = minecraft_state.bake_blocks(
     ///     &wm,
-    ///     [("minecraft:anvil", &ResourcePath("minecraft:blockstates/anvil.json".into()))]
+    ///     [(
+    ///         "minecraft:anvil",
+    ///         &ResourcePath("minecraft:blockstates/anvil.json".into()),
+    ///         RenderLayer::Solid,
+    ///     )]
     /// );
     /// ```
     pub fn bake_blocks<'a>(
         &self,
         wm: &WmRenderer,
-        block_states: impl IntoIterator<Item = (impl AsRef<str>, &'a ResourcePath)>,
-    ) {
-        let mut block_manager = self.block_manager.write();
+        block_states: impl IntoIterator<Item = (impl AsRef<str>, &'a ResourcePath, RenderLayer)>,
+    ) -> Vec<(String, MeshBakeError)> {
         let atlases = self.texture_manager.atlases.read();
         let block_atlas = atlases.get(BLOCK_ATLAS).unwrap();
 
-        //Figure out which block models there are
-        block_states
+        let model_cache = Arc::new(ModelCache::default());
+
+        let block_states = block_states
             .into_iter()
-            .for_each(|(block_name, block_state)| {
-                let blockstates: schemas::BlockStates =
-                    serde_json::from_str(&self.resource_provider.get_string(block_state).unwrap())
-                        .unwrap();
-
-                let block = match &blockstates {
-                    schemas::BlockStates::Variants { variants } => {
-                        let meshes: IndexMap<Vec<(String, StateValue)>, Vec<Arc<ModelMesh>>> =
-                            variants
-                                .iter()
-                                .map(|(variant_id, variant)| {
-                                    let key_iter = if !variant_id.is_empty() {
-                                        variant_id
-                                            .split(',')
-                                            .filter_map(|kv_pair| {
-                                                let mut split = kv_pair.split('=');
-                                                if kv_pair.is_empty() {
-                                                    return None;
-                                                }
-
-                                                Some((
-                                                    split.next().unwrap().to_string(),
-                                                    match split.next().unwrap() {
-                                                        "true" => StateValue::Bool(true),
-                                                        "false" => StateValue::Bool(false),
-                                                        other => StateValue::String(other.into()),
-                                                    },
-                                                ))
-                                            })
-                                            .collect::<Vec<_>>()
-                                    } else {
-                                        vec![]
-                                    };
-
-                                    (
-                                        key_iter,
-                                        variant
-                                            .models()
-                                            .iter()
-                                            .map(|variation| {
-                                                Arc::new(
-                                                    ModelMesh::bake(
-                                                        std::slice::from_ref(variation),
-                                                        &*self.resource_provider,
-                                                        block_atlas,
-                                                    )
-                                                    .unwrap(),
-                                                )
-                                            })
-                                            .collect::<Vec<Arc<ModelMesh>>>(),
-                                    )
-                                })
-                                .collect();
+            .map(|(block_name, block_state, layer)| {
+                (block_name.as_ref().to_string(), block_state, layer)
+            })
+            .collect::<Vec<_>>();
+
+        //Bake in parallel, but collect in the original order so that the indices in the block manager are deterministic
+        let baked = block_states
+            .par_iter()
+            .map(|(block_name, block_state, layer)| {
+                let (block, errors) =
+                    self.bake_block(block_state, *layer, &model_cache, block_atlas);
+                (block_name, block, errors)
+            })
+            .collect::<Vec<_>>();
+
+        let mut failures = Vec::new();
+
+        {
+            let mut block_manager = self.block_manager.write();
+
+            for (block_name, block, errors) in baked {
+                failures.extend(errors.into_iter().map(|error| (block_name.clone(), error)));
+                block_manager.blocks.insert(block_name.clone(), block);
+            }
+
+            block_manager.blockstates.extend(
+                block_states.iter().map(|(block_name, block_state, _)| {
+                    (block_name.clone(), (*block_state).clone())
+                }),
+            );
+            block_manager.layers.extend(
+                block_states
+                    .iter()
+                    .map(|(block_name, _, layer)| (block_name.clone(), *layer)),
+            );
+        }
+
+        failures.extend(self.upload_block_atlas(wm, &mut self.block_manager.write(), block_atlas));
+
+        failures
+    }
+
+    /// Upload the block atlas after textures were allocated in it, baking the blocks in `block_manager` which use
+    /// textures moved by a repack again
+    pub fn upload_block_atlas(
+        &self,
+        wm: &WmRenderer,
+        block_manager: &mut BlockManager,
+        block_atlas: &Atlas,
+    ) -> Vec<(String, MeshBakeError)> {
+        let upload = block_atlas.upload(wm);
+        if upload.remapped.is_empty() {
+            return Vec::new();
+        }
+
+        let old_uvs = upload
+            .remapped
+            .values()
+            .map(|(old_uv, _)| *old_uv)
+            .collect::<Vec<_>>();
+
+        self.rebake_remapped(
+            block_manager,
+            &old_uvs,
+            &Arc::new(ModelCache::default()),
+            block_atlas,
+        )
+    }
+
+    /// Bake every block with a model textured from one of `old_uvs` again, after the block atlas was repacked and
+    /// moved the textures there. The blocks keep their indices in the block manager.
+    fn rebake_remapped(
+        &self,
+        block_manager: &mut BlockManager,
+        old_uvs: &[UV],
+        model_cache: &Arc<ModelCache>,
+        block_atlas: &Atlas,
+    ) -> Vec<(String, MeshBakeError)> {
+        let rebaked = block_manager
+            .blocks
+            .iter()
+            .filter(|(_, block)| block.samples_any(old_uvs))
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .filter_map(|(block_name, old_block)| {
+                let Some(block_state) = block_manager.blockstates.get(block_name) else {
+                    log::warn!(
+                        "{block_name} uses textures moved by a repack of the block atlas but wasn't baked from a blockstate"
+                    );
+                    return None;
+                };
 
-                        Block::Variants(meshes)
+                let layer = block_manager
+                    .layers
+                    .get(block_name)
+                    .copied()
+                    .unwrap_or(RenderLayer::Solid);
+                let (block, errors) =
+                    self.bake_block_again(block_state, layer, old_block, model_cache, block_atlas);
+
+                Some((block_name.clone(), block, errors))
+            })
+            .collect::<Vec<_>>();
+
+        let mut failures = Vec::new();
+
+        for (block_name, block, errors) in rebaked {
+            failures.extend(errors.into_iter().map(|error| (block_name.clone(), error)));
+            block_manager.blocks.insert(block_name, block);
+        }
+
+        failures
+    }
+
+    /// Bake every block in the block manager again from the current resource provider, into a new [BlockManager]
+    /// and block atlas which replace the ones in use once they're complete. See [WmRenderer::reload_resources].
+    ///
+    /// Blocks are kept in the same order and multipart blocks bake the states they had baked before in the same
+    /// order, so [block::BlockstateKey]s stay the same as long as a block's blockstate doesn't change. Blocks which
+    /// weren't baked from a blockstate can't be baked again, so their textures are copied into the new atlas and
+    /// their models moved over to them.
+    pub(crate) fn rebake_blocks(
+        &self,
+        wm: &WmRenderer,
+    ) -> (BlockManager, Atlas, Vec<(String, MeshBakeError)>) {
+        let atlases = self.texture_manager.atlases.read();
+        let old_atlas = &atlases[BLOCK_ATLAS];
+        let block_atlas = Atlas::with_options(&wm.display, true, old_atlas.options());
+        let model_cache = Arc::new(ModelCache::default());
+
+        let old = self.block_manager.read();
+
+        let carried_over = old
+            .blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, (block_name, _))| !old.blockstates.contains_key(*block_name))
+            .map(|(index, (_, block))| (index, block))
+            .collect::<Vec<_>>();
+
+        //The textures of carried over blocks, found by where their models sample the old atlas
+        let sprites = old_atlas
+            .uv_map
+            .read()
+            .iter()
+            .filter(|(_, uv)| {
+                carried_over
+                    .iter()
+                    .any(|(_, block)| block.samples_any(&[**uv]))
+            })
+            .map(|(path, uv)| (path.clone(), *uv))
+            .collect::<Vec<_>>();
+
+        let textures = sprites
+            .iter()
+            .filter_map(|(path, _)| {
+                let texture_path = path.prepend("textures/").append(".png");
+                match self.resource_provider.get_bytes(&texture_path) {
+                    Some(bytes) => Some((path, bytes)),
+                    None => {
+                        log::warn!("Texture {texture_path} no longer exists");
+                        None
                     }
-                    schemas::BlockStates::Multipart { cases } => Block::Multipart(Multipart {
-                        cases: cases.clone(),
-                        keys: RwLock::new(IndexMap::new()),
-                    }),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if let Err(error) = block_atlas.allocate(
+            textures.iter().map(|(path, bytes)| (*path, bytes)),
+            &*self.resource_provider,
+        ) {
+            log::warn!("Failed to copy textures into the new block atlas: {error}");
+        }
+
+        let baked = old
+            .blocks
+            .iter()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|(block_name, old_block)| {
+                let Some(block_state) = old.blockstates.get(block_name) else {
+                    return (block_name, None, Vec::new());
                 };
 
-                block_manager
-                    .blocks
-                    .insert(String::from(block_name.as_ref()), block);
-            });
+                let layer = old
+                    .layers
+                    .get(block_name)
+                    .copied()
+                    .unwrap_or(RenderLayer::Solid);
+                let (block, errors) = self.bake_block_again(
+                    block_state,
+                    layer,
+                    old_block,
+                    &model_cache,
+                    &block_atlas,
+                );
+
+                (block_name, Some(block), errors)
+            })
+            .collect::<Vec<_>>();
+
+        //A reload which hasn't been swapped in yet may already use the next generation
+        let generation = self
+            .pending_reload
+            .read()
+            .as_ref()
+            .map_or(old.generation, |pending| pending.block_manager.generation)
+            + 1;
+
+        let mut block_manager = BlockManager {
+            blockstates: old.blockstates.clone(),
+            layers: old.layers.clone(),
+            generation,
+            ..Default::default()
+        };
+        let mut failures = Vec::new();
+
+        for (block_name, block, errors) in baked {
+            failures.extend(errors.into_iter().map(|error| (block_name.clone(), error)));
+
+            //Carried over blocks are moved once the atlas is final
+            let block = block.unwrap_or_else(|| Block::Variants(IndexMap::new()));
+            block_manager.blocks.insert(block_name.clone(), block);
+        }
+
+        failures.extend(self.upload_block_atlas(wm, &mut block_manager, &block_atlas));
+
+        for (index, block) in carried_over {
+            block_manager.blocks[index] = block.moved(&sprites, &block_atlas);
+        }
+
+        (block_manager, block_atlas, failures)
+    }
+
+    /// Bake a block again, along with every multipart state `old_block` had baked, in the same order
+    fn bake_block_again(
+        &self,
+        block_state: &ResourcePath,
+        layer: RenderLayer,
+        old_block: &Block,
+        model_cache: &Arc<ModelCache>,
+        block_atlas: &Atlas,
+    ) -> (Block, Vec<MeshBakeError>) {
+        let (block, mut errors) = self.bake_block(block_state, layer, model_cache, block_atlas);
+
+        if let (Block::Multipart(multipart), Block::Multipart(old_multipart)) = (&block, old_block)
+        {
+            errors.extend(multipart.bake_keys(
+                old_multipart.keys.read().keys(),
+                &*self.resource_provider,
+                block_atlas,
+            ));
+        }
+
+        (block, errors)
+    }
+
+    fn bake_block(
+        &self,
+        block_state: &ResourcePath,
+        layer: RenderLayer,
+        model_cache: &Arc<ModelCache>,
+        block_atlas: &Atlas,
+    ) -> (Block, Vec<MeshBakeError>) {
+        let mut errors = Vec::new();
+
+        let blockstates: schemas::BlockStates =
+            match load_json(block_state, &*self.resource_provider) {
+                Ok(blockstates) => blockstates,
+                Err(error) => {
+                    let mut variants = IndexMap::new();
+                    variants.insert(
+                        vec![],
+                        vec![WeightedModel {
+                            mesh: Arc::new(ModelMesh::missing(block_atlas, layer)),
+                            weight: 1,
+                        }],
+                    );
+                    return (Block::Variants(variants), vec![error]);
+                }
+            };
+
+        let block = match &blockstates {
+            schemas::BlockStates::Variants { variants } => {
+                let meshes: IndexMap<Vec<(String, StateValue)>, Vec<WeightedModel>> =
+                    variants
+                        .iter()
+                        .map(|(variant_id, variant)| {
+                            let key_iter = variant_id
+                                .split(',')
+                                .filter_map(|kv_pair| {
+                                    let (key, value) = kv_pair.split_once('=')?;
+
+                                    Some((
+                                        key.to_string(),
+                                        match value {
+                                            "true" => StateValue::Bool(true),
+                                            "false" => StateValue::Bool(false),
+                                            other => StateValue::String(other.into()),
+                                        },
+                                    ))
+                                })
+                                .collect::<Vec<_>>();
+
+                            (
+                                key_iter,
+                                variant
+                                    .models()
+                                    .iter()
+                                    .map(|variation| WeightedModel {
+                                        mesh: Arc::new(
+                                            ModelMesh::bake(
+                                                std::slice::from_ref(variation),
+                                                &*self.resource_provider,
+                                                model_cache,
+                                                block_atlas,
+                                                layer,
+                                            )
+                                            .unwrap_or_else(|error| {
+                                                errors.push(error);
+                                                ModelMesh::missing(block_atlas, layer)
+                                            }),
+                                        ),
+                                        weight: variation.weight,
+                                    })
+                                    .collect::<Vec<WeightedModel>>(),
+                            )
+                        })
+                        .collect();
+
+                Block::Variants(meshes)
+            }
+            schemas::BlockStates::Multipart { cases } => {
+                let multipart = Multipart {
+                    cases: cases.clone(),
+                    layer,
+                    keys: RwLock::new(IndexMap::new()),
+                    model_cache: model_cache.clone(),
+                };
+                errors.extend(multipart.allocate_textures(&*self.resource_provider, block_atlas));
+
+                Block::Multipart(multipart)
+            }
+        };
+
+        (block, errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, MemoryResources};
+
+    fn fence_resources() -> MemoryResources {
+        let element = |from: [u8; 3], to: [u8; 3]| {
+            format!(
+                r#"{{ "textures": {{ "all": "block/planks" }}, "elements": [{{ "from": {from:?}, "to": {to:?}, "faces": {{ "up": {{ "texture": "block/planks" }} }} }}] }}"#
+            )
+        };
+
+        MemoryResources::new([
+            (
+                "minecraft:models/block/fence_post.json",
+                element([6, 0, 6], [10, 16, 10]).into_bytes(),
+            ),
+            (
+                "minecraft:models/block/fence_side.json",
+                element([7, 12, 0], [9, 15, 9]).into_bytes(),
+            ),
+            (
+                "minecraft:textures/block/planks.png",
+                testing::png(&image::RgbaImage::new(16, 16)),
+            ),
+        ])
+    }
+
+    const FENCE_BLOCKSTATE: &str = r#"{ "multipart": [
+        { "apply": { "model": "block/fence_post" } },
+        { "when": { "north": "true" }, "apply": { "model": "block/fence_side", "uvlock": true } },
+        { "when": { "east": "true" }, "apply": { "model": "block/fence_side", "y": 90, "uvlock": true } }
+    ] }"#;
+
+    fn fence() -> Multipart {
+        let blockstates: schemas::BlockStates = serde_json::from_str(FENCE_BLOCKSTATE).unwrap();
+        let schemas::BlockStates::Multipart { cases } = blockstates else {
+            panic!("not a multipart blockstate");
+        };
+
+        Multipart {
+            cases,
+            layer: RenderLayer::Solid,
+            keys: RwLock::new(IndexMap::new()),
+            model_cache: Arc::default(),
+        }
+    }
+
+    fn booleans(properties: &[&str]) -> Vec<(String, Vec<StateValue>)> {
+        properties
+            .iter()
+            .map(|property| {
+                (
+                    property.to_string(),
+                    vec![StateValue::Bool(false), StateValue::Bool(true)],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn combinations() {
+        let domain = [
+            (
+                "north".to_string(),
+                vec![StateValue::Bool(false), StateValue::Bool(true)],
+            ),
+            (
+                "shape".to_string(),
+                vec![
+                    StateValue::String("low".into()),
+                    StateValue::String("tall".into()),
+                    StateValue::String("none".into()),
+                ],
+            ),
+        ];
+
+        let keys = Multipart::combinations(&domain)
+            .iter()
+            .map(|combination| {
+                multipart_key(
+                    combination
+                        .iter()
+                        .map(|(property, value)| (&property[..], value)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            keys,
+            [
+                "north=false,shape=low",
+                "north=false,shape=tall",
+                "north=false,shape=none",
+                "north=true,shape=low",
+                "north=true,shape=tall",
+                "north=true,shape=none",
+            ]
+        );
+        assert_eq!(Multipart::combinations(&[]), [Vec::new()]);
+    }
+
+    #[test]
+    fn bake_combinations() {
+        let Some(display) = testing::headless_display(16, 16) else {
+            return;
+        };
+        let resources = fence_resources();
+        let atlas = Atlas::new(&display, true);
+        let multipart = fence();
+
+        //Waterlogging doesn't change which cases apply, so it doubles the states but not the meshes
+        let domain = booleans(&["north", "east", "waterlogged"]);
+        assert!(multipart
+            .bake_combinations(&domain, &resources, &atlas)
+            .is_empty());
+
+        let keys = multipart.keys.read();
+        assert_eq!(keys.len(), 8);
+
+        let faces = |key: &str| {
+            let mesh = &keys[key];
+            [
+                &mesh.north,
+                &mesh.south,
+                &mesh.west,
+                &mesh.east,
+                &mesh.up,
+                &mesh.down,
+                &mesh.any,
+            ]
+            .iter()
+            .map(|faces| faces.len())
+            .sum::<usize>()
+        };
+        assert_eq!(faces("east=false,north=false,waterlogged=false"), 1);
+        assert_eq!(faces("east=false,north=true,waterlogged=false"), 2);
+        assert_eq!(faces("east=true,north=true,waterlogged=true"), 3);
+
+        assert!(Arc::ptr_eq(
+            &keys["east=true,north=false,waterlogged=false"],
+            &keys["east=true,north=false,waterlogged=true"],
+        ));
+        assert!(!Arc::ptr_eq(
+            &keys["east=true,north=false,waterlogged=false"],
+            &keys["east=false,north=true,waterlogged=false"],
+        ));
+        drop(keys);
+
+        //Already baked states are left alone
+        let baked = multipart.keys.read().values().cloned().collect::<Vec<_>>();
+        assert!(multipart
+            .bake_combinations(&booleans(&["north", "east"]), &resources, &atlas)
+            .is_empty());
+        assert_eq!(multipart.keys.read().len(), 12);
+        assert!(multipart
+            .keys
+            .read()
+            .values()
+            .zip(&baked)
+            .all(|(mesh, old)| Arc::ptr_eq(mesh, old)));
+    }
+
+    #[test]
+    fn multipart_textures_allocated_up_front() {
+        let resources = Arc::new(fence_resources());
+        resources.insert("minecraft:blockstates/fence.json", FENCE_BLOCKSTATE);
+
+        let Some(wm) = testing::headless_renderer(16, 16, resources.clone()) else {
+            return;
+        };
+        let blockstate = ResourcePath::from("minecraft:blockstates/fence.json");
+        assert!(wm
+            .mc
+            .bake_blocks(&wm, [("minecraft:fence", &blockstate, RenderLayer::Solid)])
+            .is_empty());
+
+        let block_manager = wm.mc.block_manager.read();
+        let Block::Multipart(multipart) = &block_manager.blocks["minecraft:fence"] else {
+            panic!("not a multipart block");
+        };
+        assert!(multipart.keys.read().is_empty());
+
+        let atlases = wm.mc.texture_manager.atlases.read();
+        let block_atlas = &atlases[BLOCK_ATLAS];
+        let uv_map = block_atlas.uv_map.read().clone();
+        let planks = uv_map[&ResourcePath::from("minecraft:block/planks")];
 
-        block_atlas.upload(wm);
+        //Baking a state lazily, like a section baking thread would, leaves the atlas alone
+        let north = StateValue::Bool(true);
+        let (mesh, _) = block_manager.blocks["minecraft:fence"]
+            .get_model_by_key([("north", &north)], &*resources, block_atlas, 0)
+            .unwrap();
+        assert!(mesh.samples_any(&[planks]));
+        assert_eq!(*block_atlas.uv_map.read(), uv_map);
     }
 }
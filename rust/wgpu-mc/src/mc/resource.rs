@@ -1,4 +1,12 @@
 use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+use regex::Regex;
+use serde_derive::Deserialize;
+use zip::ZipArchive;
 
 /// Describes a minecraft resource, like "minecraft:stone". Useful in combination with
 /// [ResourceProvider], which gets you the actual resource.
@@ -10,6 +18,13 @@ impl ResourcePath {
         Self(format!("{}{}", self.0, a))
     }
 
+    /// Where this resource is inside a resource pack, e.g. "assets/minecraft/textures/block/stone.png" for
+    /// "minecraft:textures/block/stone.png"
+    pub fn asset_path(&self) -> Option<String> {
+        let (namespace, path) = self.0.split_once(':')?;
+        Some(format!("assets/{namespace}/{path}"))
+    }
+
     pub fn prepend(&self, a: &str) -> Self {
         let mut split = self.0.split(':');
 
@@ -81,3 +96,303 @@ pub trait ResourceProvider: Send + Sync {
         String::from_utf8(self.get_bytes(id)?).ok()
     }
 }
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResourcePackError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Invalid pack.mcmeta: {0}")]
+    Mcmeta(#[from] serde_json::Error),
+    #[error("Invalid pack.mcmeta filter: {0}")]
+    Filter(#[from] regex::Error),
+}
+
+/// A [ResourceProvider] which is a whole resource pack, with files like pack.mcmeta next to its `assets` folder
+pub trait ResourcePack: ResourceProvider {
+    /// Get a file from the root of the pack, e.g. "pack.mcmeta"
+    fn get_root_file(&self, name: &str) -> Option<Vec<u8>>;
+}
+
+/// Reads resources from a resource pack that's been extracted to a directory, or any directory laid out like
+/// one, i.e. "minecraft:textures/block/stone.png" is read from `<root>/assets/minecraft/textures/block/stone.png`
+#[derive(Debug, Clone)]
+pub struct DirectoryResourceProvider {
+    pub root: PathBuf,
+}
+
+impl DirectoryResourceProvider {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ResourceProvider for DirectoryResourceProvider {
+    fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>> {
+        std::fs::read(self.root.join(id.asset_path()?)).ok()
+    }
+}
+
+impl ResourcePack for DirectoryResourceProvider {
+    fn get_root_file(&self, name: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.root.join(name)).ok()
+    }
+}
+
+/// Reads resources from a resource pack zip, or from the `assets` folder of a jar like the vanilla client jar
+pub struct ZipResourceProvider<R: Read + Seek = BufReader<File>> {
+    archive: Mutex<ZipArchive<R>>,
+}
+
+impl ZipResourceProvider {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ResourcePackError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> ZipResourceProvider<R> {
+    pub fn new(reader: R) -> Result<Self, ResourcePackError> {
+        Ok(Self {
+            archive: Mutex::new(ZipArchive::new(reader)?),
+        })
+    }
+
+    fn read(&self, name: &str) -> Option<Vec<u8>> {
+        let mut archive = self.archive.lock();
+        let mut file = archive.by_name(name).ok()?;
+
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes).ok()?;
+        Some(bytes)
+    }
+}
+
+impl<R: Read + Seek + Send> ResourceProvider for ZipResourceProvider<R> {
+    fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>> {
+        self.read(&id.asset_path()?)
+    }
+}
+
+impl<R: Read + Seek + Send> ResourcePack for ZipResourceProvider<R> {
+    fn get_root_file(&self, name: &str) -> Option<Vec<u8>> {
+        self.read(name)
+    }
+}
+
+#[derive(Deserialize)]
+struct PackMcmeta {
+    #[serde(default)]
+    filter: Option<PackFilter>,
+}
+
+#[derive(Deserialize)]
+struct PackFilter {
+    #[serde(default)]
+    block: Vec<PackFilterPattern>,
+}
+
+#[derive(Deserialize)]
+struct PackFilterPattern {
+    namespace: Option<String>,
+    path: Option<String>,
+}
+
+/// One entry of the `filter.block` list of a pack.mcmeta. Missing patterns match everything, like in vanilla
+struct ResourceFilter {
+    namespace: Option<Regex>,
+    path: Option<Regex>,
+}
+
+impl ResourceFilter {
+    fn new(pattern: &PackFilterPattern) -> Result<Self, regex::Error> {
+        //Vanilla matches the whole namespace or path, not just part of it
+        let full_match = |pattern: &Option<String>| {
+            pattern
+                .as_ref()
+                .map(|pattern| Regex::new(&format!("^(?:{pattern})$")))
+                .transpose()
+        };
+
+        Ok(Self {
+            namespace: full_match(&pattern.namespace)?,
+            path: full_match(&pattern.path)?,
+        })
+    }
+
+    fn matches(&self, namespace: &str, path: &str) -> bool {
+        self.namespace
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(namespace))
+            && self.path.as_ref().is_none_or(|regex| regex.is_match(path))
+    }
+}
+
+struct ResourceLayer {
+    provider: Box<dyn ResourceProvider>,
+    filters: Vec<ResourceFilter>,
+}
+
+/// Stacks resource packs on top of each other, the way Minecraft's resource pack screen does. Resources are read
+/// from the topmost pack which has them, so packs can override vanilla resources and each other.
+///
+/// A pack's pack.mcmeta can also hide resources of the packs below it with a `filter` section, e.g.
+///
+/// ```json
+/// { "filter": { "block": [{ "namespace": "minecraft", "path": "textures/block/.*" }] } }
+/// ```
+#[derive(Default)]
+pub struct LayeredResourceProvider {
+    layers: Vec<ResourceLayer>,
+}
+
+impl LayeredResourceProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pack on top of the ones added so far, reading the filter from its pack.mcmeta if it has one
+    pub fn push(&mut self, pack: impl ResourcePack + 'static) -> Result<(), ResourcePackError> {
+        let filters = match pack.get_root_file("pack.mcmeta") {
+            Some(mcmeta) => serde_json::from_slice::<PackMcmeta>(&mcmeta)?
+                .filter
+                .map_or(Ok(Vec::new()), |filter| {
+                    filter.block.iter().map(ResourceFilter::new).collect()
+                })?,
+            None => Vec::new(),
+        };
+
+        self.layers.push(ResourceLayer {
+            provider: Box::new(pack),
+            filters,
+        });
+
+        Ok(())
+    }
+
+    /// Add a provider which isn't a pack, e.g. one backed by the game's resource manager, on top of the ones
+    /// added so far
+    pub fn push_provider(&mut self, provider: impl ResourceProvider + 'static) {
+        self.layers.push(ResourceLayer {
+            provider: Box::new(provider),
+            filters: Vec::new(),
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+impl ResourceProvider for LayeredResourceProvider {
+    fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>> {
+        let (namespace, path) = id.0.split_once(':')?;
+
+        for layer in self.layers.iter().rev() {
+            if let Some(bytes) = layer.provider.get_bytes(id) {
+                return Some(bytes);
+            }
+
+            if layer
+                .filters
+                .iter()
+                .any(|filter| filter.matches(namespace, path))
+            {
+                return None;
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    fn zip_pack(files: &[(&str, &str)]) -> ZipResourceProvider<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+
+        ZipResourceProvider::new(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn zip_pack_reads_assets() {
+        let pack = zip_pack(&[
+            ("pack.mcmeta", "{}"),
+            ("assets/minecraft/textures/block/stone.png", "stone"),
+        ]);
+
+        assert_eq!(
+            pack.get_string(&"minecraft:textures/block/stone.png".into()),
+            Some("stone".into())
+        );
+        assert_eq!(
+            pack.get_bytes(&"minecraft:textures/block/dirt.png".into()),
+            None
+        );
+        assert_eq!(pack.get_root_file("pack.mcmeta"), Some(b"{}".to_vec()));
+    }
+
+    #[test]
+    fn layers_override_and_filter() {
+        let vanilla = zip_pack(&[
+            ("assets/minecraft/textures/block/stone.png", "vanilla stone"),
+            ("assets/minecraft/textures/block/dirt.png", "vanilla dirt"),
+            ("assets/minecraft/models/block/dirt.json", "vanilla model"),
+        ]);
+        let pack = zip_pack(&[
+            (
+                "pack.mcmeta",
+                r#"{ "pack": { "pack_format": 15, "description": "" }, "filter": { "block": [{ "path": "textures/.*" }] } }"#,
+            ),
+            ("assets/minecraft/textures/block/stone.png", "pack stone"),
+        ]);
+
+        let mut layered = LayeredResourceProvider::new();
+        layered.push(vanilla).unwrap();
+        layered.push(pack).unwrap();
+
+        assert_eq!(
+            layered.get_string(&"minecraft:textures/block/stone.png".into()),
+            Some("pack stone".into())
+        );
+        //Hidden by the pack's filter
+        assert_eq!(
+            layered.get_bytes(&"minecraft:textures/block/dirt.png".into()),
+            None
+        );
+        assert_eq!(
+            layered.get_string(&"minecraft:models/block/dirt.json".into()),
+            Some("vanilla model".into())
+        );
+    }
+
+    #[test]
+    fn invalid_filter() {
+        let pack = zip_pack(&[(
+            "pack.mcmeta",
+            r#"{ "filter": { "block": [{ "path": "(" }] } }"#,
+        )]);
+
+        assert!(matches!(
+            LayeredResourceProvider::new().push(pack),
+            Err(ResourcePackError::Filter(_))
+        ));
+    }
+}
@@ -2,25 +2,103 @@ use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
 
+use arc_swap::ArcSwap;
 use bytemuck::{Pod, Zeroable};
 use guillotiere::euclid::Size2D;
-use guillotiere::AtlasAllocator;
-use image::imageops::overlay;
-use image::{ImageBuffer, Rgba};
+use guillotiere::{AllocatorOptions, AtlasAllocator};
+use image::imageops::{overlay, replace};
+use image::{GenericImageView, ImageBuffer, Rgba, RgbaImage};
 use minecraft_assets::schemas;
-use parking_lot::RwLock;
-use wgpu::Extent3d;
+use parking_lot::{Mutex, RwLock};
 
 use crate::mc::resource::{ResourcePath, ResourceProvider};
 use crate::texture::{TextureAndView, UV};
 use crate::{Display, WmRenderer};
 
-/// The width and height of an [atlas](Atlas];
-pub const ATLAS_DIMENSIONS: u32 = 2048;
+/// The width and height an [Atlas] starts out with
+pub const INITIAL_ATLAS_SIZE: u32 = 512;
+
+/// A rectangle of an image, as `(x, y, width, height)`
+type Region = (u32, u32, u32, u32);
+
+/// Animation indices are stored in 10 bits of each vertex, and index 0 means not animated
+pub const MAX_ANIMATED_TEXTURES: usize = 1023;
+
+/// The largest an [Atlas] can grow to. UVs are stored as 16-bit texel coordinates, so this is also capped by what
+/// they can address.
+pub fn max_atlas_size(limits: &wgpu::Limits) -> u32 {
+    limits.max_texture_dimension_2d.min(1 << 15)
+}
+
+/// How an [Atlas] lays out and mipmaps its textures
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AtlasOptions {
+    /// Mip levels are generated down to this one, 0 disables mipmapping. Textures are aligned to
+    /// `1 << max_mip_level` texels, so that downsampling never mixes texels of neighbouring textures.
+    pub max_mip_level: u32,
+    /// Surround every texture with a border of its edge texels, one texel wide at the smallest mip level, so that
+    /// filtering doesn't bleed neighbouring textures into it. This costs a lot of space with many mip levels.
+    pub padding: bool,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            max_mip_level: 4,
+            padding: true,
+        }
+    }
+}
+
+impl AtlasOptions {
+    /// The alignment of textures, and the width of the padding around them
+    fn alignment(&self) -> u32 {
+        1 << self.max_mip_level
+    }
+
+    fn padding(&self) -> u32 {
+        if self.padding {
+            self.alignment()
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AtlasError {
+    #[error("couldn't decode {path}: {source}")]
+    Decode {
+        path: ResourcePath,
+        source: image::ImageError,
+    },
+    #[error("{path} ({width}x{height}) doesn't fit in the atlas")]
+    Full {
+        path: ResourcePath,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// What changed since the last time an [Atlas] was uploaded
+#[derive(Debug, Default)]
+pub struct AtlasUpload {
+    /// The atlas grew, so its texture was replaced and bind groups using the old one have to be recreated,
+    /// see [crate::render::graph::RenderGraph::update_texture_resources]
+    pub resized: bool,
+    /// Textures which were moved when the atlas was repacked, with their old and new UVs. Anything baked with the
+    /// old UVs, like block models, has to be baked again.
+    pub remapped: HashMap<ResourcePath, (UV, UV)>,
+}
 
 /// A texture atlas. This is used in many places, most notably terrain and entity rendering.
 /// Combines multiple small textures into a single big one, which can help improve performance.
 ///
+/// Atlases which resize start out [INITIAL_ATLAS_SIZE] texels wide and double in size whenever a texture doesn't
+/// fit, up to [max_atlas_size]. Growing keeps every texture where it is, so UVs stay valid, but shaders have to
+/// normalize them by the size of the atlas texture rather than assume one. Once the atlas can't grow any further
+/// it is repacked, which moves textures around and is reported by [Atlas::upload].
+///
 /// # Example
 ///
 ///```ignore
@@ -50,24 +128,36 @@ pub const ATLAS_DIMENSIONS: u32 = 2048;
 ///             &resource_provider.get_bytes(&dirt).unwrap()
 ///         )
 ///     ], &*resource_provider
-/// );
+/// ).unwrap();
 ///
 /// atlas.upload(&wm_renderer);
 /// ```
 pub struct Atlas {
     /// The image allocator which decides where images should go in the atlas texture
     pub allocator: RwLock<AtlasAllocator>,
-    /// The atlas image buffer itself. This is what gets uploaded to the GPU
+    /// The atlas image buffer itself. This is what gets uploaded to the GPU. Only the parts written by
+    /// [Atlas::allocate] are uploaded again, so changes made directly to it might not show up.
     pub image: RwLock<ImageBuffer<Rgba<u8>, Vec<u8>>>,
     /// The mapping of image [ResourcePath]s to UV coordinates
     pub uv_map: RwLock<HashMap<ResourcePath, UV>>,
-    /// The representation of the [Atlas]'s image buffer on the GPU, which can be bound to a draw call
-    pub texture: Arc<TextureAndView>,
+    /// The representation of the [Atlas]'s image buffer on the GPU, which can be bound to a draw call.
+    /// This is replaced when the atlas grows, see [Atlas::upload]
+    pub texture: ArcSwap<TextureAndView>,
     /// Not every [Atlas] is used for block textures, but the ones that are store the information for each animated texture here
-    pub animated_textures: RwLock<Vec<schemas::texture::TextureAnimation>>,
-    ///
+    pub animated_textures: RwLock<Vec<AnimatedTexture>>,
+    /// The index of each animated texture into the buffer built by [Atlas::animated_uvs], which is one more than its
+    /// index into [Atlas::animated_textures]. Index 0 is left for textures which aren't animated.
     pub animated_texture_offsets: RwLock<HashMap<ResourcePath, u32>>,
-    size: u32,
+    resizes: bool,
+    max_size: u32,
+    options: AtlasOptions,
+    /// The UVs textures had before being moved by a repack, since the last upload
+    remapped: Mutex<HashMap<ResourcePath, UV>>,
+    /// Every mip level after the first as of the last upload, so that only the parts which changed since have to
+    /// be downsampled again
+    mips: Mutex<Vec<RgbaImage>>,
+    /// The regions of the image written since the last upload, or None if all of it has to be uploaded again
+    dirty: Mutex<Option<Vec<Region>>>,
 }
 
 impl Debug for Atlas {
@@ -77,40 +167,69 @@ impl Debug for Atlas {
 }
 
 impl Atlas {
-    pub fn new(display: &Display, _resizes: bool) -> Self {
-        let tv = TextureAndView::from_rgb_bytes(
-            display,
-            &vec![0u8; (ATLAS_DIMENSIONS * ATLAS_DIMENSIONS) as usize * 4],
-            Extent3d {
-                width: ATLAS_DIMENSIONS,
-                height: ATLAS_DIMENSIONS,
-                depth_or_array_layers: 1,
-            },
-            None,
-            wgpu::TextureFormat::Rgba8Unorm,
-        )
-        .unwrap();
+    /// Atlases which don't resize are [INITIAL_ATLAS_SIZE] texels wide and fail to allocate once they're full
+    pub fn new(display: &Display, resizes: bool) -> Self {
+        Self::with_options(display, resizes, AtlasOptions::default())
+    }
+
+    pub fn with_options(display: &Display, resizes: bool, options: AtlasOptions) -> Self {
+        let image = ImageBuffer::new(INITIAL_ATLAS_SIZE, INITIAL_ATLAS_SIZE);
+        let mut mips = generate_mips(&image, options.max_mip_level);
+        let texture = Self::create_texture(display, &mips);
+        mips.remove(0);
 
         Self {
-            allocator: RwLock::new(AtlasAllocator::new(Size2D::new(
-                ATLAS_DIMENSIONS as i32,
-                ATLAS_DIMENSIONS as i32,
-            ))),
-            image: RwLock::new(ImageBuffer::new(ATLAS_DIMENSIONS, ATLAS_DIMENSIONS)),
+            allocator: RwLock::new(Self::create_allocator(&options, INITIAL_ATLAS_SIZE)),
+            texture: ArcSwap::new(Arc::new(texture)),
+            image: RwLock::new(image),
             uv_map: Default::default(),
-            texture: Arc::new(tv),
             animated_textures: RwLock::new(Vec::new()),
             animated_texture_offsets: Default::default(),
-            size: ATLAS_DIMENSIONS,
+            resizes,
+            max_size: max_atlas_size(&display.device.limits()),
+            options,
+            remapped: Default::default(),
+            mips: Mutex::new(mips),
+            dirty: Mutex::new(Some(Vec::new())),
         }
     }
 
-    /// Add multiple textures to the atlas. This automatically handles .mcmeta files when dealing with block textures
+    fn create_allocator(options: &AtlasOptions, size: u32) -> AtlasAllocator {
+        let alignment = options.alignment() as i32;
+
+        AtlasAllocator::with_options(
+            Size2D::new(size as i32, size as i32),
+            &AllocatorOptions {
+                alignment: Size2D::new(alignment, alignment),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn create_texture(display: &Display, mips: &[RgbaImage]) -> TextureAndView {
+        TextureAndView::from_mips(display, mips, None, wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    pub fn options(&self) -> AtlasOptions {
+        self.options
+    }
+
+    /// The current width and height of the atlas, which is what UVs are relative to
+    pub fn size(&self) -> u32 {
+        self.image.read().width()
+    }
+
+    /// Add multiple textures to the atlas. This automatically handles .mcmeta files when dealing with block textures.
+    /// Textures are named like sprites in models, so the .mcmeta of `minecraft:block/fire_0` is
+    /// `minecraft:textures/block/fire_0.png.mcmeta`.
+    ///
+    /// Textures are added until one fails to decode or doesn't fit, the ones before it stay allocated.
     pub fn allocate<'a, T>(
         &self,
         images: impl IntoIterator<Item = (&'a ResourcePath, &'a T)>,
         resource_provider: &dyn ResourceProvider,
-    ) where
+    ) -> Result<(), AtlasError>
+    where
         T: AsRef<[u8]> + 'a,
     {
         let mut allocator = self.allocator.write();
@@ -118,19 +237,25 @@ impl Atlas {
         let mut map = self.uv_map.write();
 
         let mut animated_textures = self.animated_textures.write();
-        // let mut animated_texture_offsets = self.animated_texture_offsets.write();
+        let mut animated_texture_offsets = self.animated_texture_offsets.write();
+
+        images.into_iter().try_for_each(|(name, slice)| {
+            //Another thread may have allocated this texture in the meantime
+            if map.contains_key(name) {
+                return Ok(());
+            }
 
-        images.into_iter().for_each(|(name, slice)| {
             self.allocate_one(
                 &mut image_buffer,
                 &mut map,
                 &mut allocator,
                 &mut animated_textures,
+                &mut animated_texture_offsets,
                 name,
                 slice.as_ref(),
                 resource_provider,
-            );
-        });
+            )
+        })
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -139,85 +264,481 @@ impl Atlas {
         image_buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
         map: &mut HashMap<ResourcePath, UV>,
         allocator: &mut AtlasAllocator,
-        animated_textures: &mut Vec<schemas::texture::TextureAnimation>,
+        animated_textures: &mut Vec<AnimatedTexture>,
+        animated_texture_offsets: &mut HashMap<ResourcePath, u32>,
         path: &ResourcePath,
         image_bytes: &[u8],
         resource_provider: &dyn ResourceProvider,
-    ) {
-        let image = image::load_from_memory(image_bytes).unwrap();
-
-        let allocation = allocator
-            .allocate(Size2D::new(image.width() as i32, image.height() as i32))
-            .unwrap();
-
-        overlay(
-            image_buffer,
-            &image,
-            allocation.rectangle.min.x as i64,
-            allocation.rectangle.min.y as i64,
-        );
+    ) -> Result<(), AtlasError> {
+        let image = image::load_from_memory(image_bytes).map_err(|source| AtlasError::Decode {
+            path: path.clone(),
+            source,
+        })?;
+        let (width, height) = (image.width(), image.height());
 
-        let mcmeta_path = path.append(".mcmeta");
+        let mcmeta_path = path.prepend("textures/").append(".png").append(".mcmeta");
 
         let mcmeta = resource_provider
             .get_string(&mcmeta_path)
             .and_then(|string| serde_json::from_str::<schemas::texture::Texture>(&string).ok());
 
-        if let Some(texture) = mcmeta {
-            if let Some(animation) = texture.animation {
-                animated_textures.push(animation)
+        let animated = mcmeta
+            .and_then(|texture| texture.animation)
+            .and_then(|animation| {
+                AnimatedTexture::new(&animation, ((0, 0), (width as u16, height as u16)))
+            });
+
+        let animated = match animated {
+            Some(_) if animated_textures.len() >= MAX_ANIMATED_TEXTURES => {
+                log::warn!(
+                    "Too many animated textures, {} will show its whole strip of frames",
+                    path.0
+                );
+                None
             }
+            animated => animated,
+        };
+
+        //The frames of animated textures are padded separately, so that they don't bleed into each other
+        let (columns, rows, frame_width, frame_height) = match &animated {
+            Some(animated) => (
+                width / animated.frame_width as u32,
+                height / animated.frame_height as u32,
+                animated.frame_width as u32,
+                animated.frame_height as u32,
+            ),
+            None => (1, 1, width, height),
+        };
+
+        let padding = self.options.padding();
+        let (stride_x, stride_y) = (frame_width + padding * 2, frame_height + padding * 2);
+        let size = Size2D::new((columns * stride_x) as i32, (rows * stride_y) as i32);
+        let mut repacked = false;
+
+        let allocation = loop {
+            if let Some(allocation) = allocator.allocate(size) {
+                break allocation;
+            }
+
+            let current_size = allocator.size().width as u32;
+
+            if self.resizes && current_size < self.max_size {
+                let new_size = (current_size * 2).min(self.max_size);
+                allocator.grow(Size2D::new(new_size as i32, new_size as i32));
+
+                let mut grown = ImageBuffer::new(new_size, new_size);
+                replace(&mut grown, image_buffer, 0, 0);
+                *image_buffer = grown;
+                *self.dirty.lock() = None;
+            } else if self.resizes
+                && !repacked
+                && self.repack(
+                    image_buffer,
+                    map,
+                    allocator,
+                    animated_textures,
+                    animated_texture_offsets,
+                )
+            {
+                repacked = true;
+            } else {
+                return Err(AtlasError::Full {
+                    path: path.clone(),
+                    width,
+                    height,
+                });
+            }
+        };
+
+        let (min_x, min_y) = (
+            allocation.rectangle.min.x as u32,
+            allocation.rectangle.min.y as u32,
+        );
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x, y) = (
+                    min_x + column * stride_x + padding,
+                    min_y + row * stride_y + padding,
+                );
+                overlay(
+                    image_buffer,
+                    &*image.view(
+                        column * frame_width,
+                        row * frame_height,
+                        frame_width,
+                        frame_height,
+                    ),
+                    x as i64,
+                    y as i64,
+                );
+                pad_sprite(image_buffer, x, y, frame_width, frame_height, padding);
+            }
+        }
+
+        if let Some(dirty) = self.dirty.lock().as_mut() {
+            dirty.push((min_x, min_y, columns * stride_x, rows * stride_y));
         }
 
-        map.insert(
-            path.clone(),
+        let strip = (
+            ((min_x + padding) as u16, (min_y + padding) as u16),
             (
-                (
-                    allocation.rectangle.min.x as u16,
-                    allocation.rectangle.min.y as u16,
-                ),
-                (
-                    allocation.rectangle.max.x as u16,
-                    allocation.rectangle.max.y as u16,
-                ),
+                (min_x + columns * stride_x - padding) as u16,
+                (min_y + rows * stride_y - padding) as u16,
             ),
         );
+
+        match animated {
+            Some(mut animated) => {
+                animated.strip = strip;
+                animated.frame_padding = padding as u16;
+
+                //Blocks are textured with the first frame and offset to the others in the vertex shader
+                map.insert(path.clone(), animated.frame_uv(0));
+                animated_textures.push(animated);
+                animated_texture_offsets.insert(path.clone(), animated_textures.len() as u32);
+            }
+            None => {
+                map.insert(path.clone(), strip);
+            }
+        }
+
+        Ok(())
     }
 
-    /// Upload the atlas texture to the GPU. If the Atlas has to resize the texture on the GPU, then the bindable_texture that this struct provides may
-    /// become obsolete if you .load() the BindableTexture before calling upload(), so you should get the BindableTexture after calling this function and not before-hand.
-    /// Returns true if the atlas was resized.
-    pub fn upload(&self, wm: &WmRenderer) -> bool {
-        wm.display.queue.write_texture(
-            self.texture.texture.as_image_copy(),
-            self.image.read().as_raw(),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * self.size),
-                rows_per_image: Some(self.size),
-            },
-            Extent3d {
-                width: self.size,
-                height: self.size,
-                depth_or_array_layers: 1,
-            },
-        );
+    /// Re-allocate every texture from scratch, tallest first, which packs them more tightly than allocating them
+    /// in the order they arrived. Returns false and leaves the atlas untouched if they don't all fit.
+    fn repack(
+        &self,
+        image_buffer: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+        map: &mut HashMap<ResourcePath, UV>,
+        allocator: &mut AtlasAllocator,
+        animated_textures: &mut [AnimatedTexture],
+        animated_texture_offsets: &HashMap<ResourcePath, u32>,
+    ) -> bool {
+        let size = allocator.size();
+        let padding = self.options.padding();
+
+        //Animated textures move their whole strip of frames
+        let strip = |path: &ResourcePath, uv: UV| match animated_texture_offsets.get(path) {
+            Some(&index) => animated_textures[index as usize - 1].strip,
+            None => uv,
+        };
+
+        let mut textures = map
+            .iter()
+            .map(|(path, &uv)| (path, uv, strip(path, uv)))
+            .collect::<Vec<_>>();
+        textures.sort_by_key(|(_, _, ((u1, v1), (u2, v2)))| std::cmp::Reverse((v2 - v1, u2 - u1)));
+
+        let mut new_allocator = Self::create_allocator(&self.options, size.width as u32);
+        let mut new_image = ImageBuffer::new(size.width as u32, size.height as u32);
+        let mut new_map = HashMap::with_capacity(map.len());
+        let mut new_strips = Vec::new();
+
+        for (path, uv, ((u1, v1), (u2, v2))) in textures {
+            let (width, height) = ((u2 - u1) as u32, (v2 - v1) as u32);
+            let Some(allocation) = new_allocator.allocate(Size2D::new(
+                (width + padding * 2) as i32,
+                (height + padding * 2) as i32,
+            )) else {
+                return false;
+            };
+
+            let (x, y) = (
+                allocation.rectangle.min.x as u32 + padding,
+                allocation.rectangle.min.y as u32 + padding,
+            );
+            //The strips of animated textures are moved along with the padding between their frames, padding the
+            //whole strip then repeats the same texels each frame was padded with
+            replace(
+                &mut new_image,
+                &*image_buffer.view(u1 as u32, v1 as u32, width, height),
+                x as i64,
+                y as i64,
+            );
+            pad_sprite(&mut new_image, x, y, width, height, padding);
+
+            let new_strip = (
+                (x as u16, y as u16),
+                ((x + width) as u16, (y + height) as u16),
+            );
+            let new_uv = match animated_texture_offsets.get(path) {
+                Some(&index) => {
+                    let mut animated = animated_textures[index as usize - 1].clone();
+                    animated.strip = new_strip;
+                    let new_uv = animated.frame_uv(0);
+                    new_strips.push((index as usize - 1, new_strip));
+                    new_uv
+                }
+                None => new_strip,
+            };
+            if new_uv != uv {
+                self.remapped.lock().entry(path.clone()).or_insert(uv);
+            }
+            new_map.insert(path.clone(), new_uv);
+        }
+
+        *allocator = new_allocator;
+        *image_buffer = new_image;
+        *map = new_map;
+        *self.dirty.lock() = None;
+        for (index, strip) in new_strips {
+            animated_textures[index].strip = strip;
+        }
+
+        true
+    }
+
+    /// Upload the atlas texture and its mipmaps to the GPU. If the atlas grew since the last upload its texture is
+    /// replaced, so [Atlas::texture] should be loaded after calling this function and not before-hand, and bind
+    /// groups created from the old texture have to be recreated.
+    ///
+    /// Only the textures allocated since the last upload are uploaded and downsampled, unless the atlas grew or was
+    /// repacked. Returns whether the texture was replaced and which textures were moved by a repack.
+    pub fn upload(&self, wm: &WmRenderer) -> AtlasUpload {
+        let image = self.image.read();
+        let mut cached_mips = self.mips.lock();
+        let dirty = self.dirty.lock().replace(Vec::new());
+
+        let resized = self.texture.load().texture.width() != image.width();
+
+        match dirty {
+            Some(regions) if !resized => {
+                let texture = self.texture.load();
+
+                for region in regions {
+                    let (x, y, width, height) = region;
+                    texture.write_region(
+                        &wm.display,
+                        0,
+                        (x, y),
+                        &image.view(x, y, width, height).to_image(),
+                    );
+
+                    for (mip, (x, y, width, height)) in
+                        update_mips(&image, &mut cached_mips, region)
+                            .into_iter()
+                            .enumerate()
+                    {
+                        texture.write_region(
+                            &wm.display,
+                            mip as u32 + 1,
+                            (x, y),
+                            &cached_mips[mip].view(x, y, width, height).to_image(),
+                        );
+                    }
+                }
+            }
+            _ => {
+                let mut mips = generate_mips(&image, self.options.max_mip_level);
+
+                if resized {
+                    self.texture
+                        .store(Arc::new(Self::create_texture(&wm.display, &mips)));
+                } else {
+                    self.texture.load().write_mips(&wm.display, &mips);
+                }
+
+                mips.remove(0);
+                *cached_mips = mips;
+            }
+        }
+
+        let uv_map = self.uv_map.read();
+        let remapped = self
+            .remapped
+            .lock()
+            .drain()
+            .filter_map(|(path, old_uv)| {
+                let new_uv = *uv_map.get(&path)?;
+                Some((path, (old_uv, new_uv)))
+            })
+            .collect();
+
+        AtlasUpload { resized, remapped }
+    }
 
-        false
+    /// The frame offsets of every animated texture at `tick`, indexed by [Atlas::animated_texture_offsets]. The
+    /// first entry is all zeroes for textures which aren't animated.
+    pub fn animated_uvs(&self, tick: u32) -> Vec<AnimatedUV> {
+        std::iter::once(AnimatedUV::zeroed())
+            .chain(
+                self.animated_textures
+                    .read()
+                    .iter()
+                    .map(|animated| animated.uv_at(tick)),
+            )
+            .collect()
     }
 
     pub fn clear(&self) {
         self.allocator.write().clear();
         self.animated_texture_offsets.write().clear();
         self.animated_textures.write().clear();
-        *self.image.write() = ImageBuffer::new(self.size, self.size);
+        self.remapped.lock().clear();
+        *self.dirty.lock() = None;
+
+        let mut image = self.image.write();
+        *image = ImageBuffer::new(image.width(), image.height());
+    }
+}
+
+/// Fill the `padding` texels around a sprite with copies of its edge texels
+fn pad_sprite(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, padding: u32) {
+    if padding == 0 {
+        return;
+    }
+
+    for pad_y in y - padding..y + height + padding {
+        for pad_x in x - padding..x + width + padding {
+            let inside = (x..x + width).contains(&pad_x) && (y..y + height).contains(&pad_y);
+            if !inside {
+                let edge = *image.get_pixel(
+                    pad_x.clamp(x, x + width - 1),
+                    pad_y.clamp(y, y + height - 1),
+                );
+                image.put_pixel(pad_x, pad_y, edge);
+            }
+        }
+    }
+}
+
+/// The texel at `x`, `y` of an image halved in size, weighting colours by their alpha so that fully transparent
+/// texels in cutout textures don't darken the texels around them
+fn downsample_texel(image: &RgbaImage, x: u32, y: u32) -> Rgba<u8> {
+    let texels = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+        image.get_pixel(
+            (x * 2 + dx).min(image.width() - 1),
+            (y * 2 + dy).min(image.height() - 1),
+        )
+    });
+
+    let alpha = texels.iter().map(|texel| texel[3] as u32).sum::<u32>();
+    let channel = |channel: usize| -> u8 {
+        texels
+            .iter()
+            .map(|texel| texel[channel] as u32 * texel[3] as u32)
+            .sum::<u32>()
+            .checked_div(alpha)
+            //Fully transparent, so plain averaging can't make anything darker
+            .unwrap_or_else(|| {
+                texels
+                    .iter()
+                    .map(|texel| texel[channel] as u32)
+                    .sum::<u32>()
+                    / 4
+            }) as u8
+    };
+
+    Rgba([channel(0), channel(1), channel(2), (alpha / 4) as u8])
+}
+
+/// Halve an image, see [downsample_texel]
+fn downsample(image: &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(
+        (image.width() / 2).max(1),
+        (image.height() / 2).max(1),
+        |x, y| downsample_texel(image, x, y),
+    )
+}
+
+/// The image followed by up to `max_mip_level` successively halved copies of it
+pub fn generate_mips(image: &RgbaImage, max_mip_level: u32) -> Vec<RgbaImage> {
+    let levels = max_mip_level.min(image.width().max(image.height()).ilog2());
+
+    let mut mips = vec![image.clone()];
+    for _ in 0..levels {
+        mips.push(downsample(mips.last().unwrap()));
+    }
+
+    mips
+}
+
+/// Downsample the part of each mip level after the first which covers `region` of the image again, after it was
+/// written to. Returns the region updated in each of `mips`.
+fn update_mips(image: &RgbaImage, mips: &mut [RgbaImage], region: Region) -> Vec<Region> {
+    let (x, y, width, height) = region;
+    let (mut x1, mut y1, mut x2, mut y2) = (x, y, x + width, y + height);
+
+    (0..mips.len())
+        .map(|level| {
+            let (above, below) = mips.split_at_mut(level);
+            let source = above.last().unwrap_or(image);
+            let mip = &mut below[0];
+
+            (x1, y1) = (x1 / 2, y1 / 2);
+            (x2, y2) = (
+                x2.div_ceil(2).min(mip.width()),
+                y2.div_ceil(2).min(mip.height()),
+            );
+
+            for texel_y in y1..y2 {
+                for texel_x in x1..x2 {
+                    mip.put_pixel(texel_x, texel_y, downsample_texel(source, texel_x, texel_y));
+                }
+            }
+
+            (x1, y1, x2 - x1, y2 - y1)
+        })
+        .collect()
+}
+
+/// How [TextureManager::default_sampler] filters textures
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SamplerOptions {
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    /// How to blend between mip levels
+    pub mipmap_filter: wgpu::FilterMode,
+    /// Only mip levels up to this one are sampled
+    pub max_mip_level: u32,
+    /// Anisotropic filtering with up to this many samples, 1 disables it. Anisotropic filtering requires linear
+    /// filtering, so all filters are switched to linear when this is above 1.
+    pub anisotropy_clamp: u16,
+}
+
+impl Default for SamplerOptions {
+    /// Crisp texels up close and smoothly blended mip levels in the distance
+    fn default() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            max_mip_level: AtlasOptions::default().max_mip_level,
+            anisotropy_clamp: 1,
+        }
+    }
+}
+
+impl SamplerOptions {
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        let filter = |filter| {
+            if self.anisotropy_clamp > 1 {
+                wgpu::FilterMode::Linear
+            } else {
+                filter
+            }
+        };
+
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: filter(self.mag_filter),
+            min_filter: filter(self.min_filter),
+            mipmap_filter: filter(self.mipmap_filter),
+            lod_max_clamp: self.max_mip_level as f32,
+            anisotropy_clamp: self.anisotropy_clamp.clamp(1, 16),
+            ..Default::default()
+        })
     }
 }
 
 /// Stores uploaded textures which will be automatically updated whenever necessary
 #[derive(Debug)]
 pub struct TextureManager {
-    pub default_sampler: Arc<wgpu::Sampler>,
+    /// Replaced by [TextureManager::set_sampler_options]
+    pub default_sampler: ArcSwap<wgpu::Sampler>,
 
     pub atlases: RwLock<HashMap<String, Atlas>>,
 }
@@ -225,74 +746,411 @@ pub struct TextureManager {
 impl TextureManager {
     #[must_use]
     pub fn new(wgpu_state: &Display) -> Self {
-        let sampler = wgpu_state.device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
         Self {
-            default_sampler: Arc::new(sampler),
+            default_sampler: ArcSwap::new(Arc::new(
+                SamplerOptions::default().create_sampler(&wgpu_state.device),
+            )),
             atlases: RwLock::new(HashMap::new()),
         }
     }
+
+    /// Replace the default sampler. Render graphs pick up the new one in
+    /// [crate::render::graph::RenderGraph::update_texture_resources]
+    pub fn set_sampler_options(&self, wgpu_state: &Display, options: SamplerOptions) {
+        self.default_sampler
+            .store(Arc::new(options.create_sampler(&wgpu_state.device)));
+    }
 }
 
+/// The offsets from an animated texture's first frame, in texels, to the frame shown and the frame after it, and
+/// how far to blend between the two. This is the layout of `UV` in the terrain shaders.
 #[repr(C)]
-#[derive(Copy, Clone, Zeroable, Pod)]
-#[allow(unused)]
-struct AnimatedUV {
+#[derive(Copy, Clone, Debug, PartialEq, Zeroable, Pod)]
+pub struct AnimatedUV {
     pub uv_1: [f32; 2],
     pub uv_2: [f32; 2],
     pub blend: f32,
     pub padding: f32,
 }
 
-// impl AnimatedTexture {
-//     pub fn new(width: u32, height: u32, real_width: f32, animation: AnimationData) -> Self {
-//         Self {
-//             width,
-//             height,
-//             frame_size: width,
-//             real_width,
-//             real_frame_size: real_width,
-//             animation,
-//             frame_count: height / width,
-//             subframe: 0,
-//         }
-//     }
-
-//     pub fn get_frame_size(&self) -> u32 {
-//         self.frame_size
-//     }
-
-//     pub fn update(&self, subframe: u32) -> [f32; 5] {
-
-//         //Due to padding in the buffer, some of these elements are always left as 0.0
-//         let mut out = [0.0; 5];
-//         let mut current_frame = (subframe / self.animation.frame_time) % self.frame_count;
-
-//         if self.animation.frames.is_some() { //if custom frame order is present translate to that
-//             current_frame = self.animation.frames.as_ref().unwrap()[current_frame as usize];
-//         }
-
-//         out[1] = self.real_frame_size * (current_frame as f32);
-
-//         if self.animation.interpolate {
-//             let mut next_frame = ((subframe / self.animation.frame_time) + 1) % self.frame_count;
-
-//             if self.animation.frames.is_some() { //if custom frame order is present translate to that
-//                 next_frame = self.animation.frames.as_ref().unwrap()[next_frame as usize];
-//             }
-
-//             out[3] = self.real_frame_size * (next_frame as f32);
-//             out[4] = ((subframe % self.animation.frame_time) as f32) / (self.animation.frame_time as f32);
-//         }
-
-//         out
-//     }
-// }
+/// A texture animated by its .mcmeta, stored in the atlas as a strip of frames read left to right, top to bottom
+#[derive(Clone, Debug)]
+pub struct AnimatedTexture {
+    /// Where the whole strip of frames is in the atlas
+    pub strip: UV,
+    pub frame_width: u16,
+    pub frame_height: u16,
+    /// The texels of padding on each side of every frame, which separate the frames in the strip
+    pub frame_padding: u16,
+    /// The index of each frame in the strip and how many ticks it's shown for, in the order they're played
+    pub frames: Vec<(u32, u32)>,
+    pub interpolate: bool,
+}
+
+impl AnimatedTexture {
+    /// Returns None if the strip doesn't hold a single frame
+    pub fn new(animation: &schemas::texture::TextureAnimation, strip: UV) -> Option<Self> {
+        let ((u1, v1), (u2, v2)) = strip;
+        let (width, height) = (u2 - u1, v2 - v1);
+
+        //Frames are square unless the .mcmeta says otherwise
+        let (frame_width, frame_height) = match (animation.width, animation.height) {
+            (Some(frame_width), Some(frame_height)) => (frame_width as u16, frame_height as u16),
+            (Some(frame_width), None) => (frame_width as u16, height),
+            (None, Some(frame_height)) => (width, frame_height as u16),
+            (None, None) => (width.min(height), width.min(height)),
+        };
+
+        if frame_width == 0 || frame_height == 0 {
+            return None;
+        }
+
+        let frame_count = (width / frame_width) as u32 * (height / frame_height) as u32;
+        let frametime = animation.frametime.max(1);
+
+        let frames = match &animation.frames {
+            Some(frames) => frames
+                .iter()
+                .map(|frame| match *frame {
+                    schemas::texture::AnimationFrame::Index(index) => (index, frametime),
+                    schemas::texture::AnimationFrame::Full { index, time } => (index, time.max(1)),
+                })
+                .filter(|&(index, _)| index < frame_count)
+                .collect(),
+            None => (0..frame_count)
+                .map(|index| (index, frametime))
+                .collect::<Vec<_>>(),
+        };
+
+        if frames.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            strip,
+            frame_width,
+            frame_height,
+            frame_padding: 0,
+            frames,
+            interpolate: animation.interpolate,
+        })
+    }
+
+    fn frame_position(&self, frame: u32) -> (u16, u16) {
+        let ((u1, v1), (u2, _)) = self.strip;
+        let (stride_u, stride_v) = (
+            self.frame_width + self.frame_padding * 2,
+            self.frame_height + self.frame_padding * 2,
+        );
+        let per_row = ((u2 - u1 + self.frame_padding * 2) / stride_u) as u32;
+
+        (
+            u1 + (frame % per_row) as u16 * stride_u,
+            v1 + (frame / per_row) as u16 * stride_v,
+        )
+    }
+
+    /// Where a frame of the strip is in the atlas
+    pub fn frame_uv(&self, frame: u32) -> UV {
+        let (u, v) = self.frame_position(frame);
+        ((u, v), (u + self.frame_width, v + self.frame_height))
+    }
+
+    fn frame_offset(&self, frame: u32) -> [f32; 2] {
+        let (u, v) = self.frame_position(frame);
+        let ((u1, v1), _) = self.strip;
+        [(u - u1) as f32, (v - v1) as f32]
+    }
+
+    /// The frame shown at `tick`, the frame after it, and how far into blending between them it is when the
+    /// animation interpolates
+    pub fn uv_at(&self, tick: u32) -> AnimatedUV {
+        let length = self.frames.iter().map(|&(_, time)| time).sum::<u32>();
+        let mut elapsed = tick % length;
+
+        let mut current = 0;
+        while elapsed >= self.frames[current].1 {
+            elapsed -= self.frames[current].1;
+            current += 1;
+        }
+
+        let (frame, time) = self.frames[current];
+        let (next_frame, _) = self.frames[(current + 1) % self.frames.len()];
+
+        AnimatedUV {
+            uv_1: self.frame_offset(frame),
+            uv_2: self.frame_offset(next_frame),
+            blend: if self.interpolate {
+                elapsed as f32 / time as f32
+            } else {
+                0.0
+            },
+            padding: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, MemoryResources};
+
+    fn png(size: u32, value: u8) -> Vec<u8> {
+        testing::png(&RgbaImage::from_pixel(
+            size,
+            size,
+            Rgba([value, value, value, 255]),
+        ))
+    }
+
+    #[test]
+    fn grows_to_fit() {
+        let Some(display) = testing::headless_display(16, 16) else {
+            return;
+        };
+        let atlas = Atlas::new(&display, true);
+
+        let small = ResourcePath::from("wgpu_mc:small");
+        let large = ResourcePath::from("wgpu_mc:large");
+        let (small_png, large_png) = (png(16, 10), png(INITIAL_ATLAS_SIZE, 20));
+
+        atlas
+            .allocate(
+                [(&small, &small_png), (&large, &large_png)],
+                &MemoryResources::default(),
+            )
+            .unwrap();
+
+        assert!(atlas.size() > INITIAL_ATLAS_SIZE);
+
+        //Growing keeps textures where they were
+        let padding = atlas.options().padding();
+        let uv_map = atlas.uv_map.read();
+        assert_eq!(
+            uv_map[&small],
+            (
+                (padding as u16, padding as u16),
+                (padding as u16 + 16, padding as u16 + 16)
+            )
+        );
+
+        let image = atlas.image.read();
+        let ((u, v), _) = uv_map[&large];
+        assert_eq!(image.get_pixel(0, 0).0, [10, 10, 10, 255]);
+        assert_eq!(image.get_pixel(u as u32, v as u32).0, [20, 20, 20, 255]);
+    }
+
+    #[test]
+    fn full_atlas() {
+        let Some(display) = testing::headless_display(16, 16) else {
+            return;
+        };
+        let atlas = Atlas::new(&display, false);
+
+        let path = ResourcePath::from("wgpu_mc:too_large");
+        let too_large = png(INITIAL_ATLAS_SIZE * 2, 0);
+
+        assert!(matches!(
+            atlas.allocate([(&path, &too_large)], &MemoryResources::default()),
+            Err(AtlasError::Full { .. })
+        ));
+        assert!(atlas.uv_map.read().is_empty());
+    }
+
+    #[test]
+    fn repack_keeps_contents() {
+        let Some(display) = testing::headless_display(16, 16) else {
+            return;
+        };
+        let atlas = Atlas::new(&display, true);
+
+        let paths = (0..4)
+            .map(|i| ResourcePath(format!("wgpu_mc:{i}")))
+            .collect::<Vec<_>>();
+        let pngs = [png(16, 1), png(64, 2), png(32, 3), png(128, 4)];
+
+        atlas
+            .allocate(paths.iter().zip(&pngs), &MemoryResources::default())
+            .unwrap();
+        let before = atlas.uv_map.read().clone();
+
+        assert!(atlas.repack(
+            &mut atlas.image.write(),
+            &mut atlas.uv_map.write(),
+            &mut atlas.allocator.write(),
+            &mut atlas.animated_textures.write(),
+            &atlas.animated_texture_offsets.read(),
+        ));
+
+        let uv_map = atlas.uv_map.read();
+        let image = atlas.image.read();
+        let remapped = atlas.remapped.lock();
+
+        for (path, value) in paths.iter().zip(1..) {
+            let ((u1, v1), (u2, v2)) = uv_map[path];
+            let ((old_u1, old_v1), (old_u2, old_v2)) = before[path];
+            assert_eq!((u2 - u1, v2 - v1), (old_u2 - old_u1, old_v2 - old_v1));
+            assert_eq!(
+                image.get_pixel(u1 as u32, v1 as u32).0,
+                [value, value, value, 255]
+            );
+
+            match remapped.get(path) {
+                Some(old_uv) => assert_eq!(*old_uv, before[path]),
+                None => assert_eq!(uv_map[path], before[path]),
+            }
+        }
+
+        //The largest texture goes first
+        let padding = atlas.options().padding() as u16;
+        assert_eq!(uv_map[&paths[3]].0, (padding, padding));
+    }
+
+    #[test]
+    fn padding_repeats_edges() {
+        let mut image = RgbaImage::new(8, 8);
+        image.put_pixel(2, 2, Rgba([1, 0, 0, 255]));
+        image.put_pixel(5, 2, Rgba([2, 0, 0, 255]));
+        image.put_pixel(2, 5, Rgba([3, 0, 0, 255]));
+        image.put_pixel(5, 5, Rgba([4, 0, 0, 255]));
+
+        pad_sprite(&mut image, 2, 2, 4, 4, 2);
+
+        assert_eq!(image.get_pixel(0, 0).0, [1, 0, 0, 255]);
+        assert_eq!(image.get_pixel(7, 0).0, [2, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 7).0, [3, 0, 0, 255]);
+        assert_eq!(image.get_pixel(7, 7).0, [4, 0, 0, 255]);
+        assert_eq!(image.get_pixel(3, 0).0, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn mips_ignore_transparent_colours() {
+        //A cutout texture with opaque white texels next to transparent black ones
+        let image = RgbaImage::from_fn(4, 4, |x, _| {
+            if x % 2 == 0 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+
+        let mips = generate_mips(&image, 4);
+        assert_eq!(mips.len(), 3);
+        assert_eq!(mips[1].dimensions(), (2, 2));
+        assert_eq!(mips[2].dimensions(), (1, 1));
+
+        assert_eq!(mips[1].get_pixel(0, 0).0, [255, 255, 255, 127]);
+        assert_eq!(mips[2].get_pixel(0, 0).0, [255, 255, 255, 127]);
+
+        assert_eq!(generate_mips(&image, 0).len(), 1);
+    }
+
+    #[test]
+    fn animation_frames() {
+        let animation: schemas::texture::TextureAnimation = serde_json::from_str(
+            r#"{ "frametime": 2, "interpolate": true, "frames": [3, { "index": 1, "time": 4 }, 7] }"#,
+        )
+        .unwrap();
+
+        //Four 16x16 frames, the out of range index is dropped
+        let strip = ((32, 48), (48, 112));
+        let animated = AnimatedTexture::new(&animation, strip).unwrap();
+        assert_eq!(animated.frames, [(3, 2), (1, 4)]);
+        assert_eq!(animated.frame_uv(0), ((32, 48), (48, 64)));
+
+        let uv = animated.uv_at(1);
+        assert_eq!(
+            (uv.uv_1, uv.uv_2, uv.blend),
+            ([0.0, 48.0], [0.0, 16.0], 0.5)
+        );
+
+        let uv = animated.uv_at(5);
+        assert_eq!(
+            (uv.uv_1, uv.uv_2, uv.blend),
+            ([0.0, 16.0], [0.0, 48.0], 0.75)
+        );
+
+        //The animation loops every 6 ticks
+        assert_eq!(animated.uv_at(7), animated.uv_at(1));
+
+        let still = AnimatedTexture {
+            interpolate: false,
+            ..animated
+        };
+        assert_eq!(still.uv_at(1).blend, 0.0);
+    }
+
+    #[test]
+    fn animated_strips() {
+        let Some(display) = testing::headless_display(16, 16) else {
+            return;
+        };
+        let atlas = Atlas::new(&display, true);
+
+        //Laid out like a resource pack, the atlas is given sprite names
+        let strip = RgbaImage::from_fn(16, 64, |_, y| Rgba([(y / 16) as u8, 0, 0, 255]));
+        let pack = MemoryResources::new([
+            (
+                "minecraft:textures/block/water_still.png",
+                testing::png(&strip),
+            ),
+            (
+                "minecraft:textures/block/water_still.png.mcmeta",
+                br#"{ "animation": {} }"#.to_vec(),
+            ),
+        ]);
+
+        let stone = ResourcePath::from("minecraft:block/stone");
+        let water = ResourcePath::from("minecraft:block/water_still");
+        let stone_png = png(16, 0);
+        let water_png = pack
+            .get_bytes(&water.prepend("textures/").append(".png"))
+            .unwrap();
+
+        atlas
+            .allocate([(&stone, &stone_png), (&water, &water_png)], &pack)
+            .unwrap();
+
+        let offsets = atlas.animated_texture_offsets.read();
+        assert_eq!(offsets.get(&water), Some(&1));
+        assert_eq!(offsets.get(&stone), None);
+
+        //Blocks are textured with the first frame
+        let ((u1, v1), (u2, v2)) = atlas.uv_map.read()[&water];
+        assert_eq!((u2 - u1, v2 - v1), (16, 16));
+
+        //Every frame is padded on its own
+        let padding = atlas.options().padding();
+        let stride = 16 + padding * 2;
+
+        let uvs = atlas.animated_uvs(2);
+        assert_eq!(uvs.len(), 2);
+        assert_eq!(uvs[0], AnimatedUV::zeroed());
+        assert_eq!(uvs[1].uv_1, [0.0, (stride * 2) as f32]);
+
+        let image = atlas.image.read();
+        let (u1, v1) = (u1 as u32, v1 as u32);
+        assert_eq!(image.get_pixel(u1, v1 + stride * 2).0, [2, 0, 0, 255]);
+        assert_eq!(image.get_pixel(u1, v1 + 16).0, [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(u1, v1 + stride - 1).0, [1, 0, 0, 255]);
+    }
+
+    #[test]
+    fn mips_update_in_place() {
+        let mut image = RgbaImage::from_pixel(64, 64, Rgba([10, 20, 30, 255]));
+        let mut mips = generate_mips(&image, 4);
+        mips.remove(0);
+
+        for y in 16..32 {
+            for x in 32..48 {
+                image.put_pixel(x, y, Rgba([x as u8, y as u8, 0, (x * y % 256) as u8]));
+            }
+        }
+
+        let regions = update_mips(&image, &mut mips, (32, 16, 16, 16));
+        assert_eq!(regions[0], (16, 8, 8, 8));
+        assert_eq!(regions[3], (2, 1, 1, 1));
+
+        assert_eq!(mips, generate_mips(&image, 4)[1..]);
+    }
+}
@@ -0,0 +1,108 @@
+//! The point of view terrain and entities are rendered and culled from
+//!
+//! Terrain is rendered relative to the origin of the section the camera is in, so the camera is split into the
+//! horizontal position of that section and a set of matrices which take terrain space to clip space.
+
+use glam::{ivec3, IVec2, IVec3, Mat4, Vec3};
+use treeculler::{BVol, Frustum, AABB};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    /// Horizontal position of the section the camera is in
+    pub section_pos: IVec2,
+    /// Takes terrain space into the space `view` expects, e.g. Minecraft's terrain transformation
+    pub model: Mat4,
+    pub view: Mat4,
+    pub projection: Mat4,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            section_pos: IVec2::ZERO,
+            model: Mat4::IDENTITY,
+            view: Mat4::IDENTITY,
+            projection: Mat4::IDENTITY,
+        }
+    }
+}
+
+impl Camera {
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection * self.view * self.model
+    }
+
+    /// The camera position relative to the origin of the camera section, which is the space terrain is rendered in.
+    /// The camera sits at the origin of view space, so this maps that point back into terrain space.
+    pub fn position(&self) -> Vec3 {
+        (self.view * self.model)
+            .inverse()
+            .transform_point3(Vec3::ZERO)
+    }
+
+    /// The section the camera is in, including its height
+    pub fn section(&self) -> IVec3 {
+        ivec3(
+            self.section_pos.x,
+            (self.position().y / 16.0).floor() as i32,
+            self.section_pos.y,
+        )
+    }
+
+    /// A section's position relative to the camera section, the offset terrain shaders are given
+    pub fn relative_section_pos(&self, section: IVec3) -> IVec3 {
+        ivec3(
+            section.x - self.section_pos.x,
+            section.y,
+            section.z - self.section_pos.y,
+        )
+    }
+
+    /// The view frustum in terrain space
+    pub fn frustum(&self) -> Frustum<f32> {
+        Frustum::from_modelview_projection(self.view_projection().to_cols_array_2d())
+    }
+
+    /// Whether an axis-aligned box in terrain space intersects the view frustum
+    pub fn is_box_visible(frustum: &Frustum<f32>, min: Vec3, max: Vec3) -> bool {
+        AABB::new(min.to_array(), max.to_array())
+            .coherent_test_against_frustum(frustum, 0)
+            .0
+    }
+
+    /// Whether a section, relative to the camera section, intersects the view frustum
+    pub fn is_section_visible(frustum: &Frustum<f32>, relative_section_pos: IVec3) -> bool {
+        let min = relative_section_pos.as_vec3() * 16.0;
+        Self::is_box_visible(frustum, min, min + 16.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera {
+            section_pos: IVec2::new(10, -3),
+            model: Mat4::IDENTITY,
+            view: Mat4::look_at_rh(
+                Vec3::new(8.0, 70.0, 8.0),
+                Vec3::new(8.0, 70.0, 100.0),
+                Vec3::Y,
+            ),
+            projection: Mat4::perspective_rh(90f32.to_radians(), 1.0, 0.05, 1000.0),
+        }
+    }
+
+    #[test]
+    fn position_and_section() {
+        let camera = camera();
+
+        assert!(camera.position().distance(Vec3::new(8.0, 70.0, 8.0)) < 1e-3);
+        assert_eq!(camera.section(), IVec3::new(10, 4, -3));
+        assert_eq!(
+            camera.relative_section_pos(IVec3::new(12, 4, -3)),
+            IVec3::new(2, 4, 0)
+        );
+    }
+}
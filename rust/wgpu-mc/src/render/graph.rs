@@ -1,9 +1,10 @@
-use glam::ivec3;
+use glam::{IVec3, Mat3, Mat4};
 use linked_hash_map::LinkedHashMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Mul;
 use std::sync::Arc;
-use treeculler::{BVol, Frustum, Vec3, AABB};
 
+use wgpu::util::{BufferInitDescriptor, DeviceExt, DrawIndexedIndirectArgs};
 use wgpu::{
     Color, LoadOp, Operations, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
     RenderPassDescriptor, SamplerBindingType, ShaderStages, StoreOp,
@@ -13,14 +14,17 @@ use crate::mc::chunk::RenderLayer;
 use crate::mc::entity::InstanceVertex;
 use crate::mc::resource::ResourcePath;
 use crate::mc::Scene;
+use crate::render::camera::Camera;
 use crate::render::entity::EntityVertex;
 use crate::render::pipeline::{QuadVertex, BLOCK_ATLAS};
 use crate::render::shader::WgslShader;
 use crate::render::shaderpack::{
-    BindGroupDef, LonghandResourceConfig, PipelineConfig, ShaderPackConfig,
-    ShorthandResourceConfig, TypeResourceConfig,
+    BindGroupDef, CommonResourceConfig, GraphDiagnostic, LonghandResourceConfig, Mat3ValueOrMult,
+    Mat4ValueOrMult, PipelineConfig, RenderTargetConfig, ShaderPackConfig, ShorthandResourceConfig,
+    TypeResourceConfig,
 };
 use crate::render::sky::{SkyVertex, SunMoonVertex};
+use crate::render::terrain::{TerrainDraw, TerrainDrawBuffers, INDIRECT_DRAW_FEATURES};
 use crate::texture::TextureAndView;
 use crate::util::WmArena;
 use crate::WmRenderer;
@@ -41,6 +45,9 @@ pub enum ResourceBacking {
     Buffer(Arc<wgpu::Buffer>, wgpu::BufferBindingType),
     BufferArray(Vec<Arc<wgpu::Buffer>>),
     Texture2D(Arc<TextureAndView>),
+    /// Bound as a storage texture with the given access, or as a sampled texture.
+    /// Textures with storage access are still sampled by shaders which don't declare a storage texture.
+    Texture3D(Arc<TextureAndView>, Option<wgpu::StorageTextureAccess>),
     Sampler(Arc<wgpu::Sampler>),
 }
 
@@ -68,50 +75,89 @@ impl ResourceBacking {
                 },
                 count: None,
             },
-            ResourceBacking::Texture2D(_) => wgpu::BindGroupLayoutEntry {
+            ResourceBacking::Texture2D(texture) => wgpu::BindGroupLayoutEntry {
                 binding,
-                visibility: wgpu::ShaderStages::FRAGMENT,
+                //Vertex shaders read the size of atlases to normalize UVs
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Texture {
-                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    sample_type: texture
+                        .format
+                        .sample_type(None, None)
+                        .unwrap_or(wgpu::TextureSampleType::Float { filterable: false }),
                     view_dimension: wgpu::TextureViewDimension::D2,
                     multisampled: false,
                 },
                 count: None,
             },
+            ResourceBacking::Texture3D(_, None) => sampled_texture_3d_layout_entry(binding),
+            ResourceBacking::Texture3D(texture, Some(access)) => wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: *access,
+                    format: texture.format,
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                },
+                count: None,
+            },
             ResourceBacking::Sampler(_) => wgpu::BindGroupLayoutEntry {
                 binding,
                 visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Sampler(SamplerBindingType::NonFiltering),
+                ty: wgpu::BindingType::Sampler(SamplerBindingType::Filtering),
                 count: None,
             },
         }
     }
 
+    /// The layout entry for a shader which declares the binding as `storage` or not
+    pub fn get_shader_layout_entry(
+        &self,
+        binding: u32,
+        storage: bool,
+    ) -> wgpu::BindGroupLayoutEntry {
+        match self {
+            ResourceBacking::Texture3D(_, Some(_)) if !storage => {
+                sampled_texture_3d_layout_entry(binding)
+            }
+            _ => self.get_bind_group_layout_entry(binding),
+        }
+    }
+
     pub fn get_bind_group_entries(&self, index: u32) -> Vec<wgpu::BindGroupEntry> {
         match self {
             ResourceBacking::Buffer(buffer, _buffer_ty) => vec![wgpu::BindGroupEntry {
                 binding: index,
                 resource: wgpu::BindingResource::Buffer(buffer.as_entire_buffer_binding()),
             }],
-            ResourceBacking::Texture2D(texture) => vec![wgpu::BindGroupEntry {
-                binding: index,
-                resource: wgpu::BindingResource::TextureView(&texture.view),
-            }],
+            ResourceBacking::Texture2D(texture) | ResourceBacking::Texture3D(texture, _) => {
+                vec![wgpu::BindGroupEntry {
+                    binding: index,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                }]
+            }
             ResourceBacking::Sampler(sampler) => vec![wgpu::BindGroupEntry {
                 binding: index,
                 resource: wgpu::BindingResource::Sampler(sampler),
             }],
-            // RenderResource::TextureHandle(handle) => vec![
-            //     wgpu::BindGroupEntry {
-            //         binding: index,
-            //         resource: wgpu::BindingResource::TextureView(handle.),
-            //     }
-            // ],
-            _ => todo!(),
+            //Rejected by RenderGraph::validate
+            ResourceBacking::BufferArray(_) => Vec::new(),
         }
     }
 }
 
+fn sampled_texture_3d_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D3,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
 #[derive(Debug)]
 pub enum WmBindGroup {
     Resource(String),
@@ -125,11 +171,460 @@ pub struct BoundPipeline {
     pub config: PipelineConfig,
 }
 
+/// The value of a `mat3` or `mat4` resource
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatrixValue {
+    Mat3(Mat3),
+    Mat4(Mat4),
+}
+
+impl MatrixValue {
+    /// The matrix laid out as a WGSL uniform, where each column of a `mat3x3<f32>` is padded to 16 bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            MatrixValue::Mat3(matrix) => bytemuck::cast_slice(
+                &matrix
+                    .to_cols_array_2d()
+                    .map(|[x, y, z]| [x, y, z, 0.0])
+                    .concat(),
+            )
+            .to_vec(),
+            MatrixValue::Mat4(matrix) => bytemuck::cast_slice(&matrix.to_cols_array()).to_vec(),
+        }
+    }
+}
+
+/// Values of the graph's matrix resources. `mult` matrices are computed from the camera's matrices,
+/// `@mat4_model`, `@mat4_view` and `@mat4_perspective`, and the matrices declared before them.
+pub fn matrix_values<'a>(
+    config: &'a ShaderPackConfig,
+    camera: &Camera,
+) -> Result<HashMap<&'a str, MatrixValue>, GraphDiagnostic> {
+    let mut values = HashMap::from([
+        ("@mat4_model", MatrixValue::Mat4(camera.model)),
+        ("@mat4_view", MatrixValue::Mat4(camera.view)),
+        ("@mat4_perspective", MatrixValue::Mat4(camera.projection)),
+    ]);
+
+    for (resource_id, resource) in &config.resources.resources {
+        let typed = match resource {
+            ShorthandResourceConfig::Longhand(LonghandResourceConfig { typed, .. }) => Some(typed),
+            _ => None,
+        };
+
+        let value = match (resource, typed) {
+            (ShorthandResourceConfig::Mat3(value), _)
+            | (_, Some(TypeResourceConfig::Mat3(Mat3ValueOrMult::Value { value }))) => {
+                MatrixValue::Mat3(Mat3::from_cols_array_2d(value))
+            }
+            (ShorthandResourceConfig::Mat4(value), _)
+            | (_, Some(TypeResourceConfig::Mat4(Mat4ValueOrMult::Value { value }))) => {
+                MatrixValue::Mat4(Mat4::from_cols_array_2d(value))
+            }
+            (_, Some(TypeResourceConfig::Mat3(Mat3ValueOrMult::Mult { mult }))) => {
+                MatrixValue::Mat3(multiply_matrices(
+                    config,
+                    &values,
+                    resource_id,
+                    mult,
+                    Mat3::IDENTITY,
+                    |value| match value {
+                        MatrixValue::Mat3(matrix) => Some(matrix),
+                        MatrixValue::Mat4(_) => None,
+                    },
+                )?)
+            }
+            (_, Some(TypeResourceConfig::Mat4(Mat4ValueOrMult::Mult { mult }))) => {
+                MatrixValue::Mat4(multiply_matrices(
+                    config,
+                    &values,
+                    resource_id,
+                    mult,
+                    Mat4::IDENTITY,
+                    |value| match value {
+                        MatrixValue::Mat4(matrix) => Some(matrix),
+                        MatrixValue::Mat3(_) => None,
+                    },
+                )?)
+            }
+            _ => continue,
+        };
+
+        values.insert(resource_id, value);
+    }
+
+    Ok(values)
+}
+
+fn multiply_matrices<M: Copy + Mul<Output = M>>(
+    config: &ShaderPackConfig,
+    values: &HashMap<&str, MatrixValue>,
+    resource_id: &str,
+    mult: &[String],
+    identity: M,
+    get: impl Fn(MatrixValue) -> Option<M>,
+) -> Result<M, GraphDiagnostic> {
+    mult.iter().try_fold(identity, |product, name| {
+        let matrix = values
+            .get(&name[..])
+            .copied()
+            .and_then(&get)
+            .ok_or_else(|| {
+                config.diagnostic(
+                    &["resources", resource_id, "mult"],
+                    format!("{name} isn't a matrix of the same size declared before {resource_id}"),
+                )
+            })?;
+
+        Ok(matrix * product)
+    })
+}
+
+fn uniform_buffer(wm: &WmRenderer, label: &str, contents: &[u8]) -> ResourceBacking {
+    ResourceBacking::Buffer(
+        Arc::new(wm.display.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some(label),
+            contents,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        })),
+        wgpu::BufferBindingType::Uniform,
+    )
+}
+
+/// Clamps a value to the range an option was declared with, if any
+fn clamp_to_range<T: PartialOrd + Copy>(value: T, [min, max]: [T; 2]) -> T {
+    if min < max && value < min {
+        min
+    } else if min < max && value > max {
+        max
+    } else {
+        value
+    }
+}
+
+/// Rows of texture copies have to be aligned, so cleared 3d textures are copied from a zeroed buffer laid out like this
+fn texture_3d_bytes_per_row(width: u32) -> u32 {
+    (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+}
+
+/// Terrain geometry is drawn by the graph, which binds the `@bg_ssbo_*` bind groups and `@pc_section_position`
+const TERRAIN_GEOMETRY: [&str; 3] = [
+    "@geo_terrain",
+    "@geo_terrain_cutout",
+    "@geo_terrain_transparent",
+];
+
+/// Resources every graph has besides the ones it declares and is given
+const BUILTIN_RESOURCES: [&str; 3] = ["@texture_block_atlas", "@sampler", "@sampler_linear"];
+
+/// The intermediate render targets `@post_output` and `@post_input` are resolved to, see [resolve_post_textures]
+const POST_TEXTURES: [&str; 2] = ["@texture_post_a", "@texture_post_b"];
+
+/// Two triangles covering the whole framebuffer, drawn for `@geo_quad`
+const FULLSCREEN_QUAD: [QuadVertex; 6] = [
+    QuadVertex {
+        position: [-1.0, -1.0],
+    },
+    QuadVertex {
+        position: [1.0, -1.0],
+    },
+    QuadVertex {
+        position: [1.0, 1.0],
+    },
+    QuadVertex {
+        position: [-1.0, -1.0],
+    },
+    QuadVertex {
+        position: [1.0, 1.0],
+    },
+    QuadVertex {
+        position: [-1.0, 1.0],
+    },
+];
+
+/// Vertex buffers of the built-in geometry, terrain is read from storage buffers instead
+fn builtin_vertex_layouts(geometry: &str) -> Option<Vec<wgpu::VertexBufferLayout<'static>>> {
+    match geometry {
+        "@geo_terrain" | "@geo_terrain_cutout" | "@geo_terrain_transparent" => Some(Vec::new()),
+        "@geo_entities" => Some(vec![EntityVertex::desc(), InstanceVertex::desc()]),
+        "@geo_quad" => Some(vec![QuadVertex::desc()]),
+        "@geo_sun_moon" => Some(vec![SunMoonVertex::desc()]),
+        "@geo_sky_scatter" | "@geo_sky_stars" | "@geo_sky_fog" => Some(vec![SkyVertex::desc()]),
+        _ => None,
+    }
+}
+
+/// The layout in [WmRenderer::bind_group_layouts] of a built-in bind group
+fn builtin_bind_group_layout(resource: &str) -> Option<&'static str> {
+    match resource {
+        "@bg_ssbo_chunks" | "@bg_ssbo_terrain_draws" | "@bg_ssbo_animated_uvs" => Some("ssbo"),
+        "@bg_entity" => Some("entity"),
+        _ => None,
+    }
+}
+
+fn push_constant_range(name: &str, offset: u32) -> Option<wgpu::PushConstantRange> {
+    let (stages, size) = match name {
+        "@pc_mat4_model" => (wgpu::ShaderStages::VERTEX, 64),
+        "@pc_section_position" => (wgpu::ShaderStages::VERTEX, 12),
+        "@pc_total_sections" => (wgpu::ShaderStages::VERTEX, 4),
+        "@pc_parts_per_entity" => (wgpu::ShaderStages::VERTEX, 4),
+        "@pc_electrum_color" => (wgpu::ShaderStages::FRAGMENT, 16),
+        _ => return None,
+    };
+
+    Some(wgpu::PushConstantRange {
+        stages,
+        range: offset..offset + size,
+    })
+}
+
+fn blend_state(name: &str) -> Option<wgpu::BlendState> {
+    match name {
+        "alpha_blending" => Some(wgpu::BlendState::ALPHA_BLENDING),
+        "premultiplied_alpha_blending" => Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+        "replace" => Some(wgpu::BlendState::REPLACE),
+        "color_add_alpha_blending" => Some(wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::Zero,
+                operation: wgpu::BlendOperation::Add,
+            },
+        }),
+        _ => None,
+    }
+}
+
+/// Formats render targets can be declared with
+pub fn render_target_format(name: &str) -> Option<wgpu::TextureFormat> {
+    Some(match name {
+        "r8unorm" => wgpu::TextureFormat::R8Unorm,
+        "rg8unorm" => wgpu::TextureFormat::Rg8Unorm,
+        "rgba8unorm" => wgpu::TextureFormat::Rgba8Unorm,
+        "rgba8unorm_srgb" => wgpu::TextureFormat::Rgba8UnormSrgb,
+        "bgra8unorm" => wgpu::TextureFormat::Bgra8Unorm,
+        "bgra8unorm_srgb" => wgpu::TextureFormat::Bgra8UnormSrgb,
+        "rgb10a2unorm" => wgpu::TextureFormat::Rgb10a2Unorm,
+        "rg11b10float" => wgpu::TextureFormat::Rg11b10Float,
+        "r16float" => wgpu::TextureFormat::R16Float,
+        "rg16float" => wgpu::TextureFormat::Rg16Float,
+        "rgba16float" => wgpu::TextureFormat::Rgba16Float,
+        "r32float" => wgpu::TextureFormat::R32Float,
+        "rg32float" => wgpu::TextureFormat::Rg32Float,
+        "rgba32float" => wgpu::TextureFormat::Rgba32Float,
+        _ => return None,
+    })
+}
+
+fn is_blendable(format: wgpu::TextureFormat) -> bool {
+    format
+        .guaranteed_format_features(wgpu::Features::empty())
+        .flags
+        .contains(wgpu::TextureFormatFeatureFlags::BLENDABLE)
+}
+
+fn binds_resource(pipeline: &PipelineConfig, resource: &str) -> bool {
+    pipeline.bind_groups.values().any(|def| {
+        matches!(def, BindGroupDef::Entries(entries) if entries.values().any(|resource_id| resource_id == resource))
+    })
+}
+
+/// Post-processing passes write `@post_output` and read what the last pass before them wrote as `@post_input`.
+/// Both are replaced by one of two render targets the size of the framebuffer, a pass reading and writing them
+/// renders into the one it doesn't read, so a chain of passes ping-pongs between the two.
+fn resolve_post_textures(mut config: ShaderPackConfig) -> ShaderPackConfig {
+    let mut current = 0;
+    let mut used = false;
+
+    for (_, pipeline) in config.pipelines.pipelines.iter_mut() {
+        let reads = binds_resource(pipeline, "@post_input");
+        let writes = pipeline
+            .output
+            .iter()
+            .any(|texture| texture == "@post_output");
+
+        for (_, def) in pipeline.bind_groups.iter_mut() {
+            if let BindGroupDef::Entries(entries) = def {
+                for (_, resource_id) in entries.iter_mut() {
+                    if resource_id == "@post_input" {
+                        *resource_id = POST_TEXTURES[current].into();
+                    }
+                }
+            }
+        }
+
+        if reads && writes {
+            current = 1 - current;
+        }
+
+        for texture in &mut pipeline.output {
+            if texture == "@post_output" {
+                *texture = POST_TEXTURES[current].into();
+            }
+        }
+
+        used |= writes;
+    }
+
+    if used {
+        for name in POST_TEXTURES {
+            config.resources.resources.insert(
+                name.into(),
+                ShorthandResourceConfig::Longhand(LonghandResourceConfig {
+                    common: CommonResourceConfig {
+                        desc: "Post-processing target".into(),
+                        show: false,
+                    },
+                    typed: TypeResourceConfig::RenderTarget(RenderTargetConfig {
+                        format: None,
+                        size: None,
+                        scale: 1.0,
+                        clear_after_frame: true,
+                        clear_color: [0.0; 4],
+                    }),
+                }),
+            );
+        }
+    }
+
+    config
+}
+
+/// The size of an attachment, as far as it's known before the graph is created
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AttachmentSize {
+    Fixed([u32; 2]),
+    /// Relative to the framebuffer
+    Scaled(f32),
+}
+
+/// Sizes of resources passed to [RenderGraph::new] aren't known, the caller might resize them with the framebuffer
+fn attachment_size(config: &ShaderPackConfig, name: &str) -> Option<AttachmentSize> {
+    match config.resources.resources.get(name) {
+        Some(ShorthandResourceConfig::Longhand(LonghandResourceConfig { typed, .. })) => {
+            match typed {
+                TypeResourceConfig::RenderTarget(RenderTargetConfig {
+                    size: Some(size), ..
+                })
+                | TypeResourceConfig::TextureDepth {
+                    size: Some(size), ..
+                } => Some(AttachmentSize::Fixed(*size)),
+                TypeResourceConfig::RenderTarget(target) => {
+                    Some(AttachmentSize::Scaled(target.scale))
+                }
+                TypeResourceConfig::TextureDepth { size: None, .. } => {
+                    Some(AttachmentSize::Scaled(1.0))
+                }
+                _ => None,
+            }
+        }
+        Some(_) => None,
+        None => matches!(
+            name,
+            "@framebuffer_texture" | "@post_output" | "@texture_depth"
+        )
+        .then_some(AttachmentSize::Scaled(1.0)),
+    }
+}
+
+fn render_target_config<'a>(
+    config: &'a ShaderPackConfig,
+    name: &str,
+) -> Option<&'a RenderTargetConfig> {
+    match config.resources.resources.get(name) {
+        Some(ShorthandResourceConfig::Longhand(LonghandResourceConfig {
+            typed: TypeResourceConfig::RenderTarget(target),
+            ..
+        })) => Some(target),
+        _ => None,
+    }
+}
+
+fn create_render_target(
+    wm: &WmRenderer,
+    label: &str,
+    target: &RenderTargetConfig,
+    framebuffer_size: [u32; 2],
+) -> TextureAndView {
+    let [width, height] = target.size_for(framebuffer_size);
+    let format = target
+        .format
+        .as_deref()
+        .and_then(render_target_format)
+        .unwrap_or_else(|| wm.display.config.read().format);
+
+    let texture = wm.display.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+
+    TextureAndView {
+        view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        texture,
+        format,
+    }
+}
+
+fn create_depth_texture(wm: &WmRenderer, label: &str, [width, height]: [u32; 2]) -> TextureAndView {
+    let texture = wm.display.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureAndView::DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+
+    TextureAndView {
+        view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        texture,
+        format: TextureAndView::DEPTH_FORMAT,
+    }
+}
+
+fn load_image(wm: &WmRenderer, src: &str) -> Result<image::RgbaImage, String> {
+    let bytes = wm
+        .mc
+        .resource_provider
+        .get_bytes(&ResourcePath::from(src))
+        .ok_or_else(|| format!("Couldn't find {src}"))?;
+
+    image::load_from_memory(&bytes)
+        .map(|image| image.to_rgba8())
+        .map_err(|error| format!("Couldn't load {src}: {error}"))
+}
+
 #[derive(Debug)]
 pub struct RenderGraph {
     pub config: ShaderPackConfig,
     pub pipelines: LinkedHashMap<String, BoundPipeline>,
     pub resources: HashMap<String, ResourceBacking>,
+    /// 3d textures declared with `clear_after_frame` and the zeroed buffers they're cleared from
+    frame_cleared_textures: Vec<(Arc<TextureAndView>, wgpu::Buffer)>,
+    quad_vertices: wgpu::Buffer,
 }
 
 impl RenderGraph {
@@ -138,233 +633,526 @@ impl RenderGraph {
         wm: &WmRenderer,
         custom_bind_groups: Option<HashMap<String, &wgpu::BindGroupLayout>>,
         geometry_vertex_layouts: Option<HashMap<String, Vec<wgpu::VertexBufferLayout>>>,
-    ) {
+    ) -> Result<(), Vec<GraphDiagnostic>> {
         self.pipelines.clear();
 
-        let arena = WmArena::new(1024);
+        let mut diagnostics = Vec::new();
 
         for (pipeline_name, pipeline_config) in &self.config.pipelines.pipelines {
-            let bind_group_layouts = pipeline_config
+            //Shaders which don't match what the pack binds are only caught by wgpu
+            wm.display
+                .device
+                .push_error_scope(wgpu::ErrorFilter::Validation);
+            let pipeline = self.create_pipeline(
+                wm,
+                pipeline_name,
+                pipeline_config,
+                custom_bind_groups.as_ref(),
+                geometry_vertex_layouts.as_ref(),
+            );
+            let error = futures::executor::block_on(wm.display.device.pop_error_scope());
+
+            match (pipeline, error) {
+                (Err(diagnostic), _) => diagnostics.push(diagnostic),
+                (Ok(_), Some(error)) => diagnostics.push(
+                    self.config
+                        .diagnostic(&["pipelines", pipeline_name], error.to_string()),
+                ),
+                (Ok(pipeline), None) => {
+                    self.pipelines.insert(pipeline_name.clone(), pipeline);
+                }
+            }
+        }
+
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    fn create_pipeline(
+        &self,
+        wm: &WmRenderer,
+        pipeline_name: &str,
+        pipeline_config: &PipelineConfig,
+        custom_bind_groups: Option<&HashMap<String, &wgpu::BindGroupLayout>>,
+        geometry_vertex_layouts: Option<&HashMap<String, Vec<wgpu::VertexBufferLayout>>>,
+    ) -> Result<BoundPipeline, GraphDiagnostic> {
+        let arena = WmArena::new(1024);
+
+        let diagnostic = |keys: &[&str], message: String| {
+            let path = ["pipelines", pipeline_name]
+                .into_iter()
+                .chain(keys.iter().copied())
+                .collect::<Vec<_>>();
+            self.config.diagnostic(&path, message)
+        };
+
+        let shader_path = ResourcePath(format!(
+            "wgpu_mc:shaders/{}.wgsl",
+            pipeline_config.shader.as_deref().unwrap_or(pipeline_name)
+        ));
+        let shader = WgslShader::init(
+            &shader_path,
+            &*wm.mc.resource_provider,
+            &wm.display.device,
+            pipeline_config.frag_entry.clone(),
+            "vert".into(),
+        )
+        .ok_or_else(|| diagnostic(&[], format!("Couldn't load shader {shader_path}")))?;
+
+        let (bind_group_layouts, wm_bind_groups): (Vec<&wgpu::BindGroupLayout>, Vec<_>) =
+            pipeline_config
                 .bind_groups
                 .iter()
-                .map(|(_slot, def)| match def {
-                    BindGroupDef::Entries(entries) => {
-                        let layout_entries = entries
-                            .iter()
-                            .map(|(index, resource_id)| {
-                                let resource = self.resources.get(resource_id).unwrap();
-                                resource.get_bind_group_layout_entry(*index as u32)
-                            })
-                            .collect::<Vec<wgpu::BindGroupLayoutEntry>>();
+                .map(|(slot, def)| {
+                    let slot_key = slot.to_string();
 
-                        &*arena.alloc(wm.display.device.create_bind_group_layout(
-                            &wgpu::BindGroupLayoutDescriptor {
-                                label: None,
-                                entries: &layout_entries,
-                            },
-                        ))
-                    }
-                    BindGroupDef::Resource(resource) => {
-                        match (&resource[..], &custom_bind_groups) {
-                            ("@bg_ssbo_chunks", _) => wm.bind_group_layouts.get("ssbo").unwrap(),
-                            ("@bg_entity", _) => wm.bind_group_layouts.get("entity").unwrap(),
-                            (_, Some(custom)) => {
-                                if let Some(entry) = custom.get(resource) {
-                                    entry
-                                } else {
-                                    unimplemented!("{}", resource)
-                                }
+                    match def {
+                        BindGroupDef::Entries(entries) => {
+                            let resources = entries
+                                .iter()
+                                .map(|(index, resource_id)| {
+                                    let resource =
+                                        self.resources.get(resource_id).ok_or_else(|| {
+                                            diagnostic(
+                                                &["bind_groups", &slot_key, &index.to_string()],
+                                                format!("Unknown resource {resource_id}"),
+                                            )
+                                        })?;
+                                    Ok((*index as u32, resource))
+                                })
+                                .collect::<Result<Vec<_>, GraphDiagnostic>>()?;
+
+                            let layout_entries = resources
+                                .iter()
+                                .map(|(index, resource)| {
+                                    resource.get_shader_layout_entry(
+                                        *index,
+                                        shader.storage_textures.contains(&(*slot as u32, *index)),
+                                    )
+                                })
+                                .collect::<Vec<wgpu::BindGroupLayoutEntry>>();
+
+                            let layout = &*arena.alloc(wm.display.device.create_bind_group_layout(
+                                &wgpu::BindGroupLayoutDescriptor {
+                                    label: None,
+                                    entries: &layout_entries,
+                                },
+                            ));
+
+                            let entries = resources
+                                .iter()
+                                .flat_map(|(index, resource)| {
+                                    resource.get_bind_group_entries(*index)
+                                })
+                                .collect::<Vec<wgpu::BindGroupEntry>>();
+
+                            let bind_group =
+                                wm.display
+                                    .device
+                                    .create_bind_group(&wgpu::BindGroupDescriptor {
+                                        label: None,
+                                        layout,
+                                        entries: &entries,
+                                    });
+
+                            Ok((layout, (*slot as u32, WmBindGroup::Custom(bind_group))))
+                        }
+                        BindGroupDef::Resource(resource) => {
+                            let layout = match builtin_bind_group_layout(resource) {
+                                Some(layout) => wm.bind_group_layouts.get(layout),
+                                None => custom_bind_groups
+                                    .and_then(|custom| custom.get(resource).copied()),
                             }
-                            (_, None) => unimplemented!(),
+                            .ok_or_else(|| {
+                                diagnostic(
+                                    &["bind_groups", &slot_key],
+                                    format!("Unknown bind group {resource}"),
+                                )
+                            })?;
+
+                            Ok((
+                                layout,
+                                (*slot as u32, WmBindGroup::Resource(resource.clone())),
+                            ))
                         }
                     }
                 })
-                .collect::<Vec<&wgpu::BindGroupLayout>>();
+                .collect::<Result<Vec<_>, GraphDiagnostic>>()?
+                .into_iter()
+                .unzip();
 
-            let wm_bind_groups = pipeline_config
-                .bind_groups
-                .iter()
-                .enumerate()
-                .map(|(vec_index, (slot, def))| match def {
-                    BindGroupDef::Entries(entries) => {
-                        let entries = entries
+        let push_constants = pipeline_config
+            .push_constants
+            .iter()
+            .filter_map(|(index, name)| push_constant_range(name, *index as u32))
+            .collect::<Vec<wgpu::PushConstantRange>>();
+
+        let layout = wm
+            .display
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &push_constants,
+            });
+
+        let vertex_buffer = builtin_vertex_layouts(&pipeline_config.geometry)
+            .or_else(|| {
+                geometry_vertex_layouts
+                    .as_ref()?
+                    .get(&pipeline_config.geometry)
+                    .cloned()
+            })
+            .unwrap_or_default();
+
+        let label = pipeline_name.to_string();
+
+        let render_pipeline =
+            wm.display
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(&label),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader.module,
+                        entry_point: &shader.vert_entry,
+                        compilation_options: Default::default(),
+                        buffers: &vertex_buffer,
+                    },
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: Some(wgpu::Face::Back),
+                        unclipped_depth: false,
+                        polygon_mode: Default::default(),
+                        conservative: false,
+                    },
+                    depth_stencil: pipeline_config.depth.as_ref().map(|_| {
+                        wgpu::DepthStencilState {
+                            format: wgpu::TextureFormat::Depth32Float,
+                            depth_write_enabled: pipeline_config.depth_write,
+                            depth_compare: wgpu::CompareFunction::Less,
+                            stencil: wgpu::StencilState::default(),
+                            bias: Default::default(),
+                        }
+                    }),
+                    multisample: Default::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader.module,
+                        entry_point: &shader.frag_entry,
+                        compilation_options: Default::default(),
+                        targets: &pipeline_config
+                            .output
                             .iter()
-                            .flat_map(|(index, resource_id)| {
-                                let resource = self.resources.get(resource_id).unwrap();
-                                resource.get_bind_group_entries(*index as u32)
+                            .map(|texture_name| {
+                                let format = match self.resources.get(texture_name) {
+                                    Some(ResourceBacking::Texture2D(texture))
+                                        if texture_name != "@framebuffer_texture" =>
+                                    {
+                                        texture.format
+                                    }
+                                    _ => wm.display.config.read().format,
+                                };
+
+                                Some(wgpu::ColorTargetState {
+                                    format,
+                                    //Float targets like rgba32float only support `blending: replace`
+                                    blend: blend_state(&pipeline_config.blending)
+                                        .filter(|_| is_blendable(format)),
+                                    write_mask: Default::default(),
+                                })
                             })
-                            .collect::<Vec<wgpu::BindGroupEntry>>();
+                            .collect::<Vec<_>>(),
+                    }),
+                    multiview: None,
+                    cache: None,
+                });
 
-                        let bind_group =
-                            wm.display
-                                .device
-                                .create_bind_group(&wgpu::BindGroupDescriptor {
-                                    label: None,
-                                    layout: bind_group_layouts[vec_index],
-                                    entries: &entries,
-                                });
+        Ok(BoundPipeline {
+            pipeline: render_pipeline,
+            bind_groups: wm_bind_groups,
+            config: pipeline_config.clone(),
+        })
+    }
 
-                        (*slot as u32, WmBindGroup::Custom(bind_group))
-                    }
-                    BindGroupDef::Resource(resource) => {
-                        (*slot as u32, WmBindGroup::Resource(resource.clone()))
-                    }
-                })
-                .collect::<Vec<(u32, WmBindGroup)>>();
+    /// Check a config against the built-in resources, bind groups, geometry and push constants, and the ones
+    /// passed to [RenderGraph::new]. The graph can't be created while there are any problems.
+    pub fn validate(
+        config: &ShaderPackConfig,
+        resources: &HashMap<String, ResourceBacking>,
+        custom_bind_groups: Option<&HashMap<String, &wgpu::BindGroupLayout>>,
+        custom_geometry: Option<&HashMap<String, Vec<wgpu::VertexBufferLayout>>>,
+    ) -> Vec<GraphDiagnostic> {
+        let mut diagnostics = Vec::new();
 
-            let push_constants = pipeline_config
-                .push_constants
-                .iter()
-                .map(|(index, name)| {
-                    let index = *index as u32;
+        if let Err(diagnostic) = matrix_values(config, &Camera::default()) {
+            diagnostics.push(diagnostic);
+        }
 
-                    match &name[..] {
-                        "@pc_mat4_model" => wgpu::PushConstantRange {
-                            stages: wgpu::ShaderStages::VERTEX,
-                            range: index..index + 64,
-                        },
-                        "@pc_section_position" => wgpu::PushConstantRange {
-                            stages: wgpu::ShaderStages::VERTEX,
-                            range: index..index + 12,
-                        },
-                        "@pc_total_sections" => wgpu::PushConstantRange {
-                            stages: wgpu::ShaderStages::VERTEX,
-                            range: index..index + 4,
-                        },
-                        "@pc_parts_per_entity" => wgpu::PushConstantRange {
-                            stages: wgpu::ShaderStages::VERTEX,
-                            range: index..index + 4,
-                        },
-                        "@pc_electrum_color" => wgpu::PushConstantRange {
-                            stages: wgpu::ShaderStages::FRAGMENT,
-                            range: index..index + 16,
-                        },
-                        _ => unimplemented!(),
+        for (resource_id, resource) in &config.resources.resources {
+            let ShorthandResourceConfig::Longhand(LonghandResourceConfig { typed, .. }) = resource
+            else {
+                continue;
+            };
+
+            match typed {
+                TypeResourceConfig::Blob { src, .. } | TypeResourceConfig::Texture2d { src }
+                    if src.is_empty() =>
+                {
+                    diagnostics.push(config.diagnostic(&["resources", resource_id], "Missing src"));
+                }
+                TypeResourceConfig::Texture3d {
+                    src, size: None, ..
+                } if src.is_empty() => {
+                    diagnostics.push(config.diagnostic(
+                        &["resources", resource_id],
+                        "3d textures need either a src or a size",
+                    ));
+                }
+                TypeResourceConfig::RenderTarget(target) => {
+                    if let Some(format) = &target.format {
+                        if render_target_format(format).is_none() {
+                            diagnostics.push(config.diagnostic(
+                                &["resources", resource_id, "format"],
+                                format!("Unknown render target format {format}"),
+                            ));
+                        }
                     }
-                })
-                .collect::<Vec<wgpu::PushConstantRange>>();
 
-            let layout =
-                wm.display
-                    .device
-                    .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                        label: None,
-                        bind_group_layouts: &bind_group_layouts,
-                        push_constant_ranges: &push_constants,
-                    });
-
-            let shader = WgslShader::init(
-                &ResourcePath(format!("wgpu_mc:shaders/{}.wgsl", pipeline_name)),
-                &*wm.mc.resource_provider,
-                &wm.display.device,
-                "frag".into(),
-                "vert".into(),
-            )
-            .unwrap();
+                    if target.size_for([1, 1]).contains(&0) || target.scale <= 0.0 {
+                        diagnostics.push(config.diagnostic(
+                            &["resources", resource_id],
+                            "Render targets can't be empty",
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
 
-            let vertex_buffer = match &pipeline_config.geometry[..] {
-                "@geo_terrain" => None,
-                "@geo_entities" => Some(vec![EntityVertex::desc(), InstanceVertex::desc()]),
-                "@geo_quad" => Some(vec![QuadVertex::desc()]),
-                "@geo_sun_moon" => Some(vec![SunMoonVertex::desc()]),
-                "@geo_sky_scatter" | "@geo_sky_stars" | "@geo_sky_fog" => {
-                    Some(vec![SkyVertex::desc()])
+        let is_resource = |name: &str| {
+            BUILTIN_RESOURCES.contains(&name)
+                || resources.contains_key(name)
+                || config.resources.resources.contains_key(name)
+        };
+
+        let is_depth_target =
+            |name: &str| match (config.resources.resources.get(name), resources.get(name)) {
+                (
+                    Some(ShorthandResourceConfig::Longhand(LonghandResourceConfig {
+                        typed: TypeResourceConfig::TextureDepth { .. },
+                        ..
+                    })),
+                    _,
+                ) => true,
+                (None, Some(ResourceBacking::Texture2D(texture))) => {
+                    texture.format.is_depth_stencil_format()
                 }
-                _ => {
-                    match geometry_vertex_layouts
-                        .as_ref()
-                        .and_then(|layouts| layouts.get(&pipeline_config.geometry))
+                _ => name == "@texture_depth",
+            };
+
+        //Whether a pipeline before the one being checked renders into @post_output
+        let mut has_post_output = false;
+
+        for (pipeline_name, pipeline) in &config.pipelines.pipelines {
+            let diagnostic = |keys: &[&str], message: String| {
+                let path = ["pipelines", pipeline_name]
+                    .into_iter()
+                    .chain(keys.iter().copied())
+                    .collect::<Vec<_>>();
+                config.diagnostic(&path, message)
+            };
+
+            let geometry = &pipeline.geometry[..];
+            let is_terrain = TERRAIN_GEOMETRY.contains(&geometry);
+            let is_entities = geometry == "@geo_entities";
+            let is_quad = geometry == "@geo_quad";
+
+            if builtin_vertex_layouts(geometry).is_none()
+                && !custom_geometry.is_some_and(|custom| custom.contains_key(geometry))
+            {
+                diagnostics.push(diagnostic(
+                    &["geometry"],
+                    format!("Unknown geometry {geometry}"),
+                ));
+            }
+
+            if blend_state(&pipeline.blending).is_none() {
+                diagnostics.push(diagnostic(
+                    &["blending"],
+                    format!("Unknown blend mode {}", pipeline.blending),
+                ));
+            }
+
+            for (position, texture) in pipeline.output.iter().enumerate() {
+                let is_sampled = binds_resource(pipeline, texture);
+
+                let message = if pipeline.output[..position].contains(texture) {
+                    Some(format!("{texture} is written more than once"))
+                } else if texture == "@framebuffer_texture" || texture == "@post_output" {
+                    None
+                } else if let Some(target) = render_target_config(config, texture) {
+                    let format = target.format.as_deref().and_then(render_target_format);
+
+                    if is_sampled {
+                        Some(format!(
+                            "{texture} can't be sampled by a pipeline rendering into it"
+                        ))
+                    } else if format.is_some_and(|format| !is_blendable(format))
+                        && pipeline.blending != "replace"
                     {
-                        None => unimplemented!(),
-                        Some(layout) => Some(layout.clone()),
+                        Some(format!(
+                            "{texture} can't be blended, use `blending: replace`"
+                        ))
+                    } else {
+                        None
                     }
+                } else if is_resource(texture) {
+                    Some(format!("{texture} isn't a render target"))
+                } else {
+                    Some(format!("Unknown output texture {texture}"))
+                };
+
+                if let Some(message) = message {
+                    diagnostics.push(diagnostic(&["output"], message));
                 }
-            };
+            }
 
-            let label = pipeline_name.to_string();
-
-            let render_pipeline =
-                wm.display
-                    .device
-                    .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                        label: Some(&label),
-                        layout: Some(&layout),
-                        vertex: wgpu::VertexState {
-                            module: &shader.module,
-                            entry_point: "vert",
-                            compilation_options: Default::default(),
-                            buffers: match &vertex_buffer {
-                                None => &[],
-                                Some(buffer_layout) => buffer_layout,
-                            },
-                        },
-                        primitive: wgpu::PrimitiveState {
-                            topology: wgpu::PrimitiveTopology::TriangleList,
-                            strip_index_format: None,
-                            front_face: wgpu::FrontFace::Ccw,
-                            cull_mode: Some(wgpu::Face::Back),
-                            unclipped_depth: false,
-                            polygon_mode: Default::default(),
-                            conservative: false,
+            //Every attachment of a render pass has to be the same size
+            let sizes = pipeline
+                .output
+                .iter()
+                .map(|texture| (texture, &["output"]))
+                .chain(pipeline.depth.iter().map(|depth| (depth, &["depth"])))
+                .filter_map(|(name, keys)| Some((name, keys, attachment_size(config, name)?)))
+                .collect::<Vec<_>>();
+
+            if let Some((first, _, first_size)) = sizes.first() {
+                for (name, keys, size) in &sizes[1..] {
+                    if size != first_size {
+                        diagnostics.push(diagnostic(
+                            &keys[..],
+                            format!("{name} isn't the same size as {first}"),
+                        ));
+                    }
+                }
+            }
+
+            if let Some(depth) = &pipeline.depth {
+                if !is_depth_target(depth) {
+                    diagnostics.push(diagnostic(
+                        &["depth"],
+                        if is_resource(depth) {
+                            format!("{depth} isn't a depth texture")
+                        } else {
+                            format!("Unknown depth target {depth}")
                         },
-                        depth_stencil: pipeline_config.depth.as_ref().map(|_| {
-                            wgpu::DepthStencilState {
-                                format: wgpu::TextureFormat::Depth32Float,
-                                depth_write_enabled: true,
-                                depth_compare: wgpu::CompareFunction::Less,
-                                stencil: wgpu::StencilState::default(),
-                                bias: Default::default(),
-                            }
-                        }),
-                        multisample: Default::default(),
-                        fragment: Some(wgpu::FragmentState {
-                            module: &shader.module,
-                            entry_point: "frag",
-                            compilation_options: Default::default(),
-                            targets: &pipeline_config
-                                .output
-                                .iter()
-                                .map(|_| {
-                                    Some(wgpu::ColorTargetState {
-                                        format: wgpu::TextureFormat::Bgra8Unorm,
-                                        blend: Some(match &pipeline_config.blending[..] {
-                                            "alpha_blending" => wgpu::BlendState::ALPHA_BLENDING,
-                                            "premultiplied_alpha_blending" => {
-                                                wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING
-                                            }
-                                            "replace" => wgpu::BlendState::REPLACE,
-                                            "color_add_alpha_blending" => wgpu::BlendState {
-                                                color: wgpu::BlendComponent {
-                                                    src_factor: wgpu::BlendFactor::SrcAlpha,
-                                                    dst_factor: wgpu::BlendFactor::One,
-                                                    operation: wgpu::BlendOperation::Add,
-                                                },
-                                                alpha: wgpu::BlendComponent {
-                                                    src_factor: wgpu::BlendFactor::One,
-                                                    dst_factor: wgpu::BlendFactor::Zero,
-                                                    operation: wgpu::BlendOperation::Add,
-                                                },
-                                            },
-                                            _ => unimplemented!("Unknown blend state"),
-                                        }),
-                                        write_mask: Default::default(),
-                                    })
+                    ));
+                }
+            }
+
+            for (position, (slot, def)) in pipeline.bind_groups.iter().enumerate() {
+                let slot_key = slot.to_string();
+
+                if *slot != position as u64 {
+                    diagnostics.push(diagnostic(
+                        &["bind_groups", &slot_key],
+                        "Bind group slots have to count up from 0".into(),
+                    ));
+                }
+
+                match def {
+                    BindGroupDef::Entries(entries) => {
+                        for (index, resource_id) in entries {
+                            let message = if resource_id == "@post_output" {
+                                Some("@post_output can't be sampled, read it as @post_input in the next pass".into())
+                            } else if resource_id == "@post_input" {
+                                (!has_post_output).then(|| {
+                                    "Nothing renders into @post_output before this pass".into()
                                 })
-                                .collect::<Vec<_>>(),
-                        }),
-                        multiview: None,
-                        cache: None,
-                    });
-
-            self.pipelines.insert(
-                pipeline_name.clone(),
-                BoundPipeline {
-                    pipeline: render_pipeline,
-                    bind_groups: wm_bind_groups,
-                    config: pipeline_config.clone(),
-                },
-            );
+                            } else if !is_resource(resource_id) {
+                                Some(format!("Unknown resource {resource_id}"))
+                            } else if let Some(ResourceBacking::BufferArray(_)) =
+                                resources.get(resource_id)
+                            {
+                                Some(format!(
+                                    "{resource_id} is a buffer array, which can't be bound"
+                                ))
+                            } else {
+                                None
+                            };
+
+                            if let Some(message) = message {
+                                diagnostics.push(diagnostic(
+                                    &["bind_groups", &slot_key, &index.to_string()],
+                                    message,
+                                ));
+                            }
+                        }
+                    }
+                    BindGroupDef::Resource(resource) => {
+                        let is_custom =
+                            custom_bind_groups.is_some_and(|custom| custom.contains_key(resource));
+
+                        let message = match builtin_bind_group_layout(resource) {
+                            Some("entity") if !is_entities => {
+                                Some(format!("{resource} can only be used with @geo_entities"))
+                            }
+                            Some("ssbo") if !is_terrain => {
+                                Some(format!("{resource} can only be used with terrain geometry"))
+                            }
+                            Some(_) => None,
+                            None if !is_custom => Some(format!("Unknown bind group {resource}")),
+                            None if is_terrain || is_entities || is_quad => Some(format!(
+                                "{geometry} can't bind custom bind group {resource}"
+                            )),
+                            None => None,
+                        };
+
+                        if let Some(message) = message {
+                            diagnostics.push(diagnostic(&["bind_groups", &slot_key], message));
+                        }
+                    }
+                }
+            }
+
+            for (offset, name) in &pipeline.push_constants {
+                //Built-in geometry only sets the push constants it knows about
+                let is_provided = if is_terrain {
+                    name == "@pc_section_position"
+                } else if is_entities {
+                    name == "@pc_parts_per_entity"
+                } else {
+                    !is_quad
+                };
+
+                let message = if push_constant_range(name, 0).is_none() {
+                    Some(format!("Unknown push constant {name}"))
+                } else if !is_provided {
+                    Some(format!("{geometry} doesn't set {name}"))
+                } else {
+                    None
+                };
+
+                if let Some(message) = message {
+                    diagnostics.push(diagnostic(
+                        &["push_constants", &offset.to_string()],
+                        message,
+                    ));
+                }
+            }
+
+            has_post_output |= pipeline
+                .output
+                .iter()
+                .any(|texture| texture == "@post_output");
         }
+
+        diagnostics
     }
 
     pub fn new(
@@ -373,52 +1161,245 @@ impl RenderGraph {
         mut resources: HashMap<String, ResourceBacking>,
         custom_bind_groups: Option<HashMap<String, &wgpu::BindGroupLayout>>,
         custom_geometry: Option<HashMap<String, Vec<wgpu::VertexBufferLayout>>>,
-    ) -> Self {
+    ) -> Result<Self, Vec<GraphDiagnostic>> {
+        let diagnostics = Self::validate(
+            &config,
+            &resources,
+            custom_bind_groups.as_ref(),
+            custom_geometry.as_ref(),
+        );
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+
+        let config = resolve_post_textures(config);
+        let matrices = matrix_values(&config, &Camera::default()).map_err(|error| vec![error])?;
+        let mut diagnostics = Vec::new();
+        let framebuffer_size = {
+            let surface_config = wm.display.config.read();
+            [surface_config.width, surface_config.height]
+        };
+        let mut frame_cleared_textures = Vec::new();
+
         for (resource_id, shorthand) in &config.resources.resources {
-            match shorthand {
-                ShorthandResourceConfig::Int(_) => {}
-                ShorthandResourceConfig::Float(_) => {}
-                ShorthandResourceConfig::Mat3(_) => {}
-                ShorthandResourceConfig::Mat4(_) => {}
+            let backing = match shorthand {
+                ShorthandResourceConfig::Int(value) => {
+                    uniform_buffer(wm, resource_id, bytemuck::bytes_of(&(*value as i32)))
+                }
+                ShorthandResourceConfig::Float(value) => {
+                    uniform_buffer(wm, resource_id, bytemuck::bytes_of(&(*value as f32)))
+                }
+                ShorthandResourceConfig::Mat3(_) | ShorthandResourceConfig::Mat4(_) => {
+                    uniform_buffer(wm, resource_id, &matrices[&resource_id[..]].to_bytes())
+                }
                 ShorthandResourceConfig::Longhand(LonghandResourceConfig { typed, .. }) => {
                     match typed {
-                        TypeResourceConfig::Blob { .. } => {}
-                        TypeResourceConfig::Texture3d { .. } => {}
-                        TypeResourceConfig::Texture2d { src } => {
-                            let bytes = wm
+                        TypeResourceConfig::Blob { src, size } => {
+                            let Some(mut bytes) = wm
                                 .mc
                                 .resource_provider
                                 .get_bytes(&ResourcePath::from(&src[..]))
-                                .unwrap();
+                            else {
+                                diagnostics.push(config.diagnostic(
+                                    &["resources", resource_id, "src"],
+                                    format!("Couldn't find {src}"),
+                                ));
+                                continue;
+                            };
+                            //Storage buffers are read in words and can't be empty
+                            bytes.resize(bytes.len().max(*size).max(1).next_multiple_of(4), 0);
 
-                            let tav = TextureAndView::from_image_file_bytes(
-                                &wm.display,
-                                &bytes,
-                                resource_id,
+                            ResourceBacking::Buffer(
+                                Arc::new(wm.display.device.create_buffer_init(
+                                    &BufferInitDescriptor {
+                                        label: Some(resource_id),
+                                        contents: &bytes,
+                                        usage: wgpu::BufferUsages::STORAGE,
+                                    },
+                                )),
+                                wgpu::BufferBindingType::Storage { read_only: true },
                             )
-                            .unwrap();
+                        }
+                        TypeResourceConfig::Texture3d {
+                            src,
+                            clear_after_frame,
+                            size,
+                        } => {
+                            let image =
+                                match (!src.is_empty()).then(|| load_image(wm, src)) {
+                                    Some(Err(message)) => {
+                                        diagnostics.push(config.diagnostic(
+                                            &["resources", resource_id, "src"],
+                                            message,
+                                        ));
+                                        continue;
+                                    }
+                                    image => image.and_then(Result::ok),
+                                };
+
+                            let [width, height, depth] = match (size, &image) {
+                                (Some(size), _) => *size,
+                                (None, Some(image)) => {
+                                    [image.width(), image.width(), image.height() / image.width()]
+                                }
+                                //Checked by RenderGraph::validate
+                                (None, None) => continue,
+                            };
+                            if image.as_ref().is_some_and(|image| {
+                                (image.width(), image.height()) != (width, height * depth)
+                            }) {
+                                diagnostics.push(config.diagnostic(
+                                    &["resources", resource_id, "src"],
+                                    format!("{src} should be {depth} slices of {width}x{height} stacked vertically"),
+                                ));
+                                continue;
+                            }
+
+                            let size = wgpu::Extent3d {
+                                width,
+                                height,
+                                depth_or_array_layers: depth,
+                            };
+                            let format = wgpu::TextureFormat::Rgba8Unorm;
+                            let texture =
+                                wm.display.device.create_texture(&wgpu::TextureDescriptor {
+                                    label: Some(resource_id),
+                                    size,
+                                    mip_level_count: 1,
+                                    sample_count: 1,
+                                    dimension: wgpu::TextureDimension::D3,
+                                    format,
+                                    usage: wgpu::TextureUsages::COPY_DST
+                                        | wgpu::TextureUsages::TEXTURE_BINDING
+                                        | if *clear_after_frame {
+                                            wgpu::TextureUsages::STORAGE_BINDING
+                                        } else {
+                                            wgpu::TextureUsages::empty()
+                                        },
+                                    view_formats: &[],
+                                });
+
+                            if let Some(image) = &image {
+                                wm.display.queue.write_texture(
+                                    texture.as_image_copy(),
+                                    image.as_raw(),
+                                    wgpu::ImageDataLayout {
+                                        offset: 0,
+                                        bytes_per_row: Some(width * 4),
+                                        rows_per_image: Some(height),
+                                    },
+                                    size,
+                                );
+                            }
+
+                            let texture = Arc::new(TextureAndView {
+                                view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                                texture,
+                                format,
+                            });
+
+                            if *clear_after_frame {
+                                let zeroes =
+                                    wm.display.device.create_buffer(&wgpu::BufferDescriptor {
+                                        label: Some(resource_id),
+                                        size: (texture_3d_bytes_per_row(width) * height * depth)
+                                            as wgpu::BufferAddress,
+                                        usage: wgpu::BufferUsages::COPY_SRC,
+                                        mapped_at_creation: false,
+                                    });
+                                frame_cleared_textures.push((texture.clone(), zeroes));
+
+                                ResourceBacking::Texture3D(
+                                    texture,
+                                    Some(wgpu::StorageTextureAccess::WriteOnly),
+                                )
+                            } else {
+                                ResourceBacking::Texture3D(texture, None)
+                            }
+                        }
+                        TypeResourceConfig::Texture2d { src } => {
+                            let tav = load_image(wm, src).and_then(|image| {
+                                TextureAndView::from_image(
+                                    &wm.display,
+                                    &image.into(),
+                                    Some(resource_id),
+                                )
+                                .map_err(|error| error.to_string())
+                            });
 
-                            resources.insert(
-                                resource_id.clone(),
-                                ResourceBacking::Texture2D(Arc::new(tav)),
-                            );
+                            match tav {
+                                Ok(tav) => ResourceBacking::Texture2D(Arc::new(tav)),
+                                Err(message) => {
+                                    diagnostics.push(
+                                        config.diagnostic(
+                                            &["resources", resource_id, "src"],
+                                            message,
+                                        ),
+                                    );
+                                    continue;
+                                }
+                            }
+                        }
+                        TypeResourceConfig::RenderTarget(target) => {
+                            ResourceBacking::Texture2D(Arc::new(create_render_target(
+                                wm,
+                                resource_id,
+                                target,
+                                framebuffer_size,
+                            )))
+                        }
+                        TypeResourceConfig::TextureDepth { size, .. } => {
+                            ResourceBacking::Texture2D(Arc::new(create_depth_texture(
+                                wm,
+                                resource_id,
+                                size.unwrap_or(framebuffer_size),
+                            )))
+                        }
+                        TypeResourceConfig::F32 { range, value } => uniform_buffer(
+                            wm,
+                            resource_id,
+                            bytemuck::bytes_of(&clamp_to_range(*value, *range)),
+                        ),
+                        //WGSL has no 64 bit uniforms without extra features, shaders can read these as a vec2<u32>
+                        TypeResourceConfig::F64 { range, value } => uniform_buffer(
+                            wm,
+                            resource_id,
+                            bytemuck::bytes_of(&clamp_to_range(*value, *range)),
+                        ),
+                        TypeResourceConfig::I64 { range, value } => uniform_buffer(
+                            wm,
+                            resource_id,
+                            bytemuck::bytes_of(&clamp_to_range(*value, *range)),
+                        ),
+                        TypeResourceConfig::I32 { range, value } => uniform_buffer(
+                            wm,
+                            resource_id,
+                            bytemuck::bytes_of(&clamp_to_range(*value, *range)),
+                        ),
+                        TypeResourceConfig::Mat3(_) | TypeResourceConfig::Mat4(_) => {
+                            uniform_buffer(wm, resource_id, &matrices[&resource_id[..]].to_bytes())
                         }
-                        TypeResourceConfig::TextureDepth => {}
-                        TypeResourceConfig::F32 { .. } => {}
-                        TypeResourceConfig::F64 { .. } => {}
-                        TypeResourceConfig::I64 { .. } => {}
-                        TypeResourceConfig::I32 { .. } => {}
-                        TypeResourceConfig::Mat3(_) => {}
-                        TypeResourceConfig::Mat4(_) => {}
                     }
                 }
-            }
+            };
+
+            resources.insert(resource_id.clone(), backing);
+        }
+
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
         }
 
         let mut graph = Self {
             config,
             pipelines: LinkedHashMap::new(),
             resources,
+            frame_cleared_textures,
+            quad_vertices: wm.display.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("@geo_quad"),
+                contents: bytemuck::cast_slice(&FULLSCREEN_QUAD),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
         };
 
         let atlases = wm.mc.texture_manager.atlases.read();
@@ -428,17 +1409,157 @@ impl RenderGraph {
         graph.resources.extend([
             (
                 "@texture_block_atlas".into(),
-                ResourceBacking::Texture2D(block_atlas.texture.clone()),
+                ResourceBacking::Texture2D(block_atlas.texture.load_full()),
             ),
             (
                 "@sampler".into(),
-                ResourceBacking::Sampler(wm.mc.texture_manager.default_sampler.clone()),
+                ResourceBacking::Sampler(wm.mc.texture_manager.default_sampler.load_full()),
+            ),
+            (
+                "@sampler_linear".into(),
+                ResourceBacking::Sampler(Arc::new(wm.display.device.create_sampler(
+                    &wgpu::SamplerDescriptor {
+                        label: Some("@sampler_linear"),
+                        mag_filter: wgpu::FilterMode::Linear,
+                        min_filter: wgpu::FilterMode::Linear,
+                        ..Default::default()
+                    },
+                ))),
             ),
         ]);
 
-        graph.create_pipelines(wm, custom_bind_groups, custom_geometry);
+        drop(atlases);
+        graph.create_pipelines(wm, custom_bind_groups, custom_geometry)?;
+
+        Ok(graph)
+    }
+
+    /// Replace a resource, recreating the bind groups which use it
+    pub fn set_resource(&mut self, wm: &WmRenderer, name: &str, resource: ResourceBacking) {
+        self.resources.insert(name.to_string(), resource);
+
+        for (_, bound_pipeline) in self.pipelines.iter_mut() {
+            for (slot, bind_group) in &mut bound_pipeline.bind_groups {
+                let Some(BindGroupDef::Entries(entries)) =
+                    bound_pipeline.config.bind_groups.get(&(*slot as u64))
+                else {
+                    continue;
+                };
+
+                if !entries.values().any(|resource_id| resource_id == name) {
+                    continue;
+                }
+
+                let entries = entries
+                    .iter()
+                    .flat_map(|(index, resource_id)| {
+                        self.resources[resource_id].get_bind_group_entries(*index as u32)
+                    })
+                    .collect::<Vec<wgpu::BindGroupEntry>>();
+
+                *bind_group = WmBindGroup::Custom(wm.display.device.create_bind_group(
+                    &wgpu::BindGroupDescriptor {
+                        label: None,
+                        layout: &bound_pipeline.pipeline.get_bind_group_layout(*slot),
+                        entries: &entries,
+                    },
+                ));
+            }
+        }
+    }
+
+    /// Pick up atlas textures which were replaced because the atlas grew, see [crate::render::atlas::Atlas::upload],
+    /// and a replaced default sampler, and resize render targets and depth textures which follow the size of the framebuffer.
+    /// Returns true if any bind groups had to be recreated.
+    pub fn update_texture_resources(&mut self, wm: &WmRenderer) -> bool {
+        let texture = {
+            let atlases = wm.mc.texture_manager.atlases.read();
+            atlases[BLOCK_ATLAS].texture.load_full()
+        };
+        let sampler = wm.mc.texture_manager.default_sampler.load_full();
+
+        let mut updated = false;
+
+        if let Some(ResourceBacking::Texture2D(current)) =
+            self.resources.get("@texture_block_atlas")
+        {
+            if !Arc::ptr_eq(current, &texture) {
+                self.set_resource(
+                    wm,
+                    "@texture_block_atlas",
+                    ResourceBacking::Texture2D(texture),
+                );
+                updated = true;
+            }
+        }
+
+        if let Some(ResourceBacking::Sampler(current)) = self.resources.get("@sampler") {
+            if !Arc::ptr_eq(current, &sampler) {
+                self.set_resource(wm, "@sampler", ResourceBacking::Sampler(sampler));
+                updated = true;
+            }
+        }
+
+        let framebuffer_size = {
+            let surface_config = wm.display.config.read();
+            [surface_config.width, surface_config.height]
+        };
+
+        let resized_targets = self
+            .config
+            .resources
+            .resources
+            .keys()
+            .filter_map(|name| {
+                let target = render_target_config(&self.config, name)?;
+                let size = target.size_for(framebuffer_size);
+
+                match self.resources.get(name) {
+                    Some(ResourceBacking::Texture2D(current))
+                        if [current.texture.width(), current.texture.height()] == size =>
+                    {
+                        None
+                    }
+                    _ => Some((name.clone(), target.clone())),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (name, target) in resized_targets {
+            let texture = create_render_target(wm, &name, &target, framebuffer_size);
+            self.set_resource(wm, &name, ResourceBacking::Texture2D(Arc::new(texture)));
+            updated = true;
+        }
+
+        //Depth textures without a fixed size follow the framebuffer too
+        let resized_depth_textures = self
+            .config
+            .resources
+            .resources
+            .iter()
+            .filter(|(name, resource)| {
+                matches!(
+                    resource,
+                    ShorthandResourceConfig::Longhand(LonghandResourceConfig {
+                        typed: TypeResourceConfig::TextureDepth { size: None, .. },
+                        ..
+                    })
+                ) && !matches!(
+                    self.resources.get(*name),
+                    Some(ResourceBacking::Texture2D(current))
+                        if [current.texture.width(), current.texture.height()] == framebuffer_size
+                )
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        for name in resized_depth_textures {
+            let texture = create_depth_texture(wm, &name, framebuffer_size);
+            self.set_resource(wm, &name, ResourceBacking::Texture2D(Arc::new(texture)));
+            updated = true;
+        }
 
-        graph
+        updated
     }
 
     pub fn render(
@@ -449,15 +1570,77 @@ impl RenderGraph {
         render_target: &wgpu::TextureView,
         clear_color: [u8; 3],
         geometry: &mut HashMap<String, Box<dyn Geometry>>,
-        frustum: &Frustum<f32>,
+    ) {
+        self.render_passes(
+            wm,
+            encoder,
+            scene,
+            render_target,
+            clear_color,
+            geometry,
+            true,
+        );
+    }
+
+    /// Render like [RenderGraph::render], but without the post-processing passes reading `@post_input`.
+    /// Passes rendering into `@post_output` for the first of them render into `render_target` instead.
+    pub fn render_scene(
+        &self,
+        wm: &WmRenderer,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &Scene,
+        render_target: &wgpu::TextureView,
+        clear_color: [u8; 3],
+        geometry: &mut HashMap<String, Box<dyn Geometry>>,
+    ) {
+        self.render_passes(
+            wm,
+            encoder,
+            scene,
+            render_target,
+            clear_color,
+            geometry,
+            false,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_passes(
+        &self,
+        wm: &WmRenderer,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &Scene,
+        render_target: &wgpu::TextureView,
+        clear_color: [u8; 3],
+        geometry: &mut HashMap<String, Box<dyn Geometry>>,
+        post_processing: bool,
     ) {
         let arena = WmArena::new(4096);
 
-        let mut should_clear_depth = true;
+        let camera = *scene.camera.read();
+        let frustum = camera.frustum();
+
+        self.write_matrix_products(wm, &camera);
+
+        //Every depth target is cleared the first time it's used in a frame, unless it's declared to keep its contents
+        let mut used_depth_targets = HashSet::new();
+        //Render targets are cleared the same way when they're first rendered into
+        let mut written_render_targets = HashSet::new();
+        //Terrain pipelines write their draws one after another into the scene's draw buffers
+        let mut terrain_draw_count = 0;
 
         for (pipeline_name, bound_pipeline) in &self.pipelines {
             let pipeline_config = self.config.pipelines.pipelines.get(pipeline_name).unwrap();
 
+            //Post-processing passes are the ones reading @post_input, see resolve_post_textures
+            if !post_processing
+                && POST_TEXTURES
+                    .iter()
+                    .any(|texture| binds_resource(pipeline_config, texture))
+            {
+                continue;
+            }
+
             let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
                 label: None,
                 occlusion_query_set: None,
@@ -466,34 +1649,64 @@ impl RenderGraph {
                     .output
                     .iter()
                     .map(|texture_name| {
+                        if texture_name == "@framebuffer_texture" {
+                            return Some(RenderPassColorAttachment {
+                                view: render_target,
+                                resolve_target: None,
+                                ops: Operations {
+                                    load: if !pipeline_config.clear {
+                                        LoadOp::Load
+                                    } else {
+                                        LoadOp::Clear(Color {
+                                            r: clear_color[0] as f64,
+                                            g: clear_color[1] as f64,
+                                            b: clear_color[2] as f64,
+                                            a: 1.0,
+                                        })
+                                    },
+                                    store: StoreOp::Store,
+                                },
+                            });
+                        }
+
+                        //Outputs are checked by RenderGraph::validate
+                        let target = render_target_config(&self.config, texture_name)?;
+                        let Some(ResourceBacking::Texture2D(texture)) =
+                            self.resources.get(texture_name)
+                        else {
+                            return None;
+                        };
+
+                        let first_write = written_render_targets.insert(texture_name);
+                        let [r, g, b, a] = target.clear_color;
+
                         Some(RenderPassColorAttachment {
-                            view: match &texture_name[..] {
-                                "@framebuffer_texture" => render_target,
-                                _ => unimplemented!(),
+                            view: if !post_processing && POST_TEXTURES.contains(&&texture_name[..])
+                            {
+                                render_target
+                            } else {
+                                &texture.view
                             },
                             resolve_target: None,
                             ops: Operations {
-                                load: if !pipeline_config.clear {
-                                    LoadOp::Load
+                                load: if pipeline_config.clear
+                                    || (first_write && target.clear_after_frame)
+                                {
+                                    LoadOp::Clear(Color { r, g, b, a })
                                 } else {
-                                    LoadOp::Clear(Color {
-                                        r: clear_color[0] as f64,
-                                        g: clear_color[1] as f64,
-                                        b: clear_color[2] as f64,
-                                        a: 1.0,
-                                    })
+                                    LoadOp::Load
                                 },
                                 store: StoreOp::Store,
                             },
                         })
                     })
                     .collect::<Vec<_>>(),
-                depth_stencil_attachment: pipeline_config.depth.as_ref().map(|depth_texture| {
-                    let will_clear_depth = should_clear_depth;
-                    should_clear_depth = false;
+                depth_stencil_attachment: pipeline_config.depth.as_ref().and_then(
+                    |depth_texture| {
+                        let will_clear_depth = used_depth_targets.insert(depth_texture)
+                            && !self.keeps_depth_contents(depth_texture);
 
-                    let depth_view =
-                        if depth_texture == "@texture_depth" {
+                        let depth_view = if depth_texture == "@texture_depth" {
                             arena.alloc(scene.depth_texture.create_view(
                                 &wgpu::TextureViewDescriptor {
                                     label: None,
@@ -507,42 +1720,62 @@ impl RenderGraph {
                                 },
                             ))
                         } else {
+                            //Depth targets are checked by RenderGraph::validate
                             match self.resources.get(depth_texture) {
                                 Some(ResourceBacking::Texture2D(view)) => &view.view,
-                                _ => unimplemented!("Unknown depth target {}", depth_texture),
+                                _ => return None,
                             }
                         };
 
-                    RenderPassDepthStencilAttachment {
-                        view: depth_view,
-                        depth_ops: Some(Operations {
-                            load: if will_clear_depth {
-                                LoadOp::Clear(1.0)
-                            } else {
-                                LoadOp::Load
-                            },
-                            store: StoreOp::Store,
-                        }),
-                        stencil_ops: None,
-                    }
-                }),
+                        Some(RenderPassDepthStencilAttachment {
+                            view: depth_view,
+                            depth_ops: Some(Operations {
+                                load: if will_clear_depth {
+                                    LoadOp::Clear(1.0)
+                                } else {
+                                    LoadOp::Load
+                                },
+                                store: StoreOp::Store,
+                            }),
+                            stencil_ops: None,
+                        })
+                    },
+                ),
             });
 
             match &pipeline_config.geometry[..] {
-                "@geo_terrain" => {
+                "@geo_terrain" | "@geo_terrain_cutout" | "@geo_terrain_transparent" => {
+                    let render_layer = match &pipeline_config.geometry[..] {
+                        "@geo_terrain_cutout" => RenderLayer::Cutout,
+                        "@geo_terrain_transparent" => RenderLayer::Transparent,
+                        _ => RenderLayer::Solid,
+                    };
+
                     render_pass.set_pipeline(&bound_pipeline.pipeline);
 
+                    //The chunk buffer page is bound per section
+                    let mut chunks_bind_group_index = None;
+                    //Pipelines binding the draw buffer read section positions from it instead of push constants
+                    let mut draws_bind_group_index = None;
                     for (index, bind_group) in bound_pipeline.bind_groups.iter() {
                         match bind_group {
                             WmBindGroup::Resource(name) => match &name[..] {
                                 "@bg_ssbo_chunks" => {
+                                    chunks_bind_group_index = Some(*index);
+                                }
+                                "@bg_ssbo_terrain_draws" => {
+                                    draws_bind_group_index = Some(*index);
+                                }
+                                "@bg_ssbo_animated_uvs" => {
+                                    let animated_uvs = wm.mc.animated_block_bind_group.load();
                                     render_pass.set_bind_group(
                                         *index,
-                                        &scene.chunk_buffer.bind_group,
+                                        (**animated_uvs).as_ref().unwrap(),
                                         &[],
                                     );
                                 }
-                                _ => unimplemented!(),
+                                //Other bind groups can't be used with terrain, see RenderGraph::validate
+                                _ => {}
                             },
                             WmBindGroup::Custom(bind_group) => {
                                 render_pass.set_bind_group(*index, bind_group, &[]);
@@ -550,28 +1783,71 @@ impl RenderGraph {
                         }
                     }
 
-                    render_pass.set_index_buffer(
-                        scene.chunk_buffer.buffer.slice(..),
-                        wgpu::IndexFormat::Uint32,
-                    );
+                    let mut sections = scene.section_storage.write();
+                    let chunk_buffers = scene.chunk_buffers.read();
+                    let camera_position = camera.position();
+
+                    sections.update_visibility(camera.section());
+
+                    let mut ordered_sections = sections
+                        .iter()
+                        .filter(|(pos, _)| sections.is_visible(**pos))
+                        .collect::<Vec<_>>();
+                    if render_layer == RenderLayer::Transparent {
+                        //Draw translucent sections back-to-front so they blend correctly
+                        let distance = |pos: &IVec3| {
+                            let center = camera.relative_section_pos(*pos).as_vec3() * 16.0 + 8.0;
+                            center.distance_squared(camera_position)
+                        };
+                        ordered_sections
+                            .sort_unstable_by(|(a, _), (b, _)| distance(b).total_cmp(&distance(a)));
+                    } else {
+                        //Order doesn't matter, so group sections by page to switch buffers as little as possible
+                        ordered_sections.sort_unstable_by_key(|(_, section)| {
+                            section
+                                .layers
+                                .get(render_layer as usize)
+                                .and_then(|layer| layer.as_ref())
+                                .map(|layer| layer.page)
+                        });
+                    }
 
-                    let sections = scene.section_storage.write();
-                    let camera_pos = *scene.camera_section_pos.read();
-                    for (pos, section) in sections.iter() {
-                        let rel_pos = ivec3(pos.x - camera_pos.x, pos.y, pos.z - camera_pos.y);
-                        let a: Vec3<f32> =
-                            [rel_pos.x as f32, rel_pos.y as f32, rel_pos.z as f32].into();
-                        let b: Vec3<f32> = a + Vec3::new(1.0, 1.0, 1.0);
+                    let visible_layers = ordered_sections
+                        .into_iter()
+                        .filter_map(|(pos, section)| {
+                            let layer = section.layers.get(render_layer as usize)?.as_ref()?;
+                            if layer.page >= chunk_buffers.len() {
+                                return None;
+                            }
+
+                            let rel_pos = camera.relative_section_pos(*pos);
 
-                        let bounds: AABB<f32> =
-                            AABB::new((a * 16.0).into_array(), (b * 16.0).into_array());
+                            Camera::is_section_visible(&frustum, rel_pos)
+                                .then_some((rel_pos, layer))
+                        })
+                        .collect::<Vec<_>>();
 
-                        if !bounds.coherent_test_against_frustum(frustum, 0).0 {
-                            continue;
+                    let bind_page = |render_pass: &mut wgpu::RenderPass, page: usize| {
+                        let chunk_buffer = &chunk_buffers[page];
+                        if let Some(index) = chunks_bind_group_index {
+                            render_pass.set_bind_group(index, &chunk_buffer.bind_group, &[]);
                         }
-                        if let Some(layer) = &section.layers[RenderLayer::Solid as usize] {
+                        render_pass.set_index_buffer(
+                            chunk_buffer.buffer.slice(..),
+                            wgpu::IndexFormat::Uint32,
+                        );
+                    };
+
+                    let Some(draws_bind_group_index) = draws_bind_group_index else {
+                        let mut bound_page = None;
+
+                        for (rel_pos, layer) in visible_layers {
+                            if bound_page != Some(layer.page) {
+                                bind_page(&mut render_pass, layer.page);
+                                bound_page = Some(layer.page);
+                            }
+
                             let mut pc: HashMap<String, (Vec<u8>, ShaderStages)> = HashMap::new();
-                            //println!("draw {pos}");
                             pc.insert(
                                 "@pc_section_position".to_string(),
                                 (
@@ -586,6 +1862,71 @@ impl RenderGraph {
                                 layer.vertex_range.start..layer.vertex_range.start + 1,
                             );
                         }
+
+                        continue;
+                    };
+
+                    let first_draw = terrain_draw_count;
+                    terrain_draw_count += visible_layers.len() as u32;
+
+                    let (draws, commands): (Vec<_>, Vec<_>) = visible_layers
+                        .iter()
+                        .zip(first_draw..)
+                        .map(|((rel_pos, layer), draw)| {
+                            (
+                                TerrainDraw {
+                                    section_pos: rel_pos.to_array(),
+                                    base_vertex: layer.vertex_range.start,
+                                },
+                                DrawIndexedIndirectArgs {
+                                    index_count: layer.index_range.end - layer.index_range.start,
+                                    instance_count: 1,
+                                    first_index: layer.index_range.start,
+                                    base_vertex: 0,
+                                    first_instance: draw,
+                                },
+                            )
+                        })
+                        .unzip();
+
+                    let mut terrain_draws = scene.terrain_draws.lock();
+                    terrain_draws.write(wm, first_draw, &draws, &commands);
+
+                    render_pass.set_bind_group(
+                        draws_bind_group_index,
+                        &terrain_draws.draws.bind_group,
+                        &[],
+                    );
+
+                    let multi_draw = wm
+                        .display
+                        .device
+                        .features()
+                        .contains(INDIRECT_DRAW_FEATURES);
+
+                    //Sections sharing a page are drawn together, for opaque layers that's everything in the page
+                    let mut draw = first_draw;
+                    for run in visible_layers.chunk_by(|(_, a), (_, b)| a.page == b.page) {
+                        bind_page(&mut render_pass, run[0].1.page);
+
+                        if multi_draw {
+                            render_pass.multi_draw_indexed_indirect(
+                                &terrain_draws.indirect_buffer,
+                                TerrainDrawBuffers::command_offset(draw),
+                                run.len() as u32,
+                            );
+                        } else {
+                            for (offset, (_, layer)) in run.iter().enumerate() {
+                                let instance = draw + offset as u32;
+                                render_pass.draw_indexed(
+                                    layer.index_range.clone(),
+                                    0,
+                                    instance..instance + 1,
+                                );
+                            }
+                        }
+
+                        draw += run.len() as u32;
                     }
                 }
                 "@geo_entities" => {
@@ -596,16 +1937,15 @@ impl RenderGraph {
                     for (_, entity_instances) in &instances {
                         for (index, bind_group) in bound_pipeline.bind_groups.iter() {
                             match bind_group {
-                                WmBindGroup::Resource(name) => match &name[..] {
-                                    "@bg_entity" => {
-                                        render_pass.set_bind_group(
-                                            *index,
-                                            &entity_instances.uploaded.bind_group,
-                                            &[],
-                                        );
-                                    }
-                                    _ => unimplemented!(),
-                                },
+                                WmBindGroup::Resource(name) if name == "@bg_entity" => {
+                                    render_pass.set_bind_group(
+                                        *index,
+                                        &entity_instances.uploaded.bind_group,
+                                        &[],
+                                    );
+                                }
+                                //Other bind groups can't be used with entities, see RenderGraph::validate
+                                WmBindGroup::Resource(_) => {}
                                 WmBindGroup::Custom(bind_group) => {
                                     render_pass.set_bind_group(*index, bind_group, &[]);
                                 }
@@ -633,15 +1973,83 @@ impl RenderGraph {
                         );
                     }
                 }
-                _ => match geometry.get_mut(&pipeline_config.geometry) {
-                    None => unimplemented!("Unknown geometry {}", &pipeline_config.geometry),
-                    Some(geometry) => {
+                "@geo_quad" => {
+                    render_pass.set_pipeline(&bound_pipeline.pipeline);
+
+                    //Quads only bind the graph's own resources, see RenderGraph::validate
+                    for (index, bind_group) in bound_pipeline.bind_groups.iter() {
+                        if let WmBindGroup::Custom(bind_group) = bind_group {
+                            render_pass.set_bind_group(*index, bind_group, &[]);
+                        }
+                    }
+
+                    render_pass.set_vertex_buffer(0, self.quad_vertices.slice(..));
+                    render_pass.draw(0..FULLSCREEN_QUAD.len() as u32, 0..1);
+                }
+                //Geometry which wasn't passed in this frame has nothing to draw
+                _ => {
+                    if let Some(geometry) = geometry.get_mut(&pipeline_config.geometry) {
                         geometry.render(wm, self, bound_pipeline, &mut render_pass, &arena);
                     }
+                }
+            }
+        }
+
+        for (texture, zeroes) in &self.frame_cleared_textures {
+            let size = texture.texture.size();
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: zeroes,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(texture_3d_bytes_per_row(size.width)),
+                        rows_per_image: Some(size.height),
+                    },
                 },
+                texture.texture.as_image_copy(),
+                size,
+            );
+        }
+    }
+
+    /// Recompute the `mult` matrices from the camera and upload them
+    fn write_matrix_products(&self, wm: &WmRenderer, camera: &Camera) {
+        let Ok(matrices) = matrix_values(&self.config, camera) else {
+            return;
+        };
+
+        for (resource_id, resource) in &self.config.resources.resources {
+            let ShorthandResourceConfig::Longhand(LonghandResourceConfig {
+                typed:
+                    TypeResourceConfig::Mat3(Mat3ValueOrMult::Mult { .. })
+                    | TypeResourceConfig::Mat4(Mat4ValueOrMult::Mult { .. }),
+                ..
+            }) = resource
+            else {
+                continue;
+            };
+
+            if let (Some(value), Some(ResourceBacking::Buffer(buffer, _))) = (
+                matrices.get(&resource_id[..]),
+                self.resources.get(resource_id),
+            ) {
+                wm.display.queue.write_buffer(buffer, 0, &value.to_bytes());
             }
         }
     }
+
+    fn keeps_depth_contents(&self, depth_texture: &str) -> bool {
+        matches!(
+            self.config.resources.resources.get(depth_texture),
+            Some(ShorthandResourceConfig::Longhand(LonghandResourceConfig {
+                typed: TypeResourceConfig::TextureDepth {
+                    clear_after_frame: false,
+                    ..
+                },
+                ..
+            }))
+        )
+    }
 }
 
 pub fn set_push_constants(
@@ -653,14 +2061,558 @@ pub fn set_push_constants(
         .push_constants
         .iter()
         .for_each(|(offset, resource)| {
-            match push_constants
+            //Push constants which weren't given a value are left as they were
+            if let Some((data, stages)) = push_constants
                 .as_ref()
                 .and_then(|others| others.get(resource))
             {
-                None => unimplemented!("Unknown push constant resource value"),
-                Some((data, stages)) => {
-                    render_pass.set_push_constants(*stages, *offset as u32, data)
-                }
+                render_pass.set_push_constants(*stages, *offset as u32, data)
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat3, Mat4, Vec3};
+
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::{matrix_values, MatrixValue, RenderGraph, ResourceBacking};
+    use crate::mc::Scene;
+    use crate::render::camera::Camera;
+    use crate::render::shaderpack::{
+        BindGroupDef, GraphDiagnostic, ShaderPackConfig, YamlLocation,
+    };
+    use crate::testing::{self, MemoryResources};
+    use crate::WmRenderer;
+
+    /// A headless renderer serving the given shaders
+    fn headless_renderer(shaders: &[(&str, &'static str)]) -> Option<WmRenderer> {
+        let shaders = MemoryResources::new(
+            shaders
+                .iter()
+                .map(|(name, src)| (format!("wgpu_mc:shaders/{name}.wgsl"), *src)),
+        );
+
+        testing::headless_renderer(16, 16, Arc::new(shaders))
+    }
+
+    const MATRICES_YAML: &str = r#"
+version: "0.0.1"
+support: wgsl
+resources:
+  scale_mat3:
+    - [2.0, 0.0, 0.0]
+    - [0.0, 2.0, 0.0]
+    - [0.0, 0.0, 2.0]
+  scale_squared_mat3:
+    type: mat3
+    mult: [scale_mat3, scale_mat3]
+  mvp_mat4:
+    type: mat4
+    mult: ["@mat4_model", "@mat4_view", "@mat4_perspective"]
+  mvp_twice_mat4:
+    type: mat4
+    mult: [mvp_mat4, mvp_mat4]
+pipelines: {}
+"#;
+
+    #[test]
+    fn matrix_products() {
+        let config: ShaderPackConfig = serde_yaml::from_str(MATRICES_YAML).unwrap();
+        let camera = Camera {
+            model: Mat4::from_translation(Vec3::X),
+            view: Mat4::from_rotation_y(1.0),
+            projection: Mat4::perspective_rh(1.0, 1.5, 0.1, 100.0),
+            ..Default::default()
+        };
+
+        let values = matrix_values(&config, &camera).unwrap();
+
+        assert_eq!(
+            values["scale_squared_mat3"],
+            MatrixValue::Mat3(Mat3::from_diagonal(Vec3::splat(4.0)))
+        );
+        assert_eq!(
+            values["mvp_mat4"],
+            MatrixValue::Mat4(camera.view_projection())
+        );
+        assert_eq!(
+            values["mvp_twice_mat4"],
+            MatrixValue::Mat4(camera.view_projection() * camera.view_projection())
+        );
+
+        //Columns of a mat3x3<f32> uniform are padded to 16 bytes
+        assert_eq!(values["scale_mat3"].to_bytes().len(), 48);
+    }
+
+    #[test]
+    fn undeclared_matrix() {
+        let config: ShaderPackConfig = serde_yaml::from_str(
+            r#"
+version: "0.0.1"
+support: wgsl
+resources:
+  view_mat3:
+    type: mat3
+    mult: ["@mat4_view"]
+pipelines: {}
+"#,
+        )
+        .unwrap();
+
+        assert!(matrix_values(&config, &Camera::default()).is_err());
+    }
+
+    const INVALID_YAML: &str = r#"
+version: "0.0.1"
+support: wgsl
+resources:
+  shadow_depth:
+    type: texture_depth
+pipelines:
+  terrain:
+    geometry: "@geo_terain"
+    depth: "@texture_depth"
+    output: ["@framebuffer_texture"]
+    blending: premultiplied
+    bind_groups:
+      0:
+        0: "@mat4_view"
+        1: "@texture_block_atlas"
+      1: "@bg_entity"
+  shadows:
+    geometry: "@geo_terrain"
+    depth: shadow_depth
+    bind_groups:
+      0: "@bg_ssbo_chunks"
+    push_constants:
+      0: "@pc_parts_per_entity"
+"#;
+
+    #[test]
+    fn validate() {
+        let config = ShaderPackConfig::from_yaml(INVALID_YAML).unwrap();
+        let diagnostics = RenderGraph::validate(&config, &HashMap::new(), None, None);
+
+        let found = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.path.join("."),
+                    diagnostic.location.map(|location| location.line),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            found,
+            [
+                ("pipelines.terrain.geometry".into(), Some(9)),
+                ("pipelines.terrain.blending".into(), Some(12)),
+                ("pipelines.terrain.bind_groups.0.0".into(), Some(15)),
+                ("pipelines.terrain.bind_groups.1".into(), Some(17)),
+                ("pipelines.shadows.push_constants.0".into(), Some(24)),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "9:5: pipelines.terrain.geometry: Unknown geometry @geo_terain"
+        );
+    }
+
+    #[test]
+    fn syntax_error() {
+        let diagnostic = ShaderPackConfig::from_yaml("version: [\n").unwrap_err();
+
+        assert!(diagnostic.path.is_empty());
+        assert!(diagnostic
+            .location
+            .is_some_and(|YamlLocation { line, .. }| line >= 1));
+    }
+
+    const RENDER_TARGETS_YAML: &str = r#"
+version: "0.0.1"
+support: wgsl
+resources:
+  albedo:
+    type: render_target
+  normals:
+    type: render_target
+    format: rgba32float
+  half_size:
+    type: render_target
+    format: rgba16f
+    scale: 0.5
+pipelines:
+  gbuffer:
+    geometry: "@geo_terrain"
+    output: [albedo, normals, albedo]
+  composite:
+    geometry: "@geo_terrain"
+    output: ["@framebuffer_texture", half_size, "@mat4_view"]
+    blending: replace
+    bind_groups:
+      0:
+        0: albedo
+        1: half_size
+"#;
+
+    #[test]
+    fn validate_render_targets() {
+        let config = ShaderPackConfig::from_yaml(RENDER_TARGETS_YAML).unwrap();
+        let diagnostics = RenderGraph::validate(&config, &HashMap::new(), None, None);
+
+        let messages = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            messages,
+            [
+                "Unknown render target format rgba16f",
+                "normals can't be blended, use `blending: replace`",
+                "albedo is written more than once",
+                "half_size can't be sampled by a pipeline rendering into it",
+                "Unknown output texture @mat4_view",
+                "half_size isn't the same size as @framebuffer_texture",
+            ]
+        );
+    }
+
+    const ATTACHMENT_SIZES_YAML: &str = r#"
+version: "0.0.1"
+support: wgsl
+resources:
+  half_size:
+    type: render_target
+    scale: 0.5
+  shadow_map:
+    type: render_target
+    size: [1024, 1024]
+  shadow_depth:
+    type: texture_depth
+    size: [1024, 1024]
+pipelines:
+  shadows:
+    geometry: "@geo_terrain"
+    output: [shadow_map]
+    depth: shadow_depth
+  clouds:
+    geometry: "@geo_terrain"
+    output: [half_size]
+    depth: "@texture_depth"
+  composite:
+    geometry: "@geo_quad"
+    output: ["@framebuffer_texture", shadow_map]
+"#;
+
+    #[test]
+    fn validate_attachment_sizes() {
+        let config = ShaderPackConfig::from_yaml(ATTACHMENT_SIZES_YAML).unwrap();
+        let diagnostics = RenderGraph::validate(&config, &HashMap::new(), None, None);
+
+        let messages = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.path.join("."), diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            messages,
+            [
+                (
+                    "pipelines.clouds.depth".into(),
+                    "@texture_depth isn't the same size as half_size"
+                ),
+                (
+                    "pipelines.composite.output".into(),
+                    "shadow_map isn't the same size as @framebuffer_texture"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_target_size() {
+        let config = ShaderPackConfig::from_yaml(RENDER_TARGETS_YAML).unwrap();
+        let target = |name| super::render_target_config(&config, name).unwrap();
+
+        assert_eq!(target("albedo").size_for([1920, 1080]), [1920, 1080]);
+        assert_eq!(target("half_size").size_for([1920, 1081]), [960, 541]);
+        assert_eq!(target("half_size").size_for([1, 1]), [1, 1]);
+    }
+
+    const POST_YAML: &str = r#"
+version: "0.0.1"
+support: wgsl
+resources:
+  scene:
+    type: render_target
+pipelines:
+  too_early:
+    geometry: "@geo_quad"
+    output: ["@framebuffer_texture"]
+    bind_groups:
+      0:
+        0: "@post_input"
+  tonemap:
+    geometry: "@geo_quad"
+    output: ["@post_output"]
+    bind_groups:
+      0:
+        0: scene
+        1: "@sampler_linear"
+  fxaa:
+    geometry: "@geo_quad"
+    output: ["@post_output"]
+    bind_groups:
+      0:
+        0: "@post_input"
+        1: "@post_output"
+  grade:
+    geometry: "@geo_quad"
+    output: ["@post_output"]
+    push_constants:
+      0: "@pc_mat4_model"
+    bind_groups:
+      0:
+        0: "@post_input"
+      1: "@bg_entity"
+  vignette:
+    geometry: "@geo_quad"
+    output: ["@framebuffer_texture"]
+    bind_groups:
+      0:
+        0: "@post_input"
+"#;
+
+    #[test]
+    fn validate_post_processing() {
+        let config = ShaderPackConfig::from_yaml(POST_YAML).unwrap();
+        let diagnostics = RenderGraph::validate(&config, &HashMap::new(), None, None);
+
+        let messages = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            messages,
+            [
+                "Nothing renders into @post_output before this pass",
+                "@post_output can't be sampled, read it as @post_input in the next pass",
+                "@bg_entity can only be used with @geo_entities",
+                "@geo_quad doesn't set @pc_mat4_model",
+            ]
+        );
+    }
+
+    #[test]
+    fn post_texture_ping_pong() {
+        let config = super::resolve_post_textures(ShaderPackConfig::from_yaml(POST_YAML).unwrap());
+        let pipeline = |name| &config.pipelines.pipelines[name];
+        let input = |name| match &pipeline(name).bind_groups[&0] {
+            BindGroupDef::Entries(entries) => entries[&0].clone(),
+            BindGroupDef::Resource(_) => unreachable!(),
+        };
+
+        assert_eq!(pipeline("tonemap").output, ["@texture_post_a"]);
+        assert_eq!(input("fxaa"), "@texture_post_a");
+        assert_eq!(pipeline("fxaa").output, ["@texture_post_b"]);
+        assert_eq!(input("grade"), "@texture_post_b");
+        assert_eq!(pipeline("grade").output, ["@texture_post_a"]);
+        assert_eq!(input("vignette"), "@texture_post_a");
+        assert_eq!(pipeline("vignette").output, ["@framebuffer_texture"]);
+
+        for name in super::POST_TEXTURES {
+            assert!(super::render_target_config(&config, name).is_some());
+        }
+    }
+
+    const VOXELS_YAML: &str = r#"
+version: "0.0.1"
+support: wgsl
+resources:
+  voxels:
+    type: texture_3d
+    clear_after_frame: true
+    size: [4, 4, 4]
+  scene_depth:
+    type: texture_depth
+pipelines:
+  voxelize:
+    geometry: "@geo_quad"
+    output: ["@framebuffer_texture"]
+    bind_groups:
+      0:
+        0: voxels
+  lighting:
+    geometry: "@geo_quad"
+    output: ["@framebuffer_texture"]
+    depth: scene_depth
+    bind_groups:
+      0:
+        0: voxels
+"#;
+
+    const VOXELIZE_WGSL: &str = r#"
+@group(0) @binding(0) var voxels: texture_storage_3d<rgba8unorm, write>;
+
+@vertex
+fn vert(@location(0) pos: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4(pos, 0.0, 1.0);
+}
+
+@fragment
+fn frag() -> @location(0) vec4<f32> {
+    textureStore(voxels, vec3<u32>(0u), vec4(0.2, 0.4, 0.6, 1.0));
+    return vec4(0.0);
+}
+"#;
+
+    const LIGHTING_WGSL: &str = r#"
+@group(0) @binding(0) var voxels: texture_3d<f32>;
+
+@vertex
+fn vert(@location(0) pos: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4(pos, 0.0, 1.0);
+}
+
+@fragment
+fn frag() -> @location(0) vec4<f32> {
+    return textureLoad(voxels, vec3<i32>(0), 0);
+}
+"#;
+
+    #[test]
+    fn frame_cleared_textures_are_sampled_by_readers() {
+        let Some(wm) =
+            headless_renderer(&[("voxelize", VOXELIZE_WGSL), ("lighting", LIGHTING_WGSL)])
+        else {
+            return;
+        };
+
+        let config = ShaderPackConfig::from_yaml(VOXELS_YAML).unwrap();
+        let graph = RenderGraph::new(&wm, config, HashMap::new(), None, None).unwrap();
+
+        let scene = Scene::new(
+            &wm,
+            wgpu::Extent3d {
+                width: 16,
+                height: 16,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let image = wm
+            .capture_frame(|encoder, view| {
+                graph.render(&wm, encoder, &scene, view, [0; 3], &mut HashMap::new());
+            })
+            .unwrap();
+
+        //lighting covers what voxelize rendered with the voxel voxelize stored
+        for (channel, expected) in image.get_pixel(8, 8).0.into_iter().zip([51, 102, 153, 255]) {
+            assert!(channel.abs_diff(expected) <= 1, "{channel} != {expected}");
+        }
+    }
+
+    #[test]
+    fn unsized_depth_follows_framebuffer() {
+        let Some(wm) =
+            headless_renderer(&[("voxelize", VOXELIZE_WGSL), ("lighting", LIGHTING_WGSL)])
+        else {
+            return;
+        };
+
+        let config = ShaderPackConfig::from_yaml(VOXELS_YAML).unwrap();
+        let mut graph = RenderGraph::new(&wm, config, HashMap::new(), None, None).unwrap();
+
+        let depth_size = |graph: &RenderGraph| match &graph.resources["scene_depth"] {
+            ResourceBacking::Texture2D(texture) => {
+                [texture.texture.width(), texture.texture.height()]
             }
+            _ => unreachable!(),
+        };
+        assert_eq!(depth_size(&graph), [16, 16]);
+
+        //Depth textures without a size follow the framebuffer
+        {
+            let mut surface_config = wm.display.config.write();
+            surface_config.width = 32;
+            surface_config.height = 8;
+        }
+        assert!(graph.update_texture_resources(&wm));
+        assert_eq!(depth_size(&graph), [32, 8]);
+        assert!(!graph.update_texture_resources(&wm));
+    }
+
+    const MISMATCHED_YAML: &str = r#"
+version: "0.0.1"
+support: wgsl
+resources:
+  exposure:
+    type: f32
+    value: 1.0
+pipelines:
+  tonemap:
+    geometry: "@geo_quad"
+    output: ["@framebuffer_texture"]
+    bind_groups:
+      0:
+        0: exposure
+"#;
+
+    const TONEMAP_WGSL: &str = r#"
+@group(0) @binding(0) var t_scene: texture_2d<f32>;
+
+@vertex
+fn vert(@location(0) pos: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4(pos, 0.0, 1.0);
+}
+
+@fragment
+fn frag(@builtin(position) pos: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureLoad(t_scene, vec2<i32>(pos.xy), 0);
+}
+"#;
+
+    #[test]
+    fn mismatched_bindings() {
+        let Some(wm) = headless_renderer(&[("tonemap", TONEMAP_WGSL)]) else {
+            return;
+        };
+        let path = |diagnostics: &[GraphDiagnostic]| {
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.path.join("."))
+                .collect::<Vec<_>>()
+        };
+
+        //The shader reads a texture where the pack binds a uniform
+        let config = ShaderPackConfig::from_yaml(MISMATCHED_YAML).unwrap();
+        let diagnostics = RenderGraph::new(&wm, config, HashMap::new(), None, None).unwrap_err();
+        assert_eq!(path(&diagnostics), ["pipelines.tonemap"]);
+
+        let buffer = wm.display.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 16,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
         });
+        let resources = HashMap::from([(
+            "lights".to_string(),
+            ResourceBacking::BufferArray(vec![Arc::new(buffer)]),
+        )]);
+        let config =
+            ShaderPackConfig::from_yaml(&MISMATCHED_YAML.replace("0: exposure", "0: lights"))
+                .unwrap();
+        let diagnostics = RenderGraph::new(&wm, config, resources, None, None).unwrap_err();
+        assert_eq!(path(&diagnostics), ["pipelines.tonemap.bind_groups.0.0"]);
+        assert_eq!(
+            diagnostics[0].message,
+            "lights is a buffer array, which can't be bound"
+        );
+    }
 }
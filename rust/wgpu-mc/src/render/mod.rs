@@ -1,7 +1,10 @@
 pub mod atlas;
+pub mod camera;
 pub mod entity;
 pub mod graph;
 pub mod pipeline;
+pub mod screenshot;
 pub mod shader;
 pub mod shaderpack;
 pub mod sky;
+pub mod terrain;
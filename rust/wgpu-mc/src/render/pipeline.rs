@@ -14,6 +14,9 @@ pub struct Vertex {
     pub uv_offset: u32,
     pub lightmap_coords: u8,
     pub ao: u8,
+    /// How many times the texture repeats up to this vertex, for quads which were merged by greedy meshing.
+    /// Zero on every vertex of a quad means it's sampled as-is
+    pub tile: [u8; 2],
 }
 
 impl Vertex {
@@ -27,8 +30,10 @@ impl Vertex {
         // Animated UV index: 10 bits
         // XYZ add one flag: 3 bits
         // Block light nibble: 1 byte (4 bits for block, 4 bits for sky)
+        // AO: 1 byte
+        // Texture repeat: 2 bytes
 
-        // Total: 101 bits (13 bytes)
+        // Total: 128 bits (16 bytes)
         let mut array = [0; Self::VERTEX_LENGTH];
 
         let x = self.position[0] * 16.0;
@@ -77,6 +82,8 @@ impl Vertex {
         array[11] = (((self.uv_offset >> 8) as u8) & 0b11) | (normal_bits << 2) | (flag_byte << 5);
         array[12] = self.lightmap_coords;
         array[13] = self.ao;
+        array[14] = self.tile[0];
+        array[15] = self.tile[1];
 
         array
     }
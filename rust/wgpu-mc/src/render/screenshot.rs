@@ -0,0 +1,309 @@
+//! Reading rendered frames back to the CPU
+//!
+//! Frames are rendered into a texture the size and format of the display, copied into a mapped buffer and
+//! converted into an [RgbaImage]. Copies require rows to be padded to [wgpu::COPY_BYTES_PER_ROW_ALIGNMENT],
+//! which is stripped again when building the image.
+//!
+//! [WmRenderer::capture_frame_tiled] renders a frame larger than the device allows by splitting the projection
+//! into a grid of tiles, each of which is rendered at the display size and then stitched together. Tiles are
+//! rendered without the render graph's post-processing passes.
+
+use std::path::Path;
+
+use glam::{Mat4, Vec3};
+use image::RgbaImage;
+
+use crate::mc::Scene;
+use crate::render::camera::Camera;
+use crate::WmRenderer;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScreenshotError {
+    #[error("Can't read back textures with format {0:?}")]
+    UnsupportedFormat(wgpu::TextureFormat),
+    #[error(transparent)]
+    Map(#[from] wgpu::BufferAsyncError),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+}
+
+/// Bytes per row of a copy into a buffer, padded to [wgpu::COPY_BYTES_PER_ROW_ALIGNMENT]
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * 4).div_ceil(alignment) * alignment
+}
+
+/// Strip the row padding from copied texture data and swizzle it into RGBA
+fn unpad_rows(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Result<RgbaImage, ScreenshotError> {
+    let bgra = match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        _ => return Err(ScreenshotError::UnsupportedFormat(format)),
+    };
+
+    let row_size = width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_size * height as usize);
+    for row in data
+        .chunks(padded_bytes_per_row(width) as usize)
+        .take(height as usize)
+    {
+        pixels.extend_from_slice(&row[..row_size]);
+    }
+
+    if bgra {
+        bytemuck::cast_slice_mut::<u8, [u8; 4]>(&mut pixels)
+            .iter_mut()
+            .for_each(|pixel| pixel.swap(0, 2));
+    }
+
+    Ok(RgbaImage::from_raw(width, height, pixels).unwrap())
+}
+
+/// The projection which renders one tile of a `tiles` by `tiles` grid over what `projection` would render.
+/// Tiles are counted from the top left.
+pub fn tile_projection(projection: Mat4, tiles: u32, tile_x: u32, tile_y: u32) -> Mat4 {
+    let scale = tiles as f32;
+    //Move the tile's center in clip space to the origin, then scale it up to cover the whole of clip space
+    let offset = Vec3::new(
+        scale - 2.0 * tile_x as f32 - 1.0,
+        2.0 * tile_y as f32 + 1.0 - scale,
+        0.0,
+    );
+
+    Mat4::from_translation(offset) * Mat4::from_scale(Vec3::new(scale, scale, 1.0)) * projection
+}
+
+impl WmRenderer {
+    /// Copy a texture into an image. The texture needs [wgpu::TextureUsages::COPY_SRC] and must be an 8-bit
+    /// RGBA or BGRA format, like the one [crate::Display::headless] renders into.
+    ///
+    /// This blocks until the GPU has finished everything submitted so far.
+    pub fn read_texture(&self, texture: &wgpu::Texture) -> Result<RgbaImage, ScreenshotError> {
+        let format = texture.format();
+        if !matches!(
+            format,
+            wgpu::TextureFormat::Rgba8Unorm
+                | wgpu::TextureFormat::Rgba8UnormSrgb
+                | wgpu::TextureFormat::Bgra8Unorm
+                | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            return Err(ScreenshotError::UnsupportedFormat(format));
+        }
+
+        let (width, height) = (texture.width(), texture.height());
+        let bytes_per_row = padded_bytes_per_row(width);
+
+        let buffer = self.display.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("screenshot"),
+            size: bytes_per_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .display
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        self.display.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.display.device.poll(wgpu::Maintain::Wait);
+        //The callback has run once the device is idle
+        receiver.recv().unwrap()?;
+
+        let image = unpad_rows(&buffer.slice(..).get_mapped_range(), width, height, format);
+        buffer.unmap();
+
+        image
+    }
+
+    /// Render a frame into a texture the size and format of the display and read it back.
+    ///
+    /// `render` records the frame into the encoder it's given, targeting the view it's given, the same way a frame
+    /// would be rendered to the surface, e.g. with [crate::render::graph::RenderGraph::render]. Surface textures
+    /// can't be copied from, so this renders a separate frame rather than reading the last one presented.
+    pub fn capture_frame(
+        &self,
+        render: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::TextureView),
+    ) -> Result<RgbaImage, ScreenshotError> {
+        let target = self.create_capture_target();
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .display
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        render(&mut encoder, &view);
+        self.display.queue.submit([encoder.finish()]);
+
+        self.read_texture(&target)
+    }
+
+    /// Render a frame `tiles` times the width and height of the display, by rendering each tile of a `tiles` by
+    /// `tiles` grid with [tile_projection] and stitching them together.
+    ///
+    /// The scene's camera is swapped for each tile's camera while `render` runs and restored afterwards. Each call
+    /// is submitted before the next, so `render` can upload the camera it's given to whatever buffers the render
+    /// graph reads matrices from.
+    ///
+    /// Post-processing passes work on whole frames, a vignette would darken the corners of every tile, so tiles
+    /// should be rendered with [crate::render::graph::RenderGraph::render_scene], which leaves them out. The
+    /// captured image is then the frame as it was before post-processing.
+    pub fn capture_frame_tiled(
+        &self,
+        scene: &Scene,
+        tiles: u32,
+        mut render: impl FnMut(&Camera, &mut wgpu::CommandEncoder, &wgpu::TextureView),
+    ) -> Result<RgbaImage, ScreenshotError> {
+        let camera = *scene.camera.read();
+
+        let target = self.create_capture_target();
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let (tile_width, tile_height) = (target.width(), target.height());
+
+        let mut image = RgbaImage::new(tile_width * tiles, tile_height * tiles);
+
+        let mut result = Ok(());
+        'tiles: for tile_y in 0..tiles {
+            for tile_x in 0..tiles {
+                let tile_camera = Camera {
+                    projection: tile_projection(camera.projection, tiles, tile_x, tile_y),
+                    ..camera
+                };
+                *scene.camera.write() = tile_camera;
+
+                let mut encoder = self
+                    .display
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                render(&tile_camera, &mut encoder, &view);
+                self.display.queue.submit([encoder.finish()]);
+
+                match self.read_texture(&target) {
+                    Ok(tile) => image::imageops::replace(
+                        &mut image,
+                        &tile,
+                        (tile_x * tile_width) as i64,
+                        (tile_y * tile_height) as i64,
+                    ),
+                    Err(error) => {
+                        result = Err(error);
+                        break 'tiles;
+                    }
+                }
+            }
+        }
+
+        *scene.camera.write() = camera;
+
+        result.map(|_| image)
+    }
+
+    /// Capture a frame with [WmRenderer::capture_frame] and write it to a PNG file
+    pub fn save_screenshot(
+        &self,
+        path: impl AsRef<Path>,
+        render: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::TextureView),
+    ) -> Result<(), ScreenshotError> {
+        self.capture_frame(render)?
+            .save_with_format(path, image::ImageFormat::Png)?;
+
+        Ok(())
+    }
+
+    fn create_capture_target(&self) -> wgpu::Texture {
+        let config = self.display.config.read();
+
+        self.display
+            .device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("screenshot"),
+                size: wgpu::Extent3d {
+                    width: config.width,
+                    height: config.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec4;
+
+    use super::*;
+
+    #[test]
+    fn unpad_and_swizzle() {
+        let width = 3;
+        let row = padded_bytes_per_row(width) as usize;
+        assert_eq!(row, 256);
+
+        let mut data = vec![0u8; row * 2];
+        data[..4].copy_from_slice(&[1, 2, 3, 4]);
+        data[row + 8..row + 12].copy_from_slice(&[5, 6, 7, 8]);
+
+        let image = unpad_rows(&data, width, 2, wgpu::TextureFormat::Bgra8Unorm).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [3, 2, 1, 4]);
+        assert_eq!(image.get_pixel(2, 1).0, [7, 6, 5, 8]);
+
+        assert!(matches!(
+            unpad_rows(&data, width, 2, wgpu::TextureFormat::R8Unorm),
+            Err(ScreenshotError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn tiles_cover_the_projection() {
+        let projection = Mat4::perspective_rh(70f32.to_radians(), 1.5, 0.05, 100.0);
+        let point = Vec4::new(1.0, 0.5, -10.0, 1.0);
+
+        let clip = projection * point;
+        let ndc = clip.truncate() / clip.w;
+
+        //Which tile of a 3x3 grid the point lands in, counting rows down from the top
+        let tiles = 3;
+        let tile_x = ((ndc.x + 1.0) / 2.0 * tiles as f32) as u32;
+        let tile_y = ((1.0 - ndc.y) / 2.0 * tiles as f32) as u32;
+
+        let tile_clip = tile_projection(projection, tiles, tile_x, tile_y) * point;
+        let tile_ndc = tile_clip.truncate() / tile_clip.w;
+
+        assert!(tile_ndc.x.abs() <= 1.0 && tile_ndc.y.abs() <= 1.0);
+        assert!((tile_ndc.z - ndc.z).abs() < 1e-5);
+
+        //The same point in the tile's local coordinates, mapped back to the full frame
+        let frame_x = (tile_x as f32 + (tile_ndc.x + 1.0) / 2.0) / tiles as f32 * 2.0 - 1.0;
+        let frame_y = 1.0 - (tile_y as f32 + (1.0 - tile_ndc.y) / 2.0) / tiles as f32 * 2.0;
+        assert!((frame_x - ndc.x).abs() < 1e-5);
+        assert!((frame_y - ndc.y).abs() < 1e-5);
+    }
+}
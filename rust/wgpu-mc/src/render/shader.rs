@@ -1,6 +1,8 @@
 use std::borrow::Cow;
+use std::collections::HashSet;
 
 use crate::mc::resource::{ResourcePath, ResourceProvider};
+use crate::wgpu::naga;
 use crate::wgpu::{ShaderModule, ShaderModuleDescriptor};
 
 pub trait WmShader: Send + Sync {
@@ -14,6 +16,8 @@ pub struct WgslShader {
     pub module: ShaderModule,
     pub frag_entry: String,
     pub vert_entry: String,
+    /// The (group, binding) slots the shader declares as storage textures
+    pub storage_textures: HashSet<(u32, u32)>,
 }
 
 impl WgslShader {
@@ -33,10 +37,35 @@ impl WgslShader {
             source: wgpu::ShaderSource::Wgsl(Cow::from(shader_src)),
         });
 
+        //Invalid shaders are reported by wgpu when the module is created
+        let storage_textures = naga::front::wgsl::parse_str(shader_src)
+            .map(|module| {
+                module
+                    .global_variables
+                    .iter()
+                    .filter(|(_, var)| {
+                        matches!(
+                            module.types[var.ty].inner,
+                            naga::TypeInner::Image {
+                                class: naga::ImageClass::Storage { .. },
+                                ..
+                            }
+                        )
+                    })
+                    .filter_map(|(_, var)| {
+                        var.binding
+                            .as_ref()
+                            .map(|binding| (binding.group, binding.binding))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
         Some(Self {
             module,
             frag_entry,
             vert_entry,
+            storage_textures,
         })
     }
 }
@@ -1,5 +1,7 @@
 //! Serde implementation of the [shaderpack specification](https://github.com/wgpu-mc/shader-spec)
 
+use std::fmt::{Display, Formatter};
+
 use linked_hash_map::LinkedHashMap;
 use serde_derive::*;
 
@@ -17,9 +19,40 @@ pub struct ShaderPackConfig {
     pub support: String,
     pub resources: ResourcesConfig,
     pub pipelines: PipelinesConfig,
+
+    /// The YAML the config was read from by [ShaderPackConfig::from_yaml], used to locate diagnostics
+    #[serde(skip)]
+    pub source: Option<String>,
 }
 
 impl ShaderPackConfig {
+    /// Parse a config, keeping the source around so problems found later can point at the offending line
+    pub fn from_yaml(source: &str) -> Result<Self, GraphDiagnostic> {
+        match serde_yaml::from_str::<Self>(source) {
+            Ok(config) => Ok(Self {
+                source: Some(source.to_string()),
+                ..config
+            }),
+            Err(error) => Err(GraphDiagnostic {
+                path: Vec::new(),
+                location: error.location().map(|location| YamlLocation {
+                    line: location.line(),
+                    column: location.column(),
+                }),
+                message: error.to_string(),
+            }),
+        }
+    }
+
+    /// Create a diagnostic about the value at `path`, e.g. `["pipelines", "terrain", "geometry"]`
+    pub fn diagnostic(&self, path: &[&str], message: impl Into<String>) -> GraphDiagnostic {
+        GraphDiagnostic {
+            path: path.iter().map(|key| key.to_string()).collect(),
+            location: self.source.as_ref().and_then(|source| locate(source, path)),
+            message: message.into(),
+        }
+    }
+
     /// Returns true if the first two numbers (major and minor) are as expected.
     /// If the format is incorrect or they're different, this returns false.
     pub fn is_correct_version(&self) -> bool {
@@ -39,12 +72,89 @@ impl ShaderPackConfig {
     }
 }
 
+/// A line and column in a YAML file, starting at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct YamlLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A problem with a shaderpack config, found while parsing it or by [crate::render::graph::RenderGraph::validate]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphDiagnostic {
+    /// The keys leading to the offending value
+    pub path: Vec<String>,
+    /// Where the last key of the path which could be found is, if the config was read with [ShaderPackConfig::from_yaml]
+    pub location: Option<YamlLocation>,
+    pub message: String,
+}
+
+impl Display for GraphDiagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(YamlLocation { line, column }) = self.location {
+            write!(f, "{line}:{column}: ")?;
+        }
+
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path.join("."))?;
+        }
+
+        f.write_str(&self.message)
+    }
+}
+
+/// Find the line of the deepest key in `path`, by following the indentation of block mappings.
+/// Keys inside flow mappings aren't found, the location of their parent is used instead.
+fn locate(source: &str, path: &[&str]) -> Option<YamlLocation> {
+    let mut location = None;
+    let mut parent_indent = None;
+    let mut lines = source.lines().enumerate();
+
+    'keys: for key in path {
+        //Only the direct children of the last key found are searched
+        let mut child_indent = None;
+
+        for (index, line) in lines.by_ref() {
+            let content = line.trim_start();
+            if content.is_empty() || content.starts_with('#') {
+                continue;
+            }
+
+            let indent = line.len() - content.len();
+            if parent_indent.is_some_and(|parent_indent| indent <= parent_indent) {
+                break 'keys;
+            }
+            if *child_indent.get_or_insert(indent) != indent {
+                continue;
+            }
+
+            let is_key = content.split_once(':').is_some_and(|(candidate, _)| {
+                candidate.trim_end().trim_matches(['"', '\'']) == *key
+            });
+
+            if is_key {
+                location = Some(YamlLocation {
+                    line: index + 1,
+                    column: indent + 1,
+                });
+                parent_indent = Some(indent);
+                continue 'keys;
+            }
+        }
+
+        break;
+    }
+
+    location
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ResourcesConfig {
     #[serde(flatten)]
     pub resources: LinkedHashMap<String, ShorthandResourceConfig>,
 }
 
+/// A resource given as just its value. Ints and floats become `i32` and `f32` uniforms.
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
 pub enum ShorthandResourceConfig {
@@ -81,20 +191,33 @@ pub enum TypeResourceConfig {
         #[serde(default)]
         size: usize,
     },
+    /// Loaded from an image of the depth slices stacked vertically, or empty if there's no `src`.
+    /// Textures cleared after every frame are bound as write-only storage textures to shaders which declare
+    /// a storage texture, and as sampled textures to shaders which read them.
     #[serde(rename = "texture_3d")]
     Texture3d {
         #[serde(default)]
         src: String,
         #[serde(default)]
         clear_after_frame: bool,
+        /// Width, height and depth. Defaults to square slices of the source image.
+        #[serde(default)]
+        size: Option<[u32; 3]>,
     },
     #[serde(rename = "texture_2d")]
     Texture2d {
         #[serde(default)]
         src: String,
     },
+    RenderTarget(RenderTargetConfig),
     #[serde(rename = "texture_depth")]
-    TextureDepth,
+    TextureDepth {
+        #[serde(default = "clear_after_frame_default")]
+        clear_after_frame: bool,
+        /// Defaults to the size of the framebuffer when the graph is created
+        #[serde(default)]
+        size: Option<[u32; 2]>,
+    },
     F32 {
         #[serde(default)]
         range: [f32; 2],
@@ -119,6 +242,8 @@ pub enum TypeResourceConfig {
     Mat4(Mat4ValueOrMult),
 }
 
+/// A `mult` matrix is the product of the listed matrices, applied in order, recomputed every frame.
+/// For example `mult: ["@mat4_model", "@mat4_view", "@mat4_perspective"]` is projection * view * model.
 #[derive(Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum Mat3ValueOrMult {
@@ -126,6 +251,7 @@ pub enum Mat3ValueOrMult {
     Mult { mult: Vec<String> },
 }
 
+/// See [Mat3ValueOrMult]
 #[derive(Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum Mat4ValueOrMult {
@@ -133,6 +259,41 @@ pub enum Mat4ValueOrMult {
     Mult { mult: Vec<String> },
 }
 
+fn clear_after_frame_default() -> bool {
+    true
+}
+
+fn scale_default() -> f32 {
+    1.0
+}
+
+/// A colour texture pipelines can render into with `output`, and later pipelines can sample
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RenderTargetConfig {
+    /// See [crate::render::graph::render_target_format], defaults to the format of the framebuffer
+    #[serde(default)]
+    pub format: Option<String>,
+    /// A fixed size, instead of following the size of the framebuffer
+    #[serde(default)]
+    pub size: Option<[u32; 2]>,
+    /// The size relative to the framebuffer, if there's no fixed size
+    #[serde(default = "scale_default")]
+    pub scale: f32,
+    /// Whether the target is cleared the first time it's rendered into each frame
+    #[serde(default = "clear_after_frame_default")]
+    pub clear_after_frame: bool,
+    #[serde(default)]
+    pub clear_color: [f64; 4],
+}
+
+impl RenderTargetConfig {
+    pub fn size_for(&self, framebuffer_size: [u32; 2]) -> [u32; 2] {
+        self.size.unwrap_or(
+            framebuffer_size.map(|length| ((length as f32 * self.scale).round() as u32).max(1)),
+        )
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct PipelinesConfig {
     #[serde(flatten)]
@@ -143,6 +304,14 @@ fn blend_default() -> String {
     "alpha_blending".into()
 }
 
+fn frag_entry_default() -> String {
+    "frag".into()
+}
+
+fn depth_write_default() -> bool {
+    true
+}
+
 #[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum BindGroupDef {
@@ -159,6 +328,9 @@ pub struct PipelineConfig {
 
     pub depth: Option<String>,
 
+    #[serde(default = "depth_write_default")]
+    pub depth_write: bool,
+
     #[serde(default)]
     pub clear: bool,
 
@@ -170,6 +342,14 @@ pub struct PipelineConfig {
 
     #[serde(default = "blend_default")]
     pub blending: String,
+
+    /// The shader in `wgpu_mc:shaders/`, defaults to the name of the pipeline.
+    /// Pipelines sharing a shader can use different fragment entry points of it.
+    #[serde(default)]
+    pub shader: Option<String>,
+
+    #[serde(default = "frag_entry_default")]
+    pub frag_entry: String,
 }
 
 #[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
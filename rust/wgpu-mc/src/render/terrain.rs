@@ -0,0 +1,95 @@
+//! Buffers for drawing terrain with one indirect draw per section layer
+//!
+//! Each visible section layer gets a [TerrainDraw], which terrain shaders read through `@bg_ssbo_terrain_draws`
+//! using the instance index, and a matching [DrawIndexedIndirectArgs] command. When the device supports
+//! [INDIRECT_DRAW_FEATURES] all of the commands for a chunk buffer page are submitted with a single
+//! `multi_draw_indexed_indirect`, otherwise they are issued one by one.
+
+use std::sync::Arc;
+
+use wgpu::util::DrawIndexedIndirectArgs;
+
+use crate::util::BindableBuffer;
+use crate::WmRenderer;
+
+/// Features required to draw terrain with `multi_draw_indexed_indirect`
+pub const INDIRECT_DRAW_FEATURES: wgpu::Features =
+    wgpu::Features::MULTI_DRAW_INDIRECT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
+const INDIRECT_ARGS_SIZE: u64 = std::mem::size_of::<DrawIndexedIndirectArgs>() as u64;
+
+/// Per-draw data for a section layer, matching the `TerrainDraw` struct in the terrain shaders
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TerrainDraw {
+    /// Section position relative to the camera section
+    pub section_pos: [i32; 3],
+    /// Start of the layer's vertices in its chunk buffer page, in u32s
+    pub base_vertex: u32,
+}
+
+pub struct TerrainDrawBuffers {
+    pub indirect_buffer: Arc<wgpu::Buffer>,
+    pub draws: Arc<BindableBuffer>,
+    /// In draws
+    capacity: u32,
+}
+
+impl TerrainDrawBuffers {
+    pub fn new(wm: &WmRenderer, capacity: u32) -> Self {
+        Self {
+            indirect_buffer: Arc::new(wm.display.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: INDIRECT_ARGS_SIZE * capacity as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::INDIRECT,
+                mapped_at_creation: false,
+            })),
+            draws: Arc::new(BindableBuffer::new_deferred(
+                wm,
+                std::mem::size_of::<TerrainDraw>() as u64 * capacity as u64,
+                wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+                "ssbo",
+            )),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Write draws and their commands starting at draw `first`, growing the buffers if they don't fit.
+    /// Growing replaces the buffers, render passes recorded earlier keep drawing from the old ones.
+    pub fn write(
+        &mut self,
+        wm: &WmRenderer,
+        first: u32,
+        draws: &[TerrainDraw],
+        commands: &[DrawIndexedIndirectArgs],
+    ) {
+        let required = first + draws.len() as u32;
+        if required > self.capacity {
+            *self = Self::new(wm, required.next_power_of_two());
+        }
+
+        wm.display.queue.write_buffer(
+            &self.draws.buffer,
+            first as u64 * std::mem::size_of::<TerrainDraw>() as u64,
+            bytemuck::cast_slice(draws),
+        );
+        wm.display.queue.write_buffer(
+            &self.indirect_buffer,
+            first as u64 * INDIRECT_ARGS_SIZE,
+            &commands
+                .iter()
+                .flat_map(|command| command.as_bytes())
+                .copied()
+                .collect::<Vec<u8>>(),
+        );
+    }
+
+    /// Byte offset of a command in [TerrainDrawBuffers::indirect_buffer]
+    pub fn command_offset(draw: u32) -> wgpu::BufferAddress {
+        draw as u64 * INDIRECT_ARGS_SIZE
+    }
+}
@@ -0,0 +1,75 @@
+//! Helpers for tests which render headlessly
+//!
+//! Tests which need a GPU fail when there's no adapter, not even a software one, so that they can't silently pass
+//! in CI. Set `WGPU_MC_SKIP_GPU_TESTS` to skip them instead.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use image::RgbaImage;
+use parking_lot::RwLock;
+
+use crate::mc::resource::{ResourcePath, ResourceProvider};
+use crate::{Display, HeadlessError, WmRenderer};
+
+/// A display rendering into a `width` by `height` RGBA texture, or None if there's no adapter and GPU tests are
+/// skipped
+pub fn headless_display(width: u32, height: u32) -> Option<Display> {
+    match futures::executor::block_on(Display::headless(
+        width,
+        height,
+        wgpu::TextureFormat::Rgba8Unorm,
+    )) {
+        Ok(display) => Some(display),
+        Err(HeadlessError::NoAdapter) if std::env::var_os("WGPU_MC_SKIP_GPU_TESTS").is_some() => {
+            None
+        }
+        Err(error) => panic!("{error}, set WGPU_MC_SKIP_GPU_TESTS to skip tests which need a GPU"),
+    }
+}
+
+/// An initialized renderer for a [headless_display], reading resources from `resources`
+pub fn headless_renderer(
+    width: u32,
+    height: u32,
+    resources: Arc<dyn ResourceProvider>,
+) -> Option<WmRenderer> {
+    let wm = WmRenderer::new(headless_display(width, height)?, resources);
+    wm.init();
+
+    Some(wm)
+}
+
+/// Resources served from memory, keyed by their full path like `minecraft:textures/block/stone.png`
+#[derive(Default)]
+pub struct MemoryResources(RwLock<HashMap<String, Vec<u8>>>);
+
+impl MemoryResources {
+    pub fn new<P: Into<String>, B: Into<Vec<u8>>>(files: impl IntoIterator<Item = (P, B)>) -> Self {
+        Self(RwLock::new(
+            files
+                .into_iter()
+                .map(|(path, bytes)| (path.into(), bytes.into()))
+                .collect(),
+        ))
+    }
+
+    /// Add a file, or replace it like a resource pack on top would
+    pub fn insert(&self, path: impl Into<String>, bytes: impl Into<Vec<u8>>) {
+        self.0.write().insert(path.into(), bytes.into());
+    }
+}
+
+impl ResourceProvider for MemoryResources {
+    fn get_bytes(&self, id: &ResourcePath) -> Option<Vec<u8>> {
+        self.0.read().get(&id.0).cloned()
+    }
+}
+
+pub fn png(image: &RgbaImage) -> Vec<u8> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    png
+}
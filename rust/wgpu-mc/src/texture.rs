@@ -96,6 +96,82 @@ impl TextureAndView {
             format,
         })
     }
+
+    /// Create a texture with a mip level for each image, each half the size of the one before it
+    pub fn from_mips(
+        wgpu_state: &Display,
+        mips: &[image::RgbaImage],
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture = wgpu_state.device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: Extent3d {
+                width: mips[0].width(),
+                height: mips[0].height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: mips.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let texture_and_view = Self {
+            texture,
+            view,
+            format,
+        };
+        texture_and_view.write_mips(wgpu_state, mips);
+
+        texture_and_view
+    }
+
+    /// Overwrite the part of a mip level starting at `origin` with an image
+    pub fn write_region(
+        &self,
+        wgpu_state: &Display,
+        mip_level: u32,
+        origin: (u32, u32),
+        image: &image::RgbaImage,
+    ) {
+        wgpu_state.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level,
+                origin: wgpu::Origin3d {
+                    x: origin.0,
+                    y: origin.1,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            image.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(image.width() * 4),
+                rows_per_image: Some(image.height()),
+            },
+            Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Overwrite the texture's mip levels, starting with the largest
+    pub fn write_mips(&self, wgpu_state: &Display, mips: &[image::RgbaImage]) {
+        for (mip_level, image) in mips.iter().enumerate() {
+            self.write_region(wgpu_state, mip_level as u32, (0, 0), image);
+        }
+    }
 }
 
 ///Represents a texture that has been uploaded to GPU and has an associated `BindGroup`